@@ -0,0 +1,260 @@
+use crate::nip05;
+use crate::relays::{self, SendResult};
+use anyhow::{anyhow, bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const ARTICLE_KIND: u16 = 30023;
+const DRAFT_KIND: u16 = 30024;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ArticlePublishArgs {
+    /// Article title
+    pub title: String,
+    /// Markdown body
+    pub content: String,
+    /// `d` identifier; reuse it to update an article (default: slug of the title)
+    pub identifier: Option<String>,
+    pub summary: Option<String>,
+    /// Header image URL
+    pub image: Option<String>,
+    /// Unix timestamp of first publication (default: kept from the existing article, else now)
+    pub published_at: Option<u64>,
+    /// Hashtags without `#`
+    pub hashtags: Option<Vec<String>>,
+    /// Save as a kind 30024 draft instead of publishing (default: false)
+    pub draft: Option<bool>,
+    /// Specific relay URLs to publish to
+    pub to_relays: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ArticleListArgs {
+    /// Author (hex, npub or NIP-05; default: active key)
+    pub author: Option<String>,
+    /// List drafts (kind 30024) instead of published articles (default: false)
+    pub drafts: Option<bool>,
+    /// Maximum number of articles (default: 20)
+    pub limit: Option<usize>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ArticleGetArgs {
+    /// `naddr1...` of the article; alternatively give `author` and `identifier`
+    pub naddr: Option<String>,
+    pub author: Option<String>,
+    pub identifier: Option<String>,
+    /// Fetch the draft (kind 30024) rather than the article (default: false)
+    pub draft: Option<bool>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArticlePublishResult {
+    pub identifier: String,
+    pub kind: u16,
+    pub naddr: String,
+    #[serde(flatten)]
+    pub send: SendResult,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Article {
+    pub identifier: String,
+    pub kind: u16,
+    pub naddr: String,
+    pub author: String,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub image: Option<String>,
+    pub published_at: Option<u64>,
+    pub created_at: u64,
+    pub hashtags: Vec<String>,
+    pub event_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.to_lowercase().chars() {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.ends_with('-') && !slug.is_empty() {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+fn tag_value(event: &Event, name: &str) -> Option<String> {
+    event
+        .tags
+        .iter()
+        .map(|t| t.as_slice())
+        .find(|t| t.first().map(String::as_str) == Some(name))
+        .and_then(|t| t.get(1).cloned())
+}
+
+pub fn naddr(kind: Kind, author: PublicKey, identifier: &str, relays: Vec<RelayUrl>) -> Result<String> {
+    let coordinate = Coordinate::new(kind, author).identifier(identifier);
+    Ok(Nip19Coordinate::new(coordinate, relays).to_bech32()?)
+}
+
+fn to_article(event: &Event, with_content: bool) -> Result<Article> {
+    let identifier = event.tags.identifier().unwrap_or_default().to_string();
+    Ok(Article {
+        naddr: naddr(event.kind, event.pubkey, &identifier, Vec::new())?,
+        identifier,
+        kind: event.kind.as_u16(),
+        author: event.pubkey.to_hex(),
+        title: tag_value(event, "title"),
+        summary: tag_value(event, "summary"),
+        image: tag_value(event, "image"),
+        published_at: tag_value(event, "published_at").and_then(|t| t.parse().ok()),
+        created_at: event.created_at.as_secs(),
+        hashtags: event
+            .tags
+            .iter()
+            .map(|t| t.as_slice())
+            .filter(|t| t.first().map(String::as_str) == Some("t"))
+            .filter_map(|t| t.get(1).cloned())
+            .collect(),
+        event_id: event.id.to_hex(),
+        content: with_content.then(|| event.content.clone()),
+    })
+}
+
+async fn fetch_article(
+    client: &Client,
+    kind: Kind,
+    author: PublicKey,
+    identifier: &str,
+    timeout: u64,
+) -> Result<Option<Event>> {
+    let filter = Filter::new().kind(kind).author(author).identifier(identifier);
+    Ok(relays::fetch_events(client, filter, timeout)
+        .await?
+        .into_iter()
+        .max_by_key(|e| (e.created_at, e.id)))
+}
+
+pub async fn publish(client: &Client, args: ArticlePublishArgs) -> Result<ArticlePublishResult> {
+    let me = client.signer().await?.get_public_key().await?;
+    let identifier = args
+        .identifier
+        .filter(|d| !d.trim().is_empty())
+        .unwrap_or_else(|| slugify(&args.title));
+    if identifier.is_empty() {
+        bail!("identifier could not be derived from the title; pass one explicitly");
+    }
+    let kind = Kind::from(if args.draft.unwrap_or(false) {
+        DRAFT_KIND
+    } else {
+        ARTICLE_KIND
+    });
+
+    let published_at = match args.published_at {
+        Some(t) => t,
+        None => fetch_article(client, Kind::from(ARTICLE_KIND), me, &identifier, 10)
+            .await?
+            .and_then(|existing| tag_value(&existing, "published_at"))
+            .and_then(|t| t.parse().ok())
+            .unwrap_or_else(|| Timestamp::now().as_secs()),
+    };
+
+    let mut tags = vec![
+        Tag::identifier(identifier.clone()),
+        Tag::parse(["title", args.title.as_str()])?,
+        Tag::parse(["published_at".to_string(), published_at.to_string()])?,
+    ];
+    if let Some(summary) = &args.summary {
+        tags.push(Tag::parse(["summary", summary.as_str()])?);
+    }
+    if let Some(image) = &args.image {
+        tags.push(Tag::parse(["image", image.as_str()])?);
+    }
+    for hashtag in args.hashtags.unwrap_or_default() {
+        tags.push(Tag::hashtag(hashtag.trim_start_matches('#').to_lowercase()));
+    }
+
+    let builder = EventBuilder::new(kind, args.content).tags(tags);
+    let send = relays::publish_event_builder(client, builder, args.to_relays.clone()).await?;
+    let hints = send
+        .success
+        .iter()
+        .filter_map(|u| RelayUrl::parse(u).ok())
+        .take(3)
+        .collect();
+    Ok(ArticlePublishResult {
+        naddr: naddr(kind, me, &identifier, hints)?,
+        identifier,
+        kind: kind.as_u16(),
+        send,
+    })
+}
+
+pub async fn list(client: &Client, args: ArticleListArgs) -> Result<Vec<Article>> {
+    let author = match args.author {
+        Some(a) => nip05::resolve_pubkey(&a).await?,
+        None => client.signer().await?.get_public_key().await?,
+    };
+    let kind = Kind::from(if args.drafts.unwrap_or(false) {
+        DRAFT_KIND
+    } else {
+        ARTICLE_KIND
+    });
+    let limit = args.limit.unwrap_or(20);
+    let filter = Filter::new().kind(kind).author(author).limit(limit * 2);
+    let mut newest: HashMap<String, Event> = HashMap::new();
+    let found = relays::fetch_events(client, filter, args.timeout_secs.unwrap_or(10)).await?;
+    for event in relays::visible_events(client, found).await {
+        let d = event.tags.identifier().unwrap_or_default().to_string();
+        match newest.get(&d) {
+            Some(existing) if existing.created_at >= event.created_at => {}
+            _ => {
+                newest.insert(d, event);
+            }
+        }
+    }
+    let mut articles = newest
+        .values()
+        .map(|e| to_article(e, false))
+        .collect::<Result<Vec<_>>>()?;
+    articles.sort_by(|a, b| {
+        b.published_at
+            .unwrap_or(b.created_at)
+            .cmp(&a.published_at.unwrap_or(a.created_at))
+    });
+    articles.truncate(limit);
+    Ok(articles)
+}
+
+pub async fn get(client: &Client, args: ArticleGetArgs) -> Result<Article> {
+    let timeout = args.timeout_secs.unwrap_or(10);
+    let (kind, author, identifier) = match (&args.naddr, &args.author, &args.identifier) {
+        (Some(naddr), _, _) => {
+            let naddr = naddr.trim().trim_start_matches("nostr:");
+            let coordinate = Nip19Coordinate::from_bech32(naddr)?.coordinate;
+            (coordinate.kind, coordinate.public_key, coordinate.identifier)
+        }
+        (None, Some(author), Some(identifier)) => {
+            let kind = Kind::from(if args.draft.unwrap_or(false) {
+                DRAFT_KIND
+            } else {
+                ARTICLE_KIND
+            });
+            (kind, nip05::resolve_pubkey(author).await?, identifier.clone())
+        }
+        _ => bail!("provide naddr, or author and identifier"),
+    };
+    let event = fetch_article(client, kind, author, &identifier, timeout)
+        .await?
+        .ok_or_else(|| anyhow!("article {identifier} not found"))?;
+    to_article(&event, true)
+}
@@ -0,0 +1,885 @@
+use crate::deletions::{self, DeleteEventsArgs};
+use crate::mint::{self, MintConnector, Proof};
+use crate::nip05;
+use crate::relays::{self, SendResult};
+use crate::thread;
+use anyhow::{anyhow, bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+const WALLET_KIND: u16 = 17375;
+const TOKEN_KIND: u16 = 7375;
+const HISTORY_KIND: u16 = 7376;
+const NUTZAP_KIND: u16 = 9321;
+const NUTZAP_INFO_KIND: u16 = 10019;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CashuWalletArgs {
+    /// Include spending history (kind 7376) (default: false)
+    pub include_history: Option<bool>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CashuWalletSetupArgs {
+    /// Mint URLs the wallet uses and accepts nutzaps from
+    pub mints: Vec<String>,
+    /// Relays senders should publish nutzaps to (default: configured relays)
+    pub nutzap_relays: Option<Vec<String>>,
+    /// Specific relay URLs to publish the wallet events to
+    pub to_relays: Option<Vec<String>>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+    /// Confirm there is no existing wallet and generate a new P2PK key when
+    /// no kind 17375 could be fetched (default: false)
+    pub force: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NutzapSendArgs {
+    /// Recipient (hex, npub or NIP-05); must have published a kind 10019 nutzap info event
+    pub recipient: String,
+    /// Amount in sats
+    pub amount_sats: u64,
+    /// Event being nutzapped: hex ID, note1... or nevent1...
+    pub event: Option<String>,
+    pub comment: Option<String>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NutzapReceiveArgs {
+    /// Unix timestamp - only nutzaps after this time
+    pub since: Option<u64>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryRow {
+    pub id: String,
+    pub created_at: u64,
+    pub direction: Option<String>,
+    pub amount: Option<u64>,
+    /// `[event id, marker]` pairs: created, destroyed or redeemed
+    pub events: Vec<(String, String)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CashuWalletView {
+    pub configured: bool,
+    /// Public key nutzaps must be P2PK-locked to
+    pub p2pk_pubkey: Option<String>,
+    pub mints: Vec<String>,
+    pub balances: BTreeMap<String, u64>,
+    pub balance: u64,
+    pub token_events: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history: Option<Vec<HistoryRow>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CashuWalletSetupResult {
+    pub p2pk_pubkey: String,
+    pub mints: Vec<String>,
+    pub wallet: SendResult,
+    pub nutzap_info: SendResult,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NutzapSendResult {
+    pub mint: String,
+    pub amount: u64,
+    pub fee: u64,
+    pub change: u64,
+    pub nutzap: SendResult,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedeemedNutzap {
+    pub nutzap_id: String,
+    pub sender: String,
+    pub mint: String,
+    pub amount: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct NutzapReceiveResult {
+    pub redeemed: Vec<RedeemedNutzap>,
+    /// Nutzap ID to the reason it could not be redeemed
+    pub failed: BTreeMap<String, String>,
+    pub already_redeemed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TokenContent {
+    mint: String,
+    proofs: Vec<Proof>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    del: Vec<String>,
+}
+
+struct TokenEvent {
+    id: EventId,
+    content: TokenContent,
+}
+
+#[derive(Default)]
+struct WalletState {
+    configured: bool,
+    privkey: Option<SecretKey>,
+    mints: Vec<String>,
+    tokens: Vec<TokenEvent>,
+    history: Vec<HistoryRow>,
+    redeemed: HashSet<String>,
+}
+
+impl WalletState {
+    fn balances(&self) -> BTreeMap<String, u64> {
+        let mut balances = BTreeMap::new();
+        for token in &self.tokens {
+            *balances.entry(token.content.mint.clone()).or_default() +=
+                token.content.proofs.iter().map(|p| p.amount).sum::<u64>();
+        }
+        balances
+    }
+
+    fn p2pk_keys(&self) -> Option<Keys> {
+        self.privkey.clone().map(Keys::new)
+    }
+}
+
+fn normalize_mint(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_string()
+}
+
+fn tag_rows(event: &Event) -> impl Iterator<Item = &[String]> {
+    event.tags.iter().map(|t| t.as_slice())
+}
+
+async fn decrypt_json<T: serde::de::DeserializeOwned>(
+    signer: &dyn NostrSigner,
+    me: &PublicKey,
+    event: &Event,
+) -> Result<T> {
+    let plain = signer.nip44_decrypt(me, &event.content).await?;
+    Ok(serde_json::from_str(&plain)?)
+}
+
+async fn encrypt_json<T: Serialize>(signer: &dyn NostrSigner, me: &PublicKey, value: &T) -> Result<String> {
+    Ok(signer.nip44_encrypt(me, &serde_json::to_string(value)?).await?)
+}
+
+/// Reads the active key's NIP-60 wallet: the newest kind 17375, every
+/// unspent kind 7375 token event and the kind 7376 history.
+async fn load_wallet(client: &Client, timeout: u64) -> Result<WalletState> {
+    let signer = client.signer().await?;
+    let me = signer.get_public_key().await?;
+    let mut state = WalletState::default();
+
+    let wallet = relays::fetch_events(
+        client,
+        Filter::new().kind(Kind::from(WALLET_KIND)).author(me),
+        timeout,
+    )
+    .await?
+    .into_iter()
+    .max_by_key(|e| e.created_at);
+    if let Some(wallet) = wallet {
+        state.configured = true;
+        let rows: Vec<Vec<String>> = decrypt_json(signer.as_ref(), &me, &wallet).await?;
+        for row in rows {
+            match (row.first().map(String::as_str), row.get(1)) {
+                (Some("privkey"), Some(key)) => state.privkey = Some(SecretKey::parse(key)?),
+                (Some("mint"), Some(url)) => state.mints.push(normalize_mint(url)),
+                _ => {}
+            }
+        }
+    }
+
+    let mut tokens = Vec::new();
+    let mut deleted: HashSet<String> = HashSet::new();
+    let token_events = relays::fetch_events(
+        client,
+        Filter::new().kind(Kind::from(TOKEN_KIND)).author(me),
+        timeout,
+    )
+    .await?;
+    for event in token_events {
+        let Ok(mut content) = decrypt_json::<TokenContent>(signer.as_ref(), &me, &event).await else {
+            continue;
+        };
+        content.mint = normalize_mint(&content.mint);
+        deleted.extend(content.del.iter().cloned());
+        tokens.push(TokenEvent {
+            id: event.id,
+            content,
+        });
+    }
+    tokens.retain(|t| !deleted.contains(&t.id.to_hex()));
+    state.tokens = tokens;
+
+    let history = relays::fetch_events(
+        client,
+        Filter::new().kind(Kind::from(HISTORY_KIND)).author(me),
+        timeout,
+    )
+    .await?;
+    for event in history {
+        for row in tag_rows(&event) {
+            let is_e = row.first().map(String::as_str) == Some("e");
+            if is_e && row.get(3).map(String::as_str) == Some("redeemed") {
+                if let Some(id) = row.get(1) {
+                    state.redeemed.insert(id.clone());
+                }
+            }
+        }
+        let rows: Vec<Vec<String>> = decrypt_json(signer.as_ref(), &me, &event)
+            .await
+            .unwrap_or_default();
+        let mut entry = HistoryRow {
+            id: event.id.to_hex(),
+            created_at: event.created_at.as_secs(),
+            direction: None,
+            amount: None,
+            events: Vec::new(),
+        };
+        for row in rows.iter().map(Vec::as_slice).chain(tag_rows(&event)) {
+            match (row.first().map(String::as_str), row.get(1)) {
+                (Some("direction"), Some(d)) => entry.direction = Some(d.clone()),
+                (Some("amount"), Some(a)) => entry.amount = a.parse().ok(),
+                (Some("e"), Some(id)) => {
+                    let marker = row.get(3).cloned().unwrap_or_default();
+                    entry.events.push((id.clone(), marker));
+                }
+                _ => {}
+            }
+        }
+        state.history.push(entry);
+    }
+    state.history.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(state)
+}
+
+pub async fn wallet(client: &Client, args: CashuWalletArgs) -> Result<CashuWalletView> {
+    let state = load_wallet(client, args.timeout_secs.unwrap_or(10)).await?;
+    let balances = state.balances();
+    Ok(CashuWalletView {
+        configured: state.configured,
+        p2pk_pubkey: state.p2pk_keys().map(|k| k.public_key().to_hex()),
+        mints: state.mints.clone(),
+        balance: balances.values().sum(),
+        balances,
+        token_events: state.tokens.len(),
+        history: args.include_history.unwrap_or(false).then_some(state.history),
+    })
+}
+
+/// Publishes the kind 17375 wallet event (keeping an existing P2PK key) and
+/// the kind 10019 nutzap info event advertising the mints, relays and key.
+pub async fn setup_wallet(client: &Client, args: CashuWalletSetupArgs) -> Result<CashuWalletSetupResult> {
+    if args.mints.is_empty() {
+        bail!("at least one mint is required");
+    }
+    let signer = client.signer().await?;
+    let me = signer.get_public_key().await?;
+    let state = load_wallet(client, args.timeout_secs.unwrap_or(10)).await?;
+    // A wallet that didn't come back (timeout, relay missing it) looks the
+    // same as no wallet; replacing its key would strand every nutzap locked
+    // to the old one.
+    let privkey = match state.privkey {
+        Some(privkey) => privkey,
+        None if args.force.unwrap_or(false) => SecretKey::generate(),
+        None => bail!(
+            "no wallet key could be fetched from the relays; pass force only if this key has no existing wallet"
+        ),
+    };
+    let p2pk = Keys::new(privkey.clone()).public_key();
+    let mints: Vec<String> = args.mints.iter().map(|m| normalize_mint(m)).collect();
+
+    let mut rows = vec![vec!["privkey".to_string(), privkey.to_secret_hex()]];
+    rows.extend(mints.iter().map(|m| vec!["mint".to_string(), m.clone()]));
+    let content = encrypt_json(signer.as_ref(), &me, &rows).await?;
+    let wallet = relays::publish_event_builder(
+        client,
+        EventBuilder::new(Kind::from(WALLET_KIND), content),
+        args.to_relays.clone(),
+    )
+    .await?;
+
+    let nutzap_relays = match args.nutzap_relays {
+        Some(relays) if !relays.is_empty() => relays,
+        _ => relays::get_relay_urls(client).await,
+    };
+    let mut tags = Vec::new();
+    for relay in &nutzap_relays {
+        tags.push(Tag::parse(["relay", relay.as_str()])?);
+    }
+    for mint in &mints {
+        tags.push(Tag::parse(["mint", mint.as_str(), "sat"])?);
+    }
+    tags.push(Tag::parse(["pubkey".to_string(), p2pk.to_hex()])?);
+    let nutzap_info = relays::publish_event_builder(
+        client,
+        EventBuilder::new(Kind::from(NUTZAP_INFO_KIND), "").tags(tags),
+        args.to_relays,
+    )
+    .await?;
+
+    Ok(CashuWalletSetupResult {
+        p2pk_pubkey: p2pk.to_hex(),
+        mints,
+        wallet,
+        nutzap_info,
+    })
+}
+
+struct NutzapInfo {
+    mints: Vec<String>,
+    relays: Vec<String>,
+    p2pk: String,
+}
+
+async fn nutzap_info(client: &Client, recipient: PublicKey, timeout: u64) -> Result<NutzapInfo> {
+    let event = relays::fetch_events(
+        client,
+        Filter::new().kind(Kind::from(NUTZAP_INFO_KIND)).author(recipient),
+        timeout,
+    )
+    .await?
+    .into_iter()
+    .max_by_key(|e| e.created_at)
+    .ok_or_else(|| anyhow!("recipient has not published nutzap info (kind 10019)"))?;
+    let mut info = NutzapInfo {
+        mints: Vec::new(),
+        relays: Vec::new(),
+        p2pk: String::new(),
+    };
+    for row in tag_rows(&event) {
+        match (row.first().map(String::as_str), row.get(1)) {
+            (Some("mint"), Some(url)) => info.mints.push(normalize_mint(url)),
+            (Some("relay"), Some(url)) => info.relays.push(url.clone()),
+            (Some("pubkey"), Some(pk)) => info.p2pk = pk.to_lowercase(),
+            _ => {}
+        }
+    }
+    if info.p2pk.is_empty() {
+        bail!("recipient's nutzap info has no pubkey");
+    }
+    Ok(info)
+}
+
+/// Replaces spent token events: publishes one new kind 7375 holding what is
+/// left, deletes the old ones and records a kind 7376 history entry.
+async fn rotate_tokens(
+    client: &Client,
+    mint: &str,
+    spent: &[EventId],
+    keep: Vec<Proof>,
+    direction: &str,
+    amount: u64,
+    public_tags: Vec<Tag>,
+) -> Result<()> {
+    let signer = client.signer().await?;
+    let me = signer.get_public_key().await?;
+    let mut history = vec![
+        vec!["direction".to_string(), direction.to_string()],
+        vec!["amount".to_string(), amount.to_string()],
+    ];
+    if !keep.is_empty() {
+        let content = TokenContent {
+            mint: mint.to_string(),
+            proofs: keep,
+            del: spent.iter().map(|id| id.to_hex()).collect(),
+        };
+        let encrypted = encrypt_json(signer.as_ref(), &me, &content).await?;
+        let sent = relays::publish_event_builder(
+            client,
+            EventBuilder::new(Kind::from(TOKEN_KIND), encrypted),
+            None,
+        )
+        .await?;
+        history.push(vec!["e".to_string(), sent.id, String::new(), "created".to_string()]);
+    }
+    if !spent.is_empty() {
+        for id in spent {
+            history.push(vec!["e".to_string(), id.to_hex(), String::new(), "destroyed".to_string()]);
+        }
+        deletions::delete_events(
+            client,
+            DeleteEventsArgs {
+                event_ids: Some(spent.iter().map(|id| id.to_hex()).collect()),
+                coordinates: None,
+                reason: None,
+                to_relays: None,
+                // The token events were just loaded from our own wallet.
+                force: Some(true),
+                timeout_secs: None,
+            },
+        )
+        .await?;
+    }
+    let encrypted = encrypt_json(signer.as_ref(), &me, &history).await?;
+    relays::publish_event_builder(
+        client,
+        EventBuilder::new(Kind::from(HISTORY_KIND), encrypted).tags(public_tags),
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Error for proofs the mint issued that the wallet may not have stored;
+/// they are included so the funds can be recovered by hand.
+fn unsaved(error: anyhow::Error, mint: &str, proofs: &[Proof]) -> anyhow::Error {
+    let token = serde_json::json!({ "mint": mint, "proofs": proofs });
+    anyhow!("{error}; proofs that may not be stored: {token}")
+}
+
+/// Outcome of swapping wallet proofs for a nutzap.
+struct NutzapSwap {
+    inputs: Vec<Proof>,
+    /// Proofs P2PK-locked to the recipient
+    locked: Vec<Proof>,
+    change: Vec<Proof>,
+    fee: u64,
+}
+
+/// Spends the largest `available` proofs at `mint_url` for `amount` sats
+/// locked to `lock` plus unlocked change.
+async fn swap_for_nutzap(
+    mints: &dyn MintConnector,
+    mint_url: &str,
+    available: &[Proof],
+    amount: u64,
+    lock: &str,
+) -> Result<NutzapSwap> {
+    let mint = mints.connect(mint_url);
+    let keyset = mint.keyset().await?;
+    let mut available = available.to_vec();
+    available.sort_by(|a, b| b.amount.cmp(&a.amount));
+    let mut inputs = Vec::new();
+    let mut total = 0u64;
+    for proof in available {
+        if total >= amount + keyset.fee(inputs.len()) {
+            break;
+        }
+        total += proof.amount;
+        inputs.push(proof);
+    }
+    let fee = keyset.fee(inputs.len());
+    if total < amount + fee {
+        bail!("insufficient funds at {mint_url}: need {} sats including fees", amount + fee);
+    }
+
+    let mut outputs = Vec::new();
+    for part in mint::split_amount(amount, &keyset)? {
+        outputs.push(mint::blind(mint::p2pk_secret(lock), part, &keyset)?);
+    }
+    let locked_count = outputs.len();
+    for part in mint::split_amount(total - amount - fee, &keyset)? {
+        outputs.push(mint::blind(mint::random_hex(), part, &keyset)?);
+    }
+    let messages: Vec<_> = outputs.iter().map(|o| o.message.clone()).collect();
+    let signatures = mint.swap(&inputs, &messages).await?;
+    let mut locked = mint::unblind(outputs, signatures, &keyset)?;
+    let change = locked.split_off(locked_count);
+    Ok(NutzapSwap {
+        inputs,
+        locked,
+        change,
+        fee,
+    })
+}
+
+/// Sends a NIP-61 nutzap: swaps wallet proofs at a mint the recipient
+/// accepts for proofs P2PK-locked to their key, stores the change back in
+/// the wallet and publishes the locked proofs as kind 9321 on their relays.
+pub async fn send_nutzap(
+    client: &Client,
+    mints: &dyn MintConnector,
+    args: NutzapSendArgs,
+) -> Result<NutzapSendResult> {
+    let timeout = args.timeout_secs.unwrap_or(10);
+    if args.amount_sats == 0 {
+        bail!("amount_sats must be greater than 0");
+    }
+    let recipient = nip05::resolve_pubkey(&args.recipient).await?;
+    let event_tag = match &args.event {
+        Some(reference) => {
+            let (id, hints) = thread::parse_event_ref(reference)?;
+            let relay = hints.first().map(|r| r.to_string()).unwrap_or_default();
+            Some(Tag::parse(["e".to_string(), id.to_hex(), relay])?)
+        }
+        None => None,
+    };
+    let info = nutzap_info(client, recipient, timeout).await?;
+    let state = load_wallet(client, timeout).await?;
+    let balances = state.balances();
+    let mint_url = info
+        .mints
+        .iter()
+        .find(|m| balances.get(*m).is_some_and(|b| *b >= args.amount_sats))
+        .cloned()
+        .ok_or_else(|| {
+            anyhow!(
+                "no mint accepted by the recipient holds {} sats in this wallet",
+                args.amount_sats
+            )
+        })?;
+
+    let available: Vec<(EventId, Proof)> = state
+        .tokens
+        .iter()
+        .filter(|t| t.content.mint == mint_url)
+        .flat_map(|t| t.content.proofs.iter().map(move |p| (t.id, p.clone())))
+        .collect();
+    let lock = if info.p2pk.len() == 66 {
+        info.p2pk.clone()
+    } else {
+        format!("02{}", info.p2pk)
+    };
+    let proofs: Vec<Proof> = available.iter().map(|(_, p)| p.clone()).collect();
+    let swap = swap_for_nutzap(mints, &mint_url, &proofs, args.amount_sats, &lock).await?;
+    let change: u64 = swap.change.iter().map(|p| p.amount).sum();
+
+    // The inputs are spent at this point: store the change before the
+    // nutzap goes out, and hand back whatever may not have been stored.
+    let spent_events: Vec<EventId> = available
+        .iter()
+        .filter(|(_, p)| swap.inputs.contains(p))
+        .map(|(id, _)| *id)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let mut keep: Vec<Proof> = state
+        .tokens
+        .iter()
+        .filter(|t| spent_events.contains(&t.id))
+        .flat_map(|t| t.content.proofs.iter().cloned())
+        .filter(|p| !swap.inputs.contains(p))
+        .collect();
+    keep.extend(swap.change.iter().cloned());
+    rotate_tokens(
+        client,
+        &mint_url,
+        &spent_events,
+        keep,
+        "out",
+        args.amount_sats,
+        Vec::new(),
+    )
+    .await
+    .map_err(|e| unsaved(e, &mint_url, &[swap.locked.clone(), swap.change.clone()].concat()))?;
+
+    let publish = async {
+        let mut tags = Vec::new();
+        for proof in &swap.locked {
+            tags.push(Tag::parse(["proof".to_string(), serde_json::to_string(proof)?])?);
+        }
+        tags.push(Tag::parse(["u", mint_url.as_str()])?);
+        tags.push(Tag::public_key(recipient));
+        tags.extend(event_tag);
+        let builder =
+            EventBuilder::new(Kind::from(NUTZAP_KIND), args.comment.unwrap_or_default()).tags(tags);
+        let to_relays = (!info.relays.is_empty()).then(|| info.relays.clone());
+        relays::publish_event_builder(client, builder, to_relays).await
+    };
+    let nutzap = publish.await.map_err(|e| unsaved(e, &mint_url, &swap.locked))?;
+    Ok(NutzapSendResult {
+        mint: mint_url,
+        amount: args.amount_sats,
+        fee: swap.fee,
+        change,
+        nutzap,
+    })
+}
+
+/// Checks that every proof in a nutzap is locked to `keys` and signs it.
+/// Returns the mint URL and the signed inputs.
+fn locked_inputs(keys: &Keys, nutzap: &Event) -> Result<(String, Vec<Proof>)> {
+    let mint_url = tag_rows(nutzap)
+        .find(|row| row.first().map(String::as_str) == Some("u"))
+        .and_then(|row| row.get(1))
+        .map(|u| normalize_mint(u))
+        .ok_or_else(|| anyhow!("nutzap has no mint"))?;
+    let lock = format!("02{}", keys.public_key().to_hex());
+    let mut inputs: Vec<Proof> = Vec::new();
+    for row in tag_rows(nutzap).filter(|row| row.first().map(String::as_str) == Some("proof")) {
+        let mut proof: Proof = serde_json::from_str(row.get(1).map(String::as_str).unwrap_or_default())?;
+        if mint::p2pk_lock(&proof.secret).as_deref() != Some(lock.as_str()) {
+            bail!("proof is not locked to this wallet's key");
+        }
+        mint::sign_p2pk(&mut proof, keys);
+        inputs.push(proof);
+    }
+    if inputs.is_empty() {
+        bail!("nutzap carries no proofs");
+    }
+    Ok((mint_url, inputs))
+}
+
+/// Swaps signed nutzap inputs for fresh proofs; returns the amount after
+/// fees and the new proofs.
+async fn swap_redeemed(
+    mints: &dyn MintConnector,
+    mint_url: &str,
+    inputs: &[Proof],
+) -> Result<(u64, Vec<Proof>)> {
+    let mint = mints.connect(mint_url);
+    let keyset = mint.keyset().await?;
+    let total: u64 = inputs.iter().map(|p| p.amount).sum();
+    let fee = keyset.fee(inputs.len());
+    let amount = total
+        .checked_sub(fee)
+        .filter(|a| *a > 0)
+        .ok_or_else(|| anyhow!("nutzap of {total} sats does not cover the {fee} sat fee"))?;
+    let outputs = mint::split_amount(amount, &keyset)?
+        .into_iter()
+        .map(|a| mint::blind(mint::random_hex(), a, &keyset))
+        .collect::<Result<Vec<_>>>()?;
+    let messages: Vec<_> = outputs.iter().map(|o| o.message.clone()).collect();
+    let signatures = mint.swap(inputs, &messages).await?;
+    Ok((amount, mint::unblind(outputs, signatures, &keyset)?))
+}
+
+async fn redeem(
+    client: &Client,
+    mints: &dyn MintConnector,
+    keys: &Keys,
+    nutzap: &Event,
+) -> Result<RedeemedNutzap> {
+    let (mint_url, inputs) = locked_inputs(keys, nutzap)?;
+    let public_tags = vec![
+        Tag::parse(["e".to_string(), nutzap.id.to_hex(), String::new(), "redeemed".to_string()])?,
+        Tag::public_key(nutzap.pubkey),
+    ];
+    let (amount, proofs) = swap_redeemed(mints, &mint_url, &inputs).await?;
+
+    // The nutzap's proofs are spent now: hand back the new ones if they
+    // cannot be stored.
+    rotate_tokens(client, &mint_url, &[], proofs.clone(), "in", amount, public_tags)
+        .await
+        .map_err(|e| unsaved(e, &mint_url, &proofs))?;
+    Ok(RedeemedNutzap {
+        nutzap_id: nutzap.id.to_hex(),
+        sender: nutzap.pubkey.to_hex(),
+        mint: mint_url,
+        amount,
+    })
+}
+
+/// Redeems incoming kind 9321 nutzaps from the wallet's mints that have not
+/// been redeemed yet, swapping the locked proofs for fresh ones.
+pub async fn receive_nutzaps(
+    client: &Client,
+    mints: &dyn MintConnector,
+    args: NutzapReceiveArgs,
+) -> Result<NutzapReceiveResult> {
+    let timeout = args.timeout_secs.unwrap_or(10);
+    let me = client.signer().await?.get_public_key().await?;
+    let state = load_wallet(client, timeout).await?;
+    let keys = state
+        .p2pk_keys()
+        .ok_or_else(|| anyhow!("no NIP-60 wallet; use nostr_cashu_wallet_setup first"))?;
+    if state.mints.is_empty() {
+        bail!("wallet lists no mints");
+    }
+    let mut filter = Filter::new()
+        .kind(Kind::from(NUTZAP_KIND))
+        .pubkey(me)
+        .custom_tags(SingleLetterTag::lowercase(Alphabet::U), state.mints.clone());
+    if let Some(since) = args.since {
+        filter = filter.since(Timestamp::from(since));
+    }
+    let mut nutzaps = relays::fetch_events(client, filter, timeout).await?;
+    nutzaps.sort_by_key(|e| e.created_at);
+
+    let mut result = NutzapReceiveResult::default();
+    for nutzap in nutzaps {
+        if state.redeemed.contains(&nutzap.id.to_hex()) {
+            result.already_redeemed += 1;
+            continue;
+        }
+        match redeem(client, mints, &keys, &nutzap).await {
+            Ok(redeemed) => result.redeemed.push(redeemed),
+            Err(e) => {
+                result.failed.insert(nutzap.id.to_hex(), e.to_string());
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mint::{BlindSignature, BlindedMessage, Keyset, Mint};
+    use nostr::secp256k1::{self, Scalar};
+    use nostr::util::SECP256K1;
+    use std::sync::{Arc, Mutex};
+
+    const MINT: &str = "https://mint.example.com";
+
+    /// Signs whatever balances, like a mint that trusts its inputs, and
+    /// records the inputs it was asked to spend.
+    struct MockMint {
+        keys: BTreeMap<u64, secp256k1::SecretKey>,
+        input_fee_ppk: u64,
+        spent: Mutex<Vec<Proof>>,
+    }
+
+    impl Mint for MockMint {
+        fn url(&self) -> &str {
+            MINT
+        }
+
+        fn keyset(&self) -> BoxedFuture<'_, Result<Keyset>> {
+            Box::pin(async move {
+                Ok(Keyset {
+                    id: "00mock".to_string(),
+                    keys: self
+                        .keys
+                        .iter()
+                        .map(|(a, k)| (*a, secp256k1::PublicKey::from_secret_key(&SECP256K1, k)))
+                        .collect(),
+                    input_fee_ppk: self.input_fee_ppk,
+                })
+            })
+        }
+
+        fn swap<'a>(
+            &'a self,
+            inputs: &'a [Proof],
+            outputs: &'a [BlindedMessage],
+        ) -> BoxedFuture<'a, Result<Vec<BlindSignature>>> {
+            Box::pin(async move {
+                let fee = (self.input_fee_ppk * inputs.len() as u64).div_ceil(1_000);
+                let spent: u64 = inputs.iter().map(|p| p.amount).sum();
+                let issued: u64 = outputs.iter().map(|o| o.amount).sum();
+                if spent != issued + fee {
+                    bail!("unbalanced swap");
+                }
+                self.spent.lock().unwrap().extend(inputs.iter().cloned());
+                outputs
+                    .iter()
+                    .map(|o| {
+                        let k = self.keys.get(&o.amount).ok_or_else(|| anyhow!("bad amount"))?;
+                        let b = secp256k1::PublicKey::from_slice(&hex::decode(&o.b)?)?;
+                        let c = b.mul_tweak(&SECP256K1, &Scalar::from(*k))?;
+                        Ok(BlindSignature {
+                            amount: o.amount,
+                            id: o.id.clone(),
+                            c: hex::encode(c.serialize()),
+                        })
+                    })
+                    .collect()
+            })
+        }
+    }
+
+    struct MockMints(Arc<MockMint>);
+
+    impl MintConnector for MockMints {
+        fn connect(&self, _url: &str) -> Arc<dyn Mint> {
+            self.0.clone()
+        }
+    }
+
+    fn mock(input_fee_ppk: u64) -> (MockMints, Arc<MockMint>) {
+        let keys = (0..8)
+            .map(|i| {
+                let key = secp256k1::SecretKey::from_slice(&SecretKey::generate().to_secret_bytes());
+                (1u64 << i, key.unwrap())
+            })
+            .collect();
+        let mint = Arc::new(MockMint {
+            keys,
+            input_fee_ppk,
+            spent: Mutex::new(Vec::new()),
+        });
+        (MockMints(mint.clone()), mint)
+    }
+
+    fn proof(amount: u64) -> Proof {
+        Proof {
+            id: "00mock".to_string(),
+            amount,
+            secret: mint::random_hex(),
+            c: String::new(),
+            witness: None,
+        }
+    }
+
+    fn lock_for(keys: &Keys) -> String {
+        format!("02{}", keys.public_key().to_hex())
+    }
+
+    #[tokio::test]
+    async fn send_locks_the_amount_and_returns_change() {
+        let (mints, mint) = mock(1_000);
+        let recipient = Keys::generate();
+        let wallet = [proof(64), proof(32), proof(4)];
+        let swap = swap_for_nutzap(&mints, MINT, &wallet, 50, &lock_for(&recipient))
+            .await
+            .unwrap();
+
+        assert_eq!(swap.inputs, vec![wallet[0].clone()]);
+        assert_eq!(*mint.spent.lock().unwrap(), swap.inputs);
+        assert_eq!(swap.fee, 1);
+        assert_eq!(swap.locked.iter().map(|p| p.amount).sum::<u64>(), 50);
+        assert_eq!(swap.change.iter().map(|p| p.amount).sum::<u64>(), 13);
+        for p in &swap.locked {
+            assert_eq!(mint::p2pk_lock(&p.secret), Some(lock_for(&recipient)));
+        }
+        assert!(swap.change.iter().all(|p| mint::p2pk_lock(&p.secret).is_none()));
+
+        let err = unsaved(anyhow!("relay down"), MINT, &swap.change).to_string();
+        assert!(err.contains(&swap.change[0].secret), "{err}");
+    }
+
+    #[tokio::test]
+    async fn send_refuses_without_funds() {
+        let (mints, mint) = mock(0);
+        let Err(err) =
+            swap_for_nutzap(&mints, MINT, &[proof(4)], 50, &lock_for(&Keys::generate())).await
+        else {
+            panic!("swap without funds succeeded");
+        };
+        assert!(err.to_string().contains("insufficient funds"), "{err}");
+        assert!(mint.spent.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn redeem_signs_and_swaps_locked_proofs() {
+        let (mints, mint) = mock(1_000);
+        let sender = Keys::generate();
+        let recipient = Keys::generate();
+        let swap = swap_for_nutzap(&mints, MINT, &[proof(64)], 50, &lock_for(&recipient))
+            .await
+            .unwrap();
+        let mut tags = vec![Tag::parse(["u", MINT]).unwrap()];
+        for p in &swap.locked {
+            tags.push(Tag::parse(["proof".to_string(), serde_json::to_string(p).unwrap()]).unwrap());
+        }
+        let nutzap = EventBuilder::new(Kind::from(NUTZAP_KIND), "")
+            .tags(tags)
+            .sign_with_keys(&sender)
+            .unwrap();
+
+        assert!(locked_inputs(&Keys::generate(), &nutzap).is_err());
+        let (mint_url, inputs) = locked_inputs(&recipient, &nutzap).unwrap();
+        assert_eq!(mint_url, MINT);
+        assert!(inputs.iter().all(|p| p.witness.is_some()));
+
+        mint.spent.lock().unwrap().clear();
+        let (amount, proofs) = swap_redeemed(&mints, &mint_url, &inputs).await.unwrap();
+        assert_eq!(amount, 50 - inputs.len() as u64);
+        assert_eq!(proofs.iter().map(|p| p.amount).sum::<u64>(), amount);
+        assert_eq!(*mint.spent.lock().unwrap(), inputs);
+    }
+}
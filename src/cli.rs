@@ -52,4 +52,9 @@ pub enum Command {
         #[arg(long, default_value_t = DEFAULT_EXTENSION_ID.to_string())]
         id: String,
     },
+    /// Approve a wallet payment previewed by nostr_nwc_pay_invoice, or list
+    /// the pending ones when no token is given
+    NwcApprove {
+        token: Option<String>,
+    },
 }
@@ -0,0 +1,302 @@
+use crate::pow::{self, Miner};
+use crate::relays::{self, PublishOptions, SendResult};
+use crate::repost;
+use anyhow::Result;
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct MediaMetadata {
+    /// URL as it appears in the content
+    pub url: String,
+    /// MIME type, e.g. `image/jpeg`
+    pub mime_type: Option<String>,
+    /// Dimensions as `<width>x<height>`
+    pub dim: Option<String>,
+    pub blurhash: Option<String>,
+    /// SHA-256 hex of the file
+    pub sha256: Option<String>,
+    /// Alt text for accessibility
+    pub alt: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+pub struct ContentTagOptions {
+    /// Derive `t`, `p`, `q` and `a` tags from hashtags and `nostr:` references (default: true)
+    pub auto_tags: Option<bool>,
+    /// Rewrite plain `@npub1...`/`@nprofile1...` mentions as `nostr:` URIs (default: false)
+    pub link_mentions: Option<bool>,
+    /// NIP-92 metadata for media URLs in the content; each becomes an `imeta` tag
+    pub media: Option<Vec<MediaMetadata>>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PostNoteArgs {
+    /// Text content of the note
+    pub content: String,
+    /// Extra tags appended after the derived ones
+    pub tags: Option<Vec<Vec<String>>>,
+    /// Proof of work difficulty; raised to the relays' NIP-11 `min_pow_difficulty` when lower
+    pub pow: Option<u8>,
+    /// Give up mining after this many seconds
+    pub pow_time_budget_secs: Option<u64>,
+    /// Specific relay URLs to publish to
+    pub to_relays: Option<Vec<String>>,
+    /// Event to quote (NIP-18): hex ID, note1... or nevent1...; its `nostr:` reference is appended
+    pub quote: Option<String>,
+    /// Relay where the quoted event can be found (default: first hint in the reference)
+    pub quote_relay_hint: Option<String>,
+    #[serde(flatten)]
+    pub content_tags: ContentTagOptions,
+    #[serde(flatten)]
+    pub publish: PublishOptions,
+}
+
+fn bech32_prefix(s: &str) -> &str {
+    let end = s
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(s.len());
+    &s[..end]
+}
+
+fn link_mentions(content: &str) -> String {
+    let mut out = content.to_string();
+    for token in content.split_whitespace() {
+        let Some(rest) = token.strip_prefix('@') else {
+            continue;
+        };
+        let bech = bech32_prefix(rest);
+        let is_profile = matches!(
+            Nip19::from_bech32(bech),
+            Ok(Nip19::Pubkey(_)) | Ok(Nip19::Profile(_))
+        );
+        if is_profile {
+            out = out.replace(&format!("@{bech}"), &format!("nostr:{bech}"));
+        }
+    }
+    out
+}
+
+/// `#tag` words, lowercased. Punctuation around a tag, as in `(#tag)` or
+/// `#tag,`, is not part of it; all-digit tags such as `#1` are skipped.
+fn hashtags(content: &str) -> Vec<String> {
+    content
+        .split_whitespace()
+        .filter_map(|token| {
+            token
+                .trim_start_matches(|c: char| c.is_ascii_punctuation() && c != '#')
+                .strip_prefix('#')
+        })
+        .map(|rest| {
+            rest.chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+                .collect::<String>()
+                .trim_end_matches('-')
+                .to_lowercase()
+        })
+        .filter(|tag| !tag.is_empty() && !tag.chars().all(|c| c.is_ascii_digit()))
+        .collect()
+}
+
+fn nostr_uris(content: &str) -> Vec<Nip19> {
+    content
+        .match_indices("nostr:")
+        .filter_map(|(i, _)| Nip19::from_bech32(bech32_prefix(&content[i + 6..])).ok())
+        .collect()
+}
+
+fn imeta_tag(media: &MediaMetadata) -> Result<Tag> {
+    let mut fields = vec!["imeta".to_string(), format!("url {}", media.url)];
+    let optional = [
+        ("m", &media.mime_type),
+        ("dim", &media.dim),
+        ("blurhash", &media.blurhash),
+        ("x", &media.sha256),
+        ("alt", &media.alt),
+    ];
+    for (key, value) in optional {
+        if let Some(value) = value {
+            fields.push(format!("{key} {value}"));
+        }
+    }
+    Ok(Tag::parse(fields)?)
+}
+
+/// Rewrites mentions if asked, then derives tags from the content: hashtags
+/// become `t`, profiles `p`, notes and nevents `q` (plus `p` for a known
+/// author), naddrs `a`, and supplied media metadata `imeta` (NIP-92).
+pub fn prepare(content: &str, options: &ContentTagOptions) -> Result<(String, Vec<Tag>)> {
+    let content = if options.link_mentions.unwrap_or(false) {
+        link_mentions(content)
+    } else {
+        content.to_string()
+    };
+    let mut tags: Vec<Tag> = Vec::new();
+    let mut push = |tag: Tag| {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    };
+
+    if options.auto_tags.unwrap_or(true) {
+        for hashtag in hashtags(&content) {
+            push(Tag::hashtag(hashtag));
+        }
+        for reference in nostr_uris(&content) {
+            match reference {
+                Nip19::Pubkey(pk) => push(Tag::public_key(pk)),
+                Nip19::Profile(profile) => push(Tag::public_key(profile.public_key)),
+                Nip19::EventId(id) => push(Tag::parse(["q".to_string(), id.to_hex()])?),
+                Nip19::Event(event) => {
+                    let relay = event.relays.first().map(|r| r.to_string()).unwrap_or_default();
+                    let author = event.author.map(|a| a.to_hex()).unwrap_or_default();
+                    push(Tag::parse(["q".to_string(), event.event_id.to_hex(), relay, author])?);
+                    if let Some(author) = event.author {
+                        push(Tag::public_key(author));
+                    }
+                }
+                Nip19::Coordinate(naddr) => {
+                    let relay = naddr.relays.first().map(|r| r.to_string()).unwrap_or_default();
+                    push(Tag::parse(["a".to_string(), naddr.coordinate.to_string(), relay])?);
+                    push(Tag::public_key(naddr.coordinate.public_key));
+                }
+                _ => {}
+            }
+        }
+    }
+    for media in options.media.iter().flatten() {
+        if content.contains(&media.url) {
+            push(imeta_tag(media)?);
+        }
+    }
+    Ok((content, tags))
+}
+
+/// Appends a quoted event's reference after the commentary, on its own
+/// paragraph, and adds the tags not derived from the content already.
+fn append_quote(
+    content: String,
+    tags: &mut Vec<Tag>,
+    reference: &str,
+    quote_tags: Vec<Tag>,
+) -> String {
+    for tag in quote_tags {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    if content.trim().is_empty() {
+        reference.to_string()
+    } else {
+        format!("{}\n\n{reference}", content.trim_end())
+    }
+}
+
+pub async fn post_note(client: &Client, miner: &Miner, args: PostNoteArgs) -> Result<SendResult> {
+    let (mut content, mut tags) = prepare(&args.content, &args.content_tags)?;
+    if let Some(target) = &args.quote {
+        let (reference, quote_tags) =
+            repost::quote(client, target, args.quote_relay_hint.as_deref(), 10).await?;
+        content = append_quote(content, &mut tags, &reference, quote_tags);
+    }
+    for tag in args.tags.unwrap_or_default() {
+        tags.push(Tag::parse(tag)?);
+    }
+    tags.extend(args.publish.tags()?);
+    let builder = EventBuilder::text_note(content).tags(tags);
+    let miner = miner.clone().time_budget(args.pow_time_budget_secs);
+    let event =
+        pow::sign_mined(client, &miner, builder, args.pow, args.to_relays.as_deref()).await?;
+    relays::publish_event(client, &event, args.to_relays).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashtags_are_lowercased_without_surrounding_punctuation() {
+        let content = "#Nostr rocks (#rust) and #MCP, see #1 or #tag-. not a#tag";
+        assert_eq!(hashtags(content), ["nostr", "rust", "mcp", "tag"]);
+    }
+
+    #[test]
+    fn only_valid_profile_mentions_are_linked() {
+        let npub = Keys::generate().public_key().to_bech32().unwrap();
+        let content = format!("hi @{npub}, and @npub1broken and @alice");
+        assert_eq!(
+            link_mentions(&content),
+            format!("hi nostr:{npub}, and @npub1broken and @alice")
+        );
+    }
+
+    #[test]
+    fn prepare_derives_tags_from_content() {
+        let keys = Keys::generate();
+        let npub = keys.public_key().to_bech32().unwrap();
+        let note = EventId::all_zeros();
+        let content = format!(
+            "@{npub} #Nostr #nostr nostr:{} https://example.com/cat.jpg",
+            note.to_bech32().unwrap()
+        );
+        let options = ContentTagOptions {
+            auto_tags: None,
+            link_mentions: Some(true),
+            media: Some(vec![MediaMetadata {
+                url: "https://example.com/cat.jpg".to_string(),
+                mime_type: Some("image/jpeg".to_string()),
+                dim: None,
+                blurhash: None,
+                sha256: None,
+                alt: Some("a cat".to_string()),
+            }]),
+        };
+        let (content, tags) = prepare(&content, &options).unwrap();
+        assert!(content.starts_with(&format!("nostr:{npub}")));
+        let tags: Vec<Vec<String>> = tags.into_iter().map(|t| t.to_vec()).collect();
+        assert_eq!(
+            tags,
+            [
+                vec!["t".to_string(), "nostr".to_string()],
+                vec!["p".to_string(), keys.public_key().to_hex()],
+                vec!["q".to_string(), note.to_hex()],
+                vec![
+                    "imeta".to_string(),
+                    "url https://example.com/cat.jpg".to_string(),
+                    "m image/jpeg".to_string(),
+                    "alt a cat".to_string(),
+                ],
+            ]
+        );
+
+        let options = ContentTagOptions {
+            auto_tags: Some(false),
+            ..Default::default()
+        };
+        let (_, tags) = prepare("#nostr", &options).unwrap();
+        assert!(tags.is_empty());
+    }
+
+    #[test]
+    fn quote_goes_after_the_commentary_without_duplicate_tags() {
+        let pubkey = Keys::generate().public_key();
+        let mut tags = vec![Tag::public_key(pubkey)];
+        let quote_tags = vec![
+            Tag::parse(["q", "abc"]).unwrap(),
+            Tag::public_key(pubkey),
+        ];
+        let content = append_quote(
+            "Look at this \n".to_string(),
+            &mut tags,
+            "nostr:nevent1x",
+            quote_tags,
+        );
+        assert_eq!(content, "Look at this\n\nnostr:nevent1x");
+        assert_eq!(tags.len(), 2);
+
+        let content =
+            append_quote(" ".to_string(), &mut Vec::new(), "nostr:nevent1x", Vec::new());
+        assert_eq!(content, "nostr:nevent1x");
+    }
+}
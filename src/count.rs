@@ -0,0 +1,440 @@
+use crate::relays;
+use anyhow::{anyhow, bail, Result};
+use futures::{SinkExt, StreamExt};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+const HLL_REGISTERS: usize = 256;
+const FALLBACK_PAGE_SIZE: usize = 500;
+/// The fetch-and-count fallback stops here and reports an inexact count.
+const FALLBACK_MAX_EVENTS: usize = 10_000;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CountEventsArgs {
+    /// NIP-01 filter describing the events to count
+    pub filter: serde_json::Value,
+    /// Relays to ask (default: all configured relays)
+    pub relays: Option<Vec<String>>,
+    /// Timeout in seconds per relay (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CountMethod {
+    Nip45,
+    FetchAndCount,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeMethod {
+    /// Fetched events unioned by ID
+    Union,
+    /// HyperLogLog registers from every relay merged and estimated
+    Hll,
+    /// Largest single count, a lower bound
+    Max,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelayCount {
+    pub method: CountMethod,
+    pub count: Option<u64>,
+    /// The relay marked its count as approximate, or the fallback fetch
+    /// stopped before it ran out of events
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub approximate: bool,
+    /// The relay returned NIP-45 HyperLogLog registers
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub hll: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CountResult {
+    pub relays: BTreeMap<String, RelayCount>,
+    /// Best estimate of distinct matching events across all relays
+    pub merged: u64,
+    pub merge: MergeMethod,
+    /// True only when `merged` is a complete, de-duplicated count
+    pub exact: bool,
+}
+
+/// NIP-45 HyperLogLog: 256 one-byte registers, merged by taking the
+/// per-register maximum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hll([u8; HLL_REGISTERS]);
+
+impl Default for Hll {
+    fn default() -> Self {
+        Self([0; HLL_REGISTERS])
+    }
+}
+
+impl Hll {
+    pub fn from_hex(value: &str) -> Result<Self> {
+        let bytes = hex::decode(value)?;
+        let registers: [u8; HLL_REGISTERS] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("hll must be {HLL_REGISTERS} bytes"))?;
+        Ok(Self(registers))
+    }
+
+    /// Adds a pubkey: the byte at `offset` picks the register, and the
+    /// leading zero bits after it, plus one, are the candidate value.
+    pub fn add(&mut self, pubkey: &PublicKey, offset: usize) {
+        let bytes = pubkey.to_bytes();
+        let Some(&index) = bytes.get(offset) else {
+            return;
+        };
+        let mut zeros: u32 = 0;
+        for byte in &bytes[offset + 1..] {
+            zeros += byte.leading_zeros();
+            if *byte != 0 {
+                break;
+            }
+        }
+        let value = (zeros + 1).min(u8::MAX as u32) as u8;
+        let register = &mut self.0[index as usize];
+        *register = (*register).max(value);
+    }
+
+    pub fn merge(&mut self, other: &Hll) {
+        for (mine, theirs) in self.0.iter_mut().zip(other.0.iter()) {
+            *mine = (*mine).max(*theirs);
+        }
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let m = HLL_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.0.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let raw = alpha * m * m / sum;
+        let empty = self.0.iter().filter(|r| **r == 0).count();
+        let estimate = if raw <= 2.5 * m && empty > 0 {
+            m * (m / empty as f64).ln()
+        } else {
+            raw
+        };
+        estimate.round() as u64
+    }
+}
+
+/// The NIP-45 register offset for a filter, when it is eligible for
+/// HyperLogLog: a single tag condition with a single 32-byte hex value. The
+/// offset is the hex digit at position 32 of that value, plus 8.
+pub fn hll_offset(filter: &Filter) -> Option<usize> {
+    let mut tags = filter.generic_tags.values();
+    let values = tags.next()?;
+    if tags.next().is_some() || values.len() != 1 {
+        return None;
+    }
+    let value = values.iter().next()?;
+    if value.len() != 64 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digit = (value.as_bytes()[32] as char).to_digit(16)?;
+    Some(digit as usize + 8)
+}
+
+struct CountReply {
+    count: u64,
+    approximate: bool,
+    hll: Option<Hll>,
+}
+
+/// Reads one relay message as the answer to COUNT subscription `sub`.
+/// Messages about other subscriptions, and NOTICEs that don't name `sub`,
+/// are not an answer: relays send NOTICEs for all kinds of reasons.
+fn count_reply(sub: &str, text: &str) -> Option<Result<CountReply>> {
+    let Ok(Value::Array(parts)) = serde_json::from_str::<Value>(text) else {
+        return None;
+    };
+    let label = parts.first().and_then(Value::as_str);
+    let second = parts.get(1).and_then(Value::as_str);
+    match (label, second) {
+        (Some("COUNT"), Some(id)) if id == sub => {
+            let body = parts.get(2).cloned().unwrap_or(Value::Null);
+            let Some(count) = body.get("count").and_then(Value::as_u64) else {
+                return Some(Err(anyhow!("COUNT reply without a count")));
+            };
+            Some(Ok(CountReply {
+                count,
+                approximate: body
+                    .get("approximate")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false),
+                hll: body
+                    .get("hll")
+                    .and_then(Value::as_str)
+                    .and_then(|h| Hll::from_hex(h).ok()),
+            }))
+        }
+        (Some("CLOSED"), Some(id)) if id == sub => Some(Err(anyhow!(
+            "relay refused COUNT: {}",
+            parts.get(2).and_then(Value::as_str).unwrap_or("")
+        ))),
+        (Some("NOTICE"), Some(notice)) if notice.contains(sub) => {
+            Some(Err(anyhow!("relay refused COUNT: {notice}")))
+        }
+        _ => None,
+    }
+}
+
+/// Sends a NIP-45 COUNT over its own connection and reads the raw reply, so
+/// the `hll` field the SDK does not expose can be merged.
+async fn nip45_count(relay: &str, filter: &Filter, timeout: Duration) -> Result<CountReply> {
+    let exchange = async {
+        let (mut ws, _) = tokio_tungstenite::connect_async(relay).await?;
+        let sub = SubscriptionId::generate().to_string();
+        let request = serde_json::json!(["COUNT", sub, filter]);
+        ws.send(WsMessage::Text(request.to_string().into())).await?;
+        while let Some(message) = ws.next().await {
+            let message = message?;
+            let Ok(text) = message.to_text() else {
+                continue;
+            };
+            if let Some(reply) = count_reply(&sub, text) {
+                let _ = ws.close(None).await;
+                return reply;
+            }
+        }
+        bail!("connection closed before a COUNT reply")
+    };
+    tokio::time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| anyhow!("no COUNT reply within {}s", timeout.as_secs()))?
+}
+
+async fn fetch_and_count(
+    client: &Client,
+    relay: &str,
+    filter: &Filter,
+    timeout: Duration,
+) -> Result<(Vec<Event>, bool)> {
+    if client.relay(relay).await.is_ok() {
+        return relays::fetch_all_from(
+            client,
+            relay,
+            filter,
+            FALLBACK_PAGE_SIZE,
+            FALLBACK_MAX_EVENTS,
+            timeout,
+        )
+        .await;
+    }
+    relays::with_relays(client, &[relay.to_string()], |scoped| async move {
+        relays::fetch_all_from(
+            &scoped,
+            relay,
+            filter,
+            FALLBACK_PAGE_SIZE,
+            FALLBACK_MAX_EVENTS,
+            timeout,
+        )
+        .await
+    })
+    .await
+}
+
+/// Asks every relay for a NIP-45 COUNT and falls back to fetching the
+/// matching events where COUNT is unsupported. Fetched events are unioned by
+/// id. Relay-side counts can only be combined through their HyperLogLog
+/// registers, which fetched events are added to as well; without registers
+/// from every COUNT relay the largest count is the best available.
+pub async fn count_events(client: &Client, args: CountEventsArgs) -> Result<CountResult> {
+    let filter = Filter::from_json(args.filter.to_string())?;
+    let targets = match args.relays {
+        Some(r) if !r.is_empty() => r,
+        _ => relays::get_relay_urls(client).await,
+    };
+    if targets.is_empty() {
+        bail!("no relays to count on");
+    }
+    let timeout = Duration::from_secs(args.timeout_secs.unwrap_or(10));
+    let offset = hll_offset(&filter);
+
+    let mut results = BTreeMap::new();
+    let mut fetched_ids: HashSet<EventId> = HashSet::new();
+    let mut registers = Hll::default();
+    let mut all_hll = offset.is_some();
+    let mut complete = true;
+    let mut largest_nip45: u64 = 0;
+    let mut nip45_relays = 0;
+    let mut answered = 0;
+
+    for url in targets {
+        let row = match nip45_count(&url, &filter, timeout).await {
+            Ok(reply) => {
+                answered += 1;
+                nip45_relays += 1;
+                largest_nip45 = largest_nip45.max(reply.count);
+                complete &= !reply.approximate;
+                match (&reply.hll, offset) {
+                    (Some(hll), Some(_)) => registers.merge(hll),
+                    _ => all_hll = false,
+                }
+                RelayCount {
+                    method: CountMethod::Nip45,
+                    count: Some(reply.count),
+                    approximate: reply.approximate,
+                    hll: reply.hll.is_some(),
+                    error: None,
+                }
+            }
+            Err(_) => match fetch_and_count(client, &url, &filter, timeout).await {
+                Ok((events, done)) => {
+                    answered += 1;
+                    complete &= done;
+                    let count = events.len() as u64;
+                    for event in events {
+                        if let Some(offset) = offset {
+                            registers.add(&event.pubkey, offset);
+                        }
+                        fetched_ids.insert(event.id);
+                    }
+                    RelayCount {
+                        method: CountMethod::FetchAndCount,
+                        count: Some(count),
+                        approximate: !done,
+                        hll: false,
+                        error: None,
+                    }
+                }
+                Err(e) => RelayCount {
+                    method: CountMethod::FetchAndCount,
+                    count: None,
+                    approximate: false,
+                    hll: false,
+                    error: Some(e.to_string()),
+                },
+            },
+        };
+        results.insert(url, row);
+    }
+
+    let fetched = fetched_ids.len() as u64;
+    let (merged, merge, exact) = if nip45_relays == 0 {
+        (fetched, MergeMethod::Union, complete)
+    } else if all_hll {
+        let estimate = registers.estimate().max(largest_nip45).max(fetched);
+        (estimate, MergeMethod::Hll, false)
+    } else {
+        let single = answered == 1 && complete;
+        (largest_nip45.max(fetched), MergeMethod::Max, single)
+    };
+    Ok(CountResult {
+        relays: results,
+        merged,
+        merge,
+        exact,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_needs_a_single_hex_tag_value() {
+        let target = "7e7e9c42a91bfef19fa929e5fda1b72e0ebc1a4c1141673e2794234d86addf4e";
+        let filter = Filter::new().kind(Kind::Reaction).event(EventId::parse(target).unwrap());
+        // position 32 of the id is '0' -> 0 + 8
+        assert_eq!(hll_offset(&filter), Some(8));
+        assert_eq!(hll_offset(&Filter::new().kind(Kind::TextNote)), None);
+        let two = Filter::new()
+            .kind(Kind::Reaction)
+            .custom_tag(SingleLetterTag::lowercase(Alphabet::E), target)
+            .custom_tag(SingleLetterTag::lowercase(Alphabet::P), target);
+        assert_eq!(hll_offset(&two), None);
+    }
+
+    #[test]
+    fn merge_keeps_register_maximum() {
+        let mut a = Hll::default();
+        let mut b = Hll::default();
+        a.0[1] = 3;
+        b.0[1] = 5;
+        b.0[2] = 1;
+        a.merge(&b);
+        assert_eq!(a.0[1], 5);
+        assert_eq!(a.0[2], 1);
+        let hex = hex::encode(a.0);
+        assert_eq!(Hll::from_hex(&hex).unwrap(), a);
+        assert!(Hll::from_hex("00ff").is_err());
+    }
+
+    #[test]
+    fn estimate_tracks_distinct_pubkeys() {
+        let mut hll = Hll::default();
+        assert_eq!(hll.estimate(), 0);
+        let keys: Vec<PublicKey> = (0..2_000).map(|_| Keys::generate().public_key()).collect();
+        for pk in &keys {
+            hll.add(pk, 8);
+            hll.add(pk, 8);
+        }
+        let estimate = hll.estimate() as f64;
+        // 256 registers give roughly 6.5% standard error
+        assert!((estimate - 2_000.0).abs() < 2_000.0 * 0.25, "estimate {estimate}");
+    }
+
+    #[test]
+    fn merged_registers_count_union_once() {
+        let keys: Vec<PublicKey> = (0..500).map(|_| Keys::generate().public_key()).collect();
+        let mut left = Hll::default();
+        let mut right = Hll::default();
+        for pk in &keys[..300] {
+            left.add(pk, 12);
+        }
+        for pk in &keys[200..] {
+            right.add(pk, 12);
+        }
+        left.merge(&right);
+        let estimate = left.estimate() as f64;
+        assert!((estimate - 500.0).abs() < 500.0 * 0.25, "estimate {estimate}");
+    }
+
+    #[test]
+    fn only_messages_for_the_subscription_answer() {
+        assert!(count_reply("abc", r#"["NOTICE","rate limited, slow down"]"#).is_none());
+        assert!(count_reply("abc", r#"["CLOSED","other","unsupported"]"#).is_none());
+        assert!(count_reply("abc", r#"["COUNT","other",{"count":3}]"#).is_none());
+        assert!(count_reply("abc", r#"["NOTICE","COUNT abc: unsupported"]"#)
+            .unwrap()
+            .is_err());
+        assert!(count_reply("abc", r#"["CLOSED","abc","unsupported"]"#)
+            .unwrap()
+            .is_err());
+        let reply = count_reply("abc", r#"["COUNT","abc",{"count":7,"approximate":true}]"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(reply.count, 7);
+        assert!(reply.approximate);
+        assert!(reply.hll.is_none());
+    }
+
+    #[tokio::test]
+    async fn nip45_count_reads_the_relay_reply() {
+        let keys = Keys::generate();
+        let relay = crate::test_relay::TestRelay::start().await;
+        relay
+            .insert((0..4).map(|i| {
+                EventBuilder::text_note(format!("note {i}"))
+                    .sign_with_keys(&keys)
+                    .unwrap()
+            }))
+            .await;
+        let filter = Filter::new().author(keys.public_key());
+        let reply = nip45_count(&relay.url, &filter, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(reply.count, 4);
+    }
+}
@@ -0,0 +1,243 @@
+use crate::relays::{self, SendResult};
+use crate::thread;
+use crate::{storage, util};
+use anyhow::{bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, OnceCell};
+
+static DELETIONS: OnceCell<Arc<DeletionStore>> = OnceCell::const_new();
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DeleteEventsArgs {
+    /// Events to delete: hex IDs, note1... or nevent1...
+    pub event_ids: Option<Vec<String>>,
+    /// Addressable events to delete, as `kind:pubkey:d-identifier` coordinates
+    pub coordinates: Option<Vec<String>>,
+    /// Reason shown to clients
+    pub reason: Option<String>,
+    /// Specific relay URLs to publish to
+    pub to_relays: Option<Vec<String>>,
+    /// Also delete IDs no relay returned, whose authorship cannot be checked (default: false)
+    pub force: Option<bool>,
+    /// Query timeout in seconds when checking authorship (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteEventsResult {
+    pub deleted_ids: Vec<String>,
+    pub deleted_coordinates: Vec<String>,
+    /// IDs no relay returned, deleted anyway because `force` was set
+    pub unverified: Vec<String>,
+    #[serde(flatten)]
+    pub send: SendResult,
+}
+
+/// Tombstones for deletions requested through goostr. Coordinates map to the
+/// deletion time, since only versions created before it are deleted.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DeletionFile {
+    ids: BTreeMap<String, u64>,
+    coordinates: BTreeMap<String, u64>,
+}
+
+pub struct DeletionStore {
+    path: PathBuf,
+    pass: Vec<u8>,
+    state: Mutex<DeletionFile>,
+}
+
+impl DeletionStore {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        Self::open(path, util::ensure_keystore_secret()?)
+    }
+
+    pub fn open(path: PathBuf, pass: Vec<u8>) -> Result<Self> {
+        let state = if path.exists() {
+            storage::decrypt_from_file(&path, &pass)?
+        } else {
+            DeletionFile::default()
+        };
+        Ok(Self {
+            path,
+            pass,
+            state: Mutex::new(state),
+        })
+    }
+
+    async fn record(&self, deletion: &Event) -> Result<()> {
+        let at = deletion.created_at.as_secs();
+        let mut state = self.state.lock().await;
+        for tag in deletion.tags.iter().map(|t| t.as_slice()) {
+            match (tag.first().map(String::as_str), tag.get(1)) {
+                (Some("e"), Some(id)) => {
+                    state.ids.insert(id.clone(), at);
+                }
+                (Some("a"), Some(coordinate)) => {
+                    state.coordinates.insert(coordinate.clone(), at);
+                }
+                _ => {}
+            }
+        }
+        util::ensure_parent_dir(&self.path)?;
+        storage::encrypt_to_file(&self.path, &self.pass, &*state)
+    }
+
+    pub async fn is_deleted(&self, event: &Event) -> bool {
+        let state = self.state.lock().await;
+        if state.ids.contains_key(&event.id.to_hex()) {
+            return true;
+        }
+        if !event.kind.is_replaceable() && !event.kind.is_addressable() {
+            return false;
+        }
+        let coordinate = Coordinate::new(event.kind, event.pubkey)
+            .identifier(event.tags.identifier().unwrap_or_default())
+            .to_string();
+        state
+            .coordinates
+            .get(&coordinate)
+            .is_some_and(|at| event.created_at.as_secs() <= *at)
+    }
+}
+
+pub async fn shared() -> Result<Arc<DeletionStore>> {
+    DELETIONS
+        .get_or_try_init(|| async { DeletionStore::load(util::nostr_deletions_path()).map(Arc::new) })
+        .await
+        .cloned()
+}
+
+/// Drops events goostr has requested deletion of.
+pub async fn apply_deletions(events: Vec<Event>) -> Vec<Event> {
+    let Ok(store) = shared().await else {
+        return events;
+    };
+    let mut kept = Vec::with_capacity(events.len());
+    for event in events {
+        if !store.is_deleted(&event).await {
+            kept.push(event);
+        }
+    }
+    kept
+}
+
+/// Publishes a NIP-09 kind 5 deletion request for events authored by the
+/// active key and records it locally so later queries hide the targets.
+pub async fn delete_events(client: &Client, args: DeleteEventsArgs) -> Result<DeleteEventsResult> {
+    let me = client.signer().await?.get_public_key().await?;
+    let timeout = args.timeout_secs.unwrap_or(10);
+
+    let mut ids = Vec::new();
+    for reference in args.event_ids.unwrap_or_default() {
+        ids.push(thread::parse_event_ref(&reference)?.0);
+    }
+    let mut coordinates = Vec::new();
+    for raw in args.coordinates.unwrap_or_default() {
+        let coordinate = Coordinate::from_str(raw.trim().trim_start_matches("nostr:"))
+            .or_else(|_| Nip19Coordinate::from_bech32(raw.trim()).map(|c| c.coordinate))
+            .map_err(|e| anyhow::anyhow!("invalid coordinate {raw}: {e}"))?;
+        if coordinate.public_key != me {
+            bail!("refusing to delete {raw}: it belongs to another key");
+        }
+        coordinates.push(coordinate);
+    }
+    if ids.is_empty() && coordinates.is_empty() {
+        bail!("provide event_ids or coordinates to delete");
+    }
+
+    let mut unverified: Vec<String> = ids.iter().map(|id| id.to_hex()).collect();
+    if !ids.is_empty() {
+        // Straight from the relays: events already hidden locally (muted,
+        // deleted, expired) must still count as found.
+        let found = client
+            .fetch_events(Filter::new().ids(ids.clone()), Duration::from_secs(timeout))
+            .await?;
+        for event in found.iter() {
+            if event.pubkey != me {
+                bail!(
+                    "refusing to delete {}: it was authored by {}",
+                    event.id.to_hex(),
+                    event.pubkey.to_bech32()?
+                );
+            }
+        }
+        unverified.retain(|id| !found.iter().any(|e| e.id.to_hex() == *id));
+    }
+    if !unverified.is_empty() && !args.force.unwrap_or(false) {
+        bail!(
+            "no relay returned {}, so authorship cannot be checked; pass force to delete anyway",
+            unverified.join(", ")
+        );
+    }
+
+    let mut request = EventDeletionRequest::new()
+        .ids(ids.clone())
+        .coordinates(coordinates.clone());
+    if let Some(reason) = args.reason.filter(|r| !r.is_empty()) {
+        request = request.reason(reason);
+    }
+    let deletion = client.sign_event_builder(EventBuilder::delete(request)).await?;
+    let send = relays::publish_event(client, &deletion, args.to_relays).await?;
+    // Only hide the targets once some relay has accepted the request.
+    if !send.success.is_empty() {
+        shared().await?.record(&deletion).await?;
+    }
+
+    Ok(DeleteEventsResult {
+        deleted_ids: ids.iter().map(|id| id.to_hex()).collect(),
+        deleted_coordinates: coordinates.iter().map(|c| c.to_string()).collect(),
+        unverified,
+        send,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn recorded_deletions_hide_targets_across_reopens() {
+        let path = std::env::temp_dir().join(format!(
+            "goostr-deletions-{}.enc",
+            Keys::generate().public_key().to_hex()
+        ));
+        let keys = Keys::generate();
+        let note = EventBuilder::text_note("gone").sign_with_keys(&keys).unwrap();
+        let kept = EventBuilder::text_note("kept").sign_with_keys(&keys).unwrap();
+        let article = |at: u64| {
+            EventBuilder::new(Kind::LongFormTextNote, "body")
+                .tag(Tag::identifier("post"))
+                .custom_created_at(Timestamp::from(at))
+                .sign_with_keys(&keys)
+                .unwrap()
+        };
+        let coordinate =
+            Coordinate::new(Kind::LongFormTextNote, keys.public_key()).identifier("post");
+        let deletion = EventBuilder::delete(
+            EventDeletionRequest::new()
+                .id(note.id)
+                .coordinate(coordinate),
+        )
+        .custom_created_at(Timestamp::from(2_000))
+        .sign_with_keys(&keys)
+        .unwrap();
+
+        let store = DeletionStore::open(path.clone(), b"test".to_vec()).unwrap();
+        store.record(&deletion).await.unwrap();
+
+        let reopened = DeletionStore::open(path.clone(), b"test".to_vec()).unwrap();
+        assert!(reopened.is_deleted(&note).await);
+        assert!(!reopened.is_deleted(&kept).await);
+        assert!(reopened.is_deleted(&article(1_000)).await);
+        assert!(!reopened.is_deleted(&article(3_000)).await);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -33,6 +33,18 @@ pub enum GoostrError {
     #[error("nostr protocol error: {0}")]
     NostrProtocol(String),
 
+    #[error("follow list is stale: built from kind 3 at {local}, relays have one from {remote}; refetch before publishing")]
+    StaleContactList { local: u64, remote: u64 },
+
+    #[error("follow list would shrink from {current} to {proposed} entries; pass allow_shrink to publish anyway")]
+    ContactListShrink { current: usize, proposed: usize },
+
+    #[error("no kind 3 could be fetched from the relays{}; pass force to publish anyway", .snapshot_at.map(|at| format!(" although a snapshot from {at} exists locally")).unwrap_or_default())]
+    ContactListUnavailable { snapshot_at: Option<u64> },
+
+    #[error("no kind {kind} list could be fetched from the relays; pass force to start a new one")]
+    ListUnavailable { kind: u16 },
+
     #[error(transparent)]
     Anyhow(#[from] anyhow::Error),
 
@@ -76,6 +88,16 @@ impl From<GoostrError> for ErrorData {
             GoostrError::NostrProtocol(msg) => {
                 ErrorData::internal_error(format!("nostr protocol error: {}", msg), None)
             }
+            err @ (GoostrError::StaleContactList { .. }
+            | GoostrError::ContactListShrink { .. }
+            | GoostrError::ContactListUnavailable { .. }
+            | GoostrError::ListUnavailable { .. }) => {
+                ErrorData::invalid_params(err.to_string(), None)
+            }
+            GoostrError::Anyhow(err) => match err.downcast::<GoostrError>() {
+                Ok(inner) => inner.into(),
+                Err(err) => ErrorData::internal_error(err.to_string(), None),
+            },
             other => ErrorData::internal_error(other.to_string(), None),
         }
     }
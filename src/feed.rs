@@ -0,0 +1,230 @@
+use crate::follows;
+use crate::profiles;
+use crate::relays;
+use anyhow::Result;
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+const DEFAULT_LIMIT: usize = 50;
+const DEFAULT_LOOKBACK_SECS: u64 = 24 * 60 * 60;
+const AUTHOR_BATCH: usize = 250;
+const MAX_OUTBOX_RELAYS_PER_AUTHOR: usize = 3;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FeedHomeArgs {
+    /// Only include items after this Unix timestamp (default: 24 hours ago)
+    pub since: Option<u64>,
+    /// Only include items before this Unix timestamp
+    pub until: Option<u64>,
+    /// Maximum number of items (default: 50)
+    pub limit: Option<usize>,
+    /// Event kinds to include (default: 1 notes, 6 reposts, 30023 articles)
+    pub kinds: Option<Vec<u16>>,
+    /// Attach author names and reply/reaction counts to each item (default: false)
+    pub hydrate: Option<bool>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct FeedStats {
+    pub replies: usize,
+    pub reactions: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeedItem {
+    pub event: Event,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<FeedStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeedHomeResult {
+    pub follows: usize,
+    /// Relays queried, with how many followed authors each was asked for
+    pub relays: BTreeMap<String, usize>,
+    pub items: Vec<FeedItem>,
+}
+
+/// NIP-65 write relays per author, from their newest kind 10002.
+pub async fn write_relays(
+    client: &Client,
+    authors: &[PublicKey],
+    timeout: u64,
+) -> Result<HashMap<PublicKey, Vec<String>>> {
+    let mut newest: HashMap<PublicKey, Event> = HashMap::new();
+    for chunk in authors.chunks(AUTHOR_BATCH) {
+        let filter = Filter::new()
+            .kind(Kind::RelayList)
+            .authors(chunk.iter().copied());
+        for event in relays::fetch_events(client, filter, timeout).await? {
+            match newest.get(&event.pubkey) {
+                Some(existing) if existing.created_at >= event.created_at => {}
+                _ => {
+                    newest.insert(event.pubkey, event);
+                }
+            }
+        }
+    }
+    Ok(newest
+        .into_iter()
+        .map(|(pk, event)| {
+            let urls = event
+                .tags
+                .iter()
+                .map(|t| t.as_slice())
+                .filter(|t| t.first().map(String::as_str) == Some("r"))
+                .filter(|t| t.get(2).map(String::as_str) != Some("read"))
+                .filter_map(|t| t.get(1).cloned())
+                .filter(|u| RelayUrl::parse(u).is_ok())
+                .take(MAX_OUTBOX_RELAYS_PER_AUTHOR)
+                .collect();
+            (pk, urls)
+        })
+        .collect())
+}
+
+async fn hydrate_stats(
+    client: &Client,
+    ids: &[EventId],
+    timeout: u64,
+) -> Result<HashMap<EventId, FeedStats>> {
+    let wanted: HashSet<EventId> = ids.iter().copied().collect();
+    let filter = Filter::new()
+        .kinds([Kind::TextNote, Kind::Comment, Kind::Reaction])
+        .events(ids.iter().copied());
+    let mut stats: HashMap<EventId, FeedStats> = HashMap::new();
+    for event in relays::fetch_events(client, filter, timeout).await? {
+        let targets = event
+            .tags
+            .iter()
+            .map(|t| t.as_slice())
+            .filter(|t| t.first().map(String::as_str) == Some("e"))
+            .filter_map(|t| t.get(1).and_then(|id| EventId::from_hex(id).ok()))
+            .filter(|id| wanted.contains(id))
+            .collect::<HashSet<_>>();
+        for target in targets {
+            let entry = stats.entry(target).or_default();
+            if event.kind == Kind::Reaction {
+                entry.reactions += 1;
+            } else {
+                entry.replies += 1;
+            }
+        }
+    }
+    Ok(stats)
+}
+
+/// Builds the active key's home timeline from its kind 3 follows. Authors
+/// with a known NIP-65 relay list are queried on their own write relays;
+/// everyone else is queried on the configured relays.
+pub async fn home(client: &Client, args: FeedHomeArgs) -> Result<FeedHomeResult> {
+    let me = client.signer().await?.get_public_key().await?;
+    let follows: Vec<PublicKey> = follows::fetch_follows(client, &me)
+        .await?
+        .iter()
+        .filter_map(|f| PublicKey::parse(&f.pubkey).ok())
+        .collect();
+    let timeout = args.timeout_secs.unwrap_or(10);
+    let limit = args.limit.unwrap_or(DEFAULT_LIMIT).max(1);
+    let kinds: Vec<Kind> = args
+        .kinds
+        .unwrap_or_else(|| vec![1, 6, 30023])
+        .into_iter()
+        .map(Kind::from)
+        .collect();
+    let since = Timestamp::from(
+        args.since
+            .unwrap_or_else(|| Timestamp::now().as_secs().saturating_sub(DEFAULT_LOOKBACK_SECS)),
+    );
+
+    let outboxes = write_relays(client, &follows, timeout).await?;
+    let default_relays = relays::get_relay_urls(client).await;
+    let mut plan: BTreeMap<String, Vec<PublicKey>> = BTreeMap::new();
+    for author in &follows {
+        let targets = outboxes
+            .get(author)
+            .filter(|urls| !urls.is_empty())
+            .unwrap_or(&default_relays);
+        for url in targets {
+            plan.entry(url.clone()).or_default().push(*author);
+        }
+    }
+
+    // Outbox relays outside the configured pool are reached through a
+    // short-lived client so they don't linger in the pool afterwards.
+    let mut extra: HashSet<String> = HashSet::new();
+    for relay in plan.keys() {
+        if client.relay(relay.as_str()).await.is_err() {
+            extra.insert(relay.clone());
+        }
+    }
+    let extra_urls: Vec<String> = extra.iter().cloned().collect();
+    let (plan_ref, extra_ref, kinds_ref) = (&plan, &extra, &kinds);
+    let until = args.until;
+    let events = relays::with_relays(client, &extra_urls, |scoped| async move {
+        let mut seen: HashSet<EventId> = HashSet::new();
+        let mut events: Vec<Event> = Vec::new();
+        for (relay, authors) in plan_ref {
+            let source = if extra_ref.contains(relay) { &scoped } else { client };
+            for chunk in authors.chunks(AUTHOR_BATCH) {
+                let mut filter = Filter::new()
+                    .authors(chunk.iter().copied())
+                    .kinds(kinds_ref.clone())
+                    .since(since)
+                    .limit(limit);
+                if let Some(until) = until {
+                    filter = filter.until(Timestamp::from(until));
+                }
+                let Ok(found) = source
+                    .fetch_events_from([relay.as_str()], filter, Duration::from_secs(timeout))
+                    .await
+                else {
+                    continue;
+                };
+                events.extend(found.into_iter().filter(|e| seen.insert(e.id)));
+            }
+        }
+        Ok(events)
+    })
+    .await?;
+    let mut events = relays::visible_events(client, events).await;
+    events.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.id.cmp(&b.id)));
+    events.truncate(limit);
+
+    let items = if args.hydrate.unwrap_or(false) {
+        let names =
+            profiles::display_names(client, events.iter().map(|e| e.pubkey), timeout).await?;
+        let ids: Vec<EventId> = events.iter().map(|e| e.id).collect();
+        let mut stats = hydrate_stats(client, &ids, timeout).await?;
+        events
+            .into_iter()
+            .map(|event| FeedItem {
+                author_name: names.get(&event.pubkey).cloned(),
+                stats: Some(stats.remove(&event.id).unwrap_or_default()),
+                event,
+            })
+            .collect()
+    } else {
+        events
+            .into_iter()
+            .map(|event| FeedItem {
+                event,
+                author_name: None,
+                stats: None,
+            })
+            .collect()
+    };
+
+    Ok(FeedHomeResult {
+        follows: follows.len(),
+        relays: plan.into_iter().map(|(url, a)| (url, a.len())).collect(),
+        items,
+    })
+}
@@ -0,0 +1,285 @@
+use crate::error::GoostrError;
+use crate::relays;
+use crate::{storage, util};
+use anyhow::{anyhow, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OnceCell};
+
+const FETCH_TIMEOUT_SECS: u64 = 10;
+const MAX_SNAPSHOTS_PER_KEY: usize = 20;
+const MAX_SNAPSHOT_KEYS: usize = 16;
+/// Lists at least this long may not lose more than half their entries in
+/// one publish without `allow_shrink`.
+const SHRINK_GUARD_MIN_LEN: usize = 10;
+
+static SNAPSHOTS: OnceCell<Arc<SnapshotStore>> = OnceCell::const_new();
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContactSnapshot {
+    pub id: String,
+    pub taken_at: u64,
+    pub reason: String,
+    pub event: Event,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotRow {
+    pub id: String,
+    pub taken_at: u64,
+    pub reason: String,
+    pub created_at: u64,
+    pub follows: usize,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FollowsSnapshotsArgs {}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FollowsRollbackArgs {
+    /// Snapshot ID from `nostr_follows_snapshots`
+    pub snapshot_id: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SnapshotFile {
+    keys: BTreeMap<String, Vec<ContactSnapshot>>,
+}
+
+pub struct SnapshotStore {
+    path: PathBuf,
+    pass: Vec<u8>,
+    state: Mutex<SnapshotFile>,
+}
+
+impl SnapshotStore {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let pass = util::ensure_keystore_secret()?;
+        let state = if path.exists() {
+            storage::decrypt_from_file(&path, &pass)?
+        } else {
+            SnapshotFile::default()
+        };
+        Ok(Self {
+            path,
+            pass,
+            state: Mutex::new(state),
+        })
+    }
+
+    pub async fn record(&self, event: &Event, reason: &str) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let list = state.keys.entry(event.pubkey.to_hex()).or_default();
+        if list.iter().any(|s| s.event.id == event.id) {
+            return Ok(());
+        }
+        list.push(ContactSnapshot {
+            id: event.id.to_hex()[..16].to_string(),
+            taken_at: Timestamp::now().as_secs(),
+            reason: reason.to_string(),
+            event: event.clone(),
+        });
+        if list.len() > MAX_SNAPSHOTS_PER_KEY {
+            let excess = list.len() - MAX_SNAPSHOTS_PER_KEY;
+            list.drain(..excess);
+        }
+        prune_keys(&mut state.keys, MAX_SNAPSHOT_KEYS);
+        util::ensure_parent_dir(&self.path)?;
+        storage::encrypt_to_file(&self.path, &self.pass, &*state)
+    }
+
+    pub async fn list(&self, pubkey: &PublicKey) -> Vec<ContactSnapshot> {
+        let state = self.state.lock().await;
+        let mut list = state.keys.get(&pubkey.to_hex()).cloned().unwrap_or_default();
+        list.reverse();
+        list
+    }
+}
+
+/// Drops the keys whose newest snapshot is oldest until at most `max` remain.
+fn prune_keys(keys: &mut BTreeMap<String, Vec<ContactSnapshot>>, max: usize) {
+    while keys.len() > max {
+        let Some(stalest) = keys
+            .iter()
+            .min_by_key(|(_, list)| list.last().map_or(0, |s| s.taken_at))
+            .map(|(key, _)| key.clone())
+        else {
+            break;
+        };
+        keys.remove(&stalest);
+    }
+}
+
+pub async fn shared() -> Result<Arc<SnapshotStore>> {
+    SNAPSHOTS
+        .get_or_try_init(|| async {
+            SnapshotStore::load(util::nostr_contact_snapshots_path()).map(Arc::new)
+        })
+        .await
+        .cloned()
+}
+
+fn p_tag_count(event: &Event) -> usize {
+    event
+        .tags
+        .iter()
+        .filter(|t| t.as_slice().first().map(String::as_str) == Some("p"))
+        .count()
+}
+
+/// Newest kind 3 for `pubkey` across every configured relay.
+pub async fn latest_contact_list(client: &Client, pubkey: &PublicKey) -> Result<Option<Event>> {
+    let filter = Filter::new().kind(Kind::ContactList).author(*pubkey);
+    Ok(relays::fetch_events(client, filter, FETCH_TIMEOUT_SECS)
+        .await?
+        .into_iter()
+        .max_by_key(|e| (e.created_at, e.id)))
+}
+
+/// Snapshots a kind 3 that was read from the relays when it belongs to the
+/// active key. Lists of other people (copy, export, feed) are not kept.
+pub async fn observe(client: &Client, event: &Event) -> Result<()> {
+    let me = client.signer().await?.get_public_key().await?;
+    if event.pubkey != me {
+        return Ok(());
+    }
+    shared().await?.record(event, "fetched").await
+}
+
+/// True when `remote` is a different contact list than `base` and not older
+/// than it, i.e. the list being published was not built from the newest one.
+fn is_stale(base: Option<&Event>, remote: &Event) -> bool {
+    match base {
+        None => true,
+        Some(base) => remote.id != base.id && remote.created_at >= base.created_at,
+    }
+}
+
+/// Refuses to publish a follow list built from a kind 3 other than the
+/// newest one on the relays, or that would drop most of the current follows.
+/// `base` is the event the list was built from, as returned by the fetch.
+/// The newest remote list is snapshotted first so it can be restored.
+///
+/// When the relays return no kind 3 at all (a timeout looks the same as a
+/// new account) the publish is refused unless `force` is set, so a failed
+/// fetch can't replace the real list with an empty one.
+pub async fn check_before_publish(
+    client: &Client,
+    base: Option<&Event>,
+    proposed_len: usize,
+    allow_shrink: bool,
+    force: bool,
+) -> Result<()> {
+    let me = client.signer().await?.get_public_key().await?;
+    let Some(remote) = latest_contact_list(client, &me).await? else {
+        if force {
+            return Ok(());
+        }
+        let snapshot_at = shared()
+            .await?
+            .list(&me)
+            .await
+            .first()
+            .map(|s| s.event.created_at.as_secs());
+        return Err(GoostrError::ContactListUnavailable { snapshot_at }.into());
+    };
+    shared().await?.record(&remote, "before publish").await?;
+
+    if is_stale(base, &remote) {
+        return Err(GoostrError::StaleContactList {
+            local: base.map_or(0, |b| b.created_at.as_secs()),
+            remote: remote.created_at.as_secs(),
+        }
+        .into());
+    }
+    let current = p_tag_count(&remote);
+    if !allow_shrink && current >= SHRINK_GUARD_MIN_LEN && proposed_len * 2 < current {
+        return Err(GoostrError::ContactListShrink {
+            current,
+            proposed: proposed_len,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+pub async fn snapshots(client: &Client) -> Result<Vec<SnapshotRow>> {
+    let me = client.signer().await?.get_public_key().await?;
+    Ok(shared()
+        .await?
+        .list(&me)
+        .await
+        .into_iter()
+        .map(|s| SnapshotRow {
+            follows: p_tag_count(&s.event),
+            created_at: s.event.created_at.as_secs(),
+            id: s.id,
+            taken_at: s.taken_at,
+            reason: s.reason,
+        })
+        .collect())
+}
+
+/// Republishes the tags of an earlier kind 3 as a new contact list.
+pub async fn rollback(client: &Client, snapshot_id: &str) -> Result<relays::SendResult> {
+    let me = client.signer().await?.get_public_key().await?;
+    let snapshot = shared()
+        .await?
+        .list(&me)
+        .await
+        .into_iter()
+        .find(|s| s.id == snapshot_id)
+        .ok_or_else(|| anyhow!("no follow list snapshot {snapshot_id}"))?;
+    if let Some(remote) = latest_contact_list(client, &me).await? {
+        shared().await?.record(&remote, "before rollback").await?;
+    }
+    let builder = EventBuilder::new(Kind::ContactList, snapshot.event.content.clone())
+        .tags(snapshot.event.tags.iter().cloned());
+    let event = client.sign_event_builder(builder).await?;
+    let result = relays::publish_event(client, &event, None).await?;
+    shared().await?.record(&event, "rollback").await?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact_list(keys: &Keys, at: u64) -> Event {
+        EventBuilder::new(Kind::ContactList, "")
+            .custom_created_at(Timestamp::from(at))
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn stale_only_against_a_different_newer_list() {
+        let keys = Keys::generate();
+        let base = contact_list(&keys, 100);
+        assert!(!is_stale(Some(&base), &base));
+        assert!(is_stale(Some(&base), &contact_list(&keys, 101)));
+        assert!(is_stale(None, &base));
+        // A relay lagging behind what we built from is not a conflict
+        assert!(!is_stale(Some(&base), &contact_list(&keys, 99)));
+    }
+
+    #[test]
+    fn prune_keeps_the_most_recently_snapshotted_keys() {
+        let snapshot = |taken_at: u64| ContactSnapshot {
+            id: String::new(),
+            taken_at,
+            reason: "fetched".to_string(),
+            event: contact_list(&Keys::generate(), taken_at),
+        };
+        let mut keys: BTreeMap<String, Vec<ContactSnapshot>> = (0..5u64)
+            .map(|i| (format!("key{i}"), vec![snapshot(10 - i), snapshot(100 + i)]))
+            .collect();
+        prune_keys(&mut keys, 3);
+        let kept: Vec<&str> = keys.keys().map(String::as_str).collect();
+        assert_eq!(kept, ["key2", "key3", "key4"]);
+    }
+}
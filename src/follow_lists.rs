@@ -0,0 +1,481 @@
+use crate::follows::{self, FollowEntry};
+use crate::relays::SendResult;
+use crate::nip05;
+use crate::profiles;
+use anyhow::{anyhow, bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const NAME_TIMEOUT_SECS: u64 = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct FollowRow {
+    /// Followed public key (hex, npub or NIP-05 on input; hex on output)
+    pub pubkey: String,
+    pub relay_url: Option<String>,
+    pub petname: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ListFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyMode {
+    /// Keep current follows and add or update the given ones
+    #[default]
+    Merge,
+    /// Make the given list the whole follow list
+    Replace,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FollowsSetArgs {
+    /// Follows to set
+    pub follows: Vec<FollowRow>,
+    /// `replace` (default) or `merge` into the current list
+    pub mode: Option<ApplyMode>,
+    /// Only show the diff without publishing (default: false)
+    pub dry_run: Option<bool>,
+    /// Publish even if more than half of the current follows would be dropped
+    pub allow_shrink: Option<bool>,
+    /// Publish even if no kind 3 could be fetched from the relays
+    pub force: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FollowsAddArgs {
+    /// Public key to follow (hex, npub or NIP-05)
+    pub pubkey: String,
+    pub relay_url: Option<String>,
+    pub petname: Option<String>,
+    /// Only show the diff without publishing (default: false)
+    pub dry_run: Option<bool>,
+    /// Publish even if no kind 3 could be fetched from the relays
+    pub force: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FollowsRemoveArgs {
+    /// Public key to unfollow (hex, npub or NIP-05)
+    pub pubkey: String,
+    /// Only show the diff without publishing (default: false)
+    pub dry_run: Option<bool>,
+    /// Publish even if more than half of the current follows would be dropped
+    pub allow_shrink: Option<bool>,
+    /// Publish even if no kind 3 could be fetched from the relays
+    pub force: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FollowsExportArgs {
+    /// Whose follow list to export (default: active key)
+    pub pubkey: Option<String>,
+    /// `json` (default) or `csv`
+    pub format: Option<ListFormat>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FollowsImportArgs {
+    /// Exported follow list as JSON array or CSV with a `pubkey,relay_url,petname` header
+    pub data: String,
+    /// `json` (default) or `csv`
+    pub format: Option<ListFormat>,
+    /// `merge` (default) or `replace`
+    pub mode: Option<ApplyMode>,
+    /// Only show the diff without publishing (default: true)
+    pub dry_run: Option<bool>,
+    /// Publish even if more than half of the current follows would be dropped
+    pub allow_shrink: Option<bool>,
+    /// Publish even if no kind 3 could be fetched from the relays
+    pub force: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FollowsCopyArgs {
+    /// Whose follow list to copy (hex, npub or NIP-05)
+    pub source: String,
+    /// `merge` (default) or `replace`
+    pub mode: Option<ApplyMode>,
+    /// Only show the diff without publishing (default: true)
+    pub dry_run: Option<bool>,
+    /// Publish even if more than half of the current follows would be dropped
+    pub allow_shrink: Option<bool>,
+    /// Publish even if no kind 3 could be fetched from the relays
+    pub force: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldChange {
+    pub pubkey: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub petname: Option<(Option<String>, Option<String>)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relay_url: Option<(Option<String>, Option<String>)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FollowDiff {
+    pub added: Vec<FollowRow>,
+    pub removed: Vec<FollowRow>,
+    pub changed: Vec<FieldChange>,
+    pub unchanged: usize,
+    pub before: usize,
+    pub after: usize,
+    /// Display names of added, removed and changed follows, by hex public key
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub names: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FollowsApplyResult {
+    pub diff: FollowDiff,
+    pub dry_run: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<SendResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FollowsExport {
+    pub pubkey: String,
+    pub count: usize,
+    pub data: String,
+}
+
+impl From<&FollowEntry> for FollowRow {
+    fn from(entry: &FollowEntry) -> Self {
+        Self {
+            pubkey: entry.pubkey.clone(),
+            relay_url: entry.relay_url.clone(),
+            petname: entry.petname.clone(),
+        }
+    }
+}
+
+impl From<FollowRow> for FollowEntry {
+    fn from(row: FollowRow) -> Self {
+        FollowEntry {
+            pubkey: row.pubkey,
+            relay_url: row.relay_url,
+            petname: row.petname,
+        }
+    }
+}
+
+fn non_empty(s: Option<String>) -> Option<String> {
+    s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+async fn normalize(rows: Vec<FollowRow>) -> Result<Vec<FollowRow>> {
+    let mut out = Vec::with_capacity(rows.len());
+    for row in rows {
+        out.push(FollowRow {
+            pubkey: nip05::resolve_pubkey(&row.pubkey).await?.to_hex(),
+            relay_url: non_empty(row.relay_url),
+            petname: non_empty(row.petname),
+        });
+    }
+    Ok(out)
+}
+
+pub fn diff(current: &[FollowRow], proposed: &[FollowRow]) -> FollowDiff {
+    let before: BTreeMap<&str, &FollowRow> =
+        current.iter().map(|r| (r.pubkey.as_str(), r)).collect();
+    let after: BTreeMap<&str, &FollowRow> =
+        proposed.iter().map(|r| (r.pubkey.as_str(), r)).collect();
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    let mut unchanged = 0;
+    for (pk, new) in &after {
+        match before.get(pk) {
+            None => added.push((*new).clone()),
+            Some(old) if old == new => unchanged += 1,
+            Some(old) => changed.push(FieldChange {
+                pubkey: pk.to_string(),
+                petname: (old.petname != new.petname)
+                    .then(|| (old.petname.clone(), new.petname.clone())),
+                relay_url: (old.relay_url != new.relay_url)
+                    .then(|| (old.relay_url.clone(), new.relay_url.clone())),
+            }),
+        }
+    }
+    let removed = before
+        .iter()
+        .filter(|(pk, _)| !after.contains_key(*pk))
+        .map(|(_, r)| (*r).clone())
+        .collect();
+    FollowDiff {
+        added,
+        removed,
+        changed,
+        unchanged,
+        before: before.len(),
+        after: after.len(),
+        names: BTreeMap::new(),
+    }
+}
+
+/// Fills in `names` from the profile cache. Names are a convenience, so a
+/// lookup that fails leaves them out rather than failing the change.
+async fn name_diff(client: &Client, diff: &mut FollowDiff) {
+    let pubkeys: Vec<PublicKey> = diff
+        .added
+        .iter()
+        .chain(&diff.removed)
+        .map(|r| r.pubkey.as_str())
+        .chain(diff.changed.iter().map(|c| c.pubkey.as_str()))
+        .filter_map(|pk| PublicKey::from_hex(pk).ok())
+        .collect();
+    if let Ok(names) = profiles::display_names(client, pubkeys, NAME_TIMEOUT_SECS).await {
+        diff.names = names.into_iter().map(|(pk, n)| (pk.to_hex(), n)).collect();
+    }
+}
+
+fn merge(current: &[FollowRow], incoming: Vec<FollowRow>, mode: ApplyMode) -> Vec<FollowRow> {
+    let mut merged: BTreeMap<String, FollowRow> = match mode {
+        ApplyMode::Merge => current.iter().map(|r| (r.pubkey.clone(), r.clone())).collect(),
+        ApplyMode::Replace => BTreeMap::new(),
+    };
+    for row in incoming {
+        merged.insert(row.pubkey.clone(), row);
+    }
+    // Keep the existing order for follows that stay, append new ones.
+    let mut out: Vec<FollowRow> = current
+        .iter()
+        .filter_map(|r| merged.remove(&r.pubkey))
+        .collect();
+    out.extend(merged.into_values());
+    out
+}
+
+async fn current_rows(client: &Client, pubkey: &PublicKey) -> Result<Vec<FollowRow>> {
+    Ok(follows::fetch_follows(client, pubkey)
+        .await?
+        .iter()
+        .map(FollowRow::from)
+        .collect())
+}
+
+/// Builds the proposed list from the newest kind 3 and publishes it with
+/// that event as the base, so a list changed elsewhere in between is refused.
+async fn apply_with<F>(
+    client: &Client,
+    dry_run: bool,
+    allow_shrink: bool,
+    force: bool,
+    propose: F,
+) -> Result<FollowsApplyResult>
+where
+    F: FnOnce(&[FollowRow]) -> Vec<FollowRow>,
+{
+    let me = client.signer().await?.get_public_key().await?;
+    let (base, entries) = follows::fetch_contact_list(client, &me).await?;
+    let current: Vec<FollowRow> = entries.iter().map(FollowRow::from).collect();
+    let proposed = propose(&current);
+    let mut diff = diff(&current, &proposed);
+    name_diff(client, &mut diff).await;
+    let published = if dry_run {
+        None
+    } else {
+        let entries: Vec<FollowEntry> = proposed.into_iter().map(FollowEntry::from).collect();
+        Some(follows::publish_follows(client, base.as_ref(), &entries, allow_shrink, force).await?)
+    };
+    Ok(FollowsApplyResult {
+        diff,
+        dry_run,
+        published,
+    })
+}
+
+pub(crate) async fn apply(
+    client: &Client,
+    incoming: Vec<FollowRow>,
+    mode: ApplyMode,
+    dry_run: bool,
+    allow_shrink: bool,
+    force: bool,
+) -> Result<FollowsApplyResult> {
+    let incoming = normalize(incoming).await?;
+    apply_with(client, dry_run, allow_shrink, force, |current| {
+        merge(current, incoming, mode)
+    })
+    .await
+}
+
+pub fn to_csv(rows: &[FollowRow]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["pubkey", "relay_url", "petname"])?;
+    for row in rows {
+        writer.write_record([
+            row.pubkey.as_str(),
+            row.relay_url.as_deref().unwrap_or_default(),
+            row.petname.as_deref().unwrap_or_default(),
+        ])?;
+    }
+    let data = writer.into_inner().map_err(|e| anyhow!(e.to_string()))?;
+    Ok(String::from_utf8(data)?)
+}
+
+pub fn from_csv(data: &str) -> Result<Vec<FollowRow>> {
+    if data.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+    let header: Vec<String> = reader.headers()?.iter().map(|h| h.to_lowercase()).collect();
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let Some(pubkey_col) = column("pubkey") else {
+        bail!("CSV header must contain a pubkey column");
+    };
+    let relay_col = column("relay_url");
+    let petname_col = column("petname");
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let get = |i: Option<usize>| i.and_then(|i| record.get(i)).map(str::to_string);
+        let row = FollowRow {
+            pubkey: get(Some(pubkey_col)).unwrap_or_default(),
+            relay_url: non_empty(get(relay_col)),
+            petname: non_empty(get(petname_col)),
+        };
+        if !row.pubkey.is_empty() {
+            rows.push(row);
+        }
+    }
+    Ok(rows)
+}
+
+/// `nostr_follows_set`: replaces (or merges into) the kind 3 list, with an
+/// optional preview of the diff instead of publishing.
+pub async fn set_follows(client: &Client, args: FollowsSetArgs) -> Result<FollowsApplyResult> {
+    apply(
+        client,
+        args.follows,
+        args.mode.unwrap_or(ApplyMode::Replace),
+        args.dry_run.unwrap_or(false),
+        args.allow_shrink.unwrap_or(false),
+        args.force.unwrap_or(false),
+    )
+    .await
+}
+
+pub async fn add_follow(client: &Client, args: FollowsAddArgs) -> Result<FollowsApplyResult> {
+    let row = FollowRow {
+        pubkey: args.pubkey,
+        relay_url: args.relay_url,
+        petname: args.petname,
+    };
+    apply(
+        client,
+        vec![row],
+        ApplyMode::Merge,
+        args.dry_run.unwrap_or(false),
+        false,
+        args.force.unwrap_or(false),
+    )
+    .await
+}
+
+pub async fn remove_follow(client: &Client, args: FollowsRemoveArgs) -> Result<FollowsApplyResult> {
+    let pubkey = nip05::resolve_pubkey(&args.pubkey).await?.to_hex();
+    apply_with(
+        client,
+        args.dry_run.unwrap_or(false),
+        args.allow_shrink.unwrap_or(false),
+        args.force.unwrap_or(false),
+        |current| current.iter().filter(|r| r.pubkey != pubkey).cloned().collect(),
+    )
+    .await
+}
+
+pub async fn export(client: &Client, args: FollowsExportArgs) -> Result<FollowsExport> {
+    let pubkey = match args.pubkey {
+        Some(pk) => nip05::resolve_pubkey(&pk).await?,
+        None => client.signer().await?.get_public_key().await?,
+    };
+    let rows = current_rows(client, &pubkey).await?;
+    let data = match args.format.unwrap_or_default() {
+        ListFormat::Json => serde_json::to_string_pretty(&rows)?,
+        ListFormat::Csv => to_csv(&rows)?,
+    };
+    Ok(FollowsExport {
+        pubkey: pubkey.to_hex(),
+        count: rows.len(),
+        data,
+    })
+}
+
+pub async fn import(client: &Client, args: FollowsImportArgs) -> Result<FollowsApplyResult> {
+    let rows = match args.format.unwrap_or_default() {
+        ListFormat::Json => serde_json::from_str(&args.data)?,
+        ListFormat::Csv => from_csv(&args.data)?,
+    };
+    apply(
+        client,
+        rows,
+        args.mode.unwrap_or_default(),
+        args.dry_run.unwrap_or(true),
+        args.allow_shrink.unwrap_or(false),
+        args.force.unwrap_or(false),
+    )
+    .await
+}
+
+pub async fn copy(client: &Client, args: FollowsCopyArgs) -> Result<FollowsApplyResult> {
+    let source = nip05::resolve_pubkey(&args.source).await?;
+    let rows = current_rows(client, &source).await?;
+    if rows.is_empty() {
+        bail!("{} has no follow list on the configured relays", source.to_hex());
+    }
+    apply(
+        client,
+        rows,
+        args.mode.unwrap_or_default(),
+        args.dry_run.unwrap_or(true),
+        args.allow_shrink.unwrap_or(false),
+        args.force.unwrap_or(false),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_round_trips_quoted_fields() {
+        let rows = vec![
+            FollowRow {
+                pubkey: "a".repeat(64),
+                relay_url: Some("wss://relay.example.com".to_string()),
+                petname: Some("Smith, \"Bob\"\nsecond line".to_string()),
+            },
+            FollowRow {
+                pubkey: "b".repeat(64),
+                relay_url: None,
+                petname: None,
+            },
+        ];
+        assert_eq!(from_csv(&to_csv(&rows).unwrap()).unwrap(), rows);
+    }
+
+    #[test]
+    fn csv_columns_found_by_header() {
+        let data = "Petname,PUBKEY\n\"multi\nline\",abc\n\n,\n";
+        let rows = from_csv(data).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].pubkey, "abc");
+        assert_eq!(rows[0].petname.as_deref(), Some("multi\nline"));
+        assert!(from_csv("name\nx").is_err());
+        assert!(from_csv("").unwrap().is_empty());
+    }
+}
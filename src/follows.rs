@@ -1,33 +1,90 @@
+use crate::follow_guard;
+use crate::follow_lists::{self, ApplyMode, FollowRow, FollowsApplyResult};
+use crate::relays::{self, SendResult};
 use anyhow::Result;
 
-pub use nostr_mcp_core::follows::{
-    AddFollowArgs, FollowsResult, PublishFollowsResult, RemoveFollowArgs, SetFollowsArgs,
-};
-use nostr_mcp_core::follows as core_follows;
 pub use nostr_mcp_core::settings::FollowEntry;
 use nostr_sdk::prelude::*;
 
+fn entries(event: &Event) -> Vec<FollowEntry> {
+    event
+        .tags
+        .iter()
+        .map(|t| t.as_slice())
+        .filter(|t| t.first().map(String::as_str) == Some("p"))
+        .filter_map(|t| {
+            let pubkey = PublicKey::from_hex(t.get(1)?).ok()?.to_hex();
+            let non_empty = |i: usize| t.get(i).filter(|s| !s.is_empty()).cloned();
+            Some(FollowEntry {
+                pubkey,
+                relay_url: non_empty(2),
+                petname: non_empty(3),
+            })
+        })
+        .collect()
+}
+
+/// The newest kind 3 for `pubkey` and its follows. Pass the event back to
+/// `publish_follows` as the base a new list was built from.
+pub async fn fetch_contact_list(
+    client: &Client,
+    pubkey: &PublicKey,
+) -> Result<(Option<Event>, Vec<FollowEntry>)> {
+    let Some(event) = follow_guard::latest_contact_list(client, pubkey).await? else {
+        return Ok((None, Vec::new()));
+    };
+    follow_guard::observe(client, &event).await?;
+    let follows = entries(&event);
+    Ok((Some(event), follows))
+}
+
 pub async fn fetch_follows(client: &Client, pubkey: &PublicKey) -> Result<Vec<FollowEntry>> {
-    core_follows::fetch_follows(client, pubkey)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+    Ok(fetch_contact_list(client, pubkey).await?.1)
 }
 
+/// Publishes a kind 3, refusing when the relays hold a different, newer
+/// contact list than `base` (the one `follows` was built from), when it
+/// would drop more than half of the current follows unless `allow_shrink` is
+/// set, or when no kind 3 could be fetched unless `force` is set. The signed
+/// event is snapshotted as published.
 pub async fn publish_follows(
     client: &Client,
+    base: Option<&Event>,
     follows: &[FollowEntry],
-) -> Result<PublishFollowsResult> {
-    core_follows::publish_follows(client, follows)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+    allow_shrink: bool,
+    force: bool,
+) -> Result<SendResult> {
+    follow_guard::check_before_publish(client, base, follows.len(), allow_shrink, force).await?;
+    let mut tags = Vec::with_capacity(follows.len());
+    for follow in follows {
+        let mut tag = vec![
+            "p".to_string(),
+            follow.pubkey.clone(),
+            follow.relay_url.clone().unwrap_or_default(),
+            follow.petname.clone().unwrap_or_default(),
+        ];
+        while tag.len() > 2 && tag.last().is_some_and(String::is_empty) {
+            tag.pop();
+        }
+        tags.push(Tag::parse(tag)?);
+    }
+    // Kind 3 content is kept as is; some clients still store relays there.
+    let content = base.map(|b| b.content.clone()).unwrap_or_default();
+    let builder = EventBuilder::new(Kind::ContactList, content).tags(tags);
+    let event = client.sign_event_builder(builder).await?;
+    let result = relays::publish_event(client, &event, None).await?;
+    follow_guard::shared().await?.record(&event, "published").await?;
+    Ok(result)
 }
 
+/// Merges `local_follows` into the newest kind 3 on the relays and publishes
+/// the result through the same guard as `publish_follows`. With `dry_run`
+/// only the diff is returned.
 pub async fn sync_follows(
     client: &Client,
-    pubkey: &PublicKey,
     local_follows: Vec<FollowEntry>,
-) -> Result<(Vec<FollowEntry>, bool)> {
-    core_follows::sync_follows(client, pubkey, local_follows)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+    dry_run: bool,
+) -> Result<FollowsApplyResult> {
+    let rows = local_follows.iter().map(FollowRow::from).collect();
+    follow_lists::apply(client, rows, ApplyMode::Merge, dry_run, false, false).await
 }
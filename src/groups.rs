@@ -0,0 +1,507 @@
+use crate::nip05;
+use crate::nip11;
+use crate::profiles;
+use crate::relays;
+use anyhow::{anyhow, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+const GROUP_METADATA: u16 = 39000;
+const GROUP_ADMINS: u16 = 39001;
+const GROUP_MEMBERS: u16 = 39002;
+const GROUP_ROLES: u16 = 39003;
+const GROUP_CHAT: u16 = 9;
+const GROUP_THREAD: u16 = 11;
+const JOIN_REQUEST: u16 = 9021;
+const GROUP_FLAGS: [&str; 6] = [
+    "public",
+    "private",
+    "open",
+    "closed",
+    "restricted",
+    "hidden",
+];
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GroupsListArgs {
+    /// Relay hosting the groups (wss://...)
+    pub relay: String,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GroupMessagesArgs {
+    /// Relay hosting the group (wss://...)
+    pub relay: String,
+    /// Group ID (the `h` tag)
+    pub group_id: String,
+    /// Event kinds to fetch (default: 9 chat messages and 11 threads)
+    pub kinds: Option<Vec<u16>>,
+    /// Maximum number of events (default: 50)
+    pub limit: Option<usize>,
+    /// Unix timestamp - only events after this time
+    pub since: Option<u64>,
+    /// Unix timestamp - only events before this time
+    pub until: Option<u64>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GroupMembershipArgs {
+    /// Relay hosting the groups (wss://...)
+    pub relay: String,
+    /// Only this group (default: every group on the relay)
+    pub group_id: Option<String>,
+    /// Whose membership to check (hex, npub or NIP-05; default: active key)
+    pub pubkey: Option<String>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupRole {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupAdmin {
+    pub pubkey: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupInfo {
+    pub id: String,
+    pub name: Option<String>,
+    pub about: Option<String>,
+    pub picture: Option<String>,
+    /// Flags such as `public`/`private`, `open`/`closed`, `restricted`, `hidden`
+    pub flags: Vec<String>,
+    pub admins: Vec<GroupAdmin>,
+    /// `None` when the relay does not publish the member list
+    pub member_count: Option<usize>,
+    pub roles: Vec<GroupRole>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupMessage {
+    pub id: String,
+    pub kind: u16,
+    pub pubkey: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    pub created_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub content: String,
+    /// Event this message replies to, from its `q` or `e` tag
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupMembership {
+    pub pubkey: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    pub group_id: String,
+    pub name: Option<String>,
+    /// `None` when the relay does not publish the member list
+    pub member: Option<bool>,
+    pub admin: bool,
+    pub roles: Vec<String>,
+    /// Time of our latest join request (kind 9021), if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub join_requested_at: Option<u64>,
+}
+
+#[derive(Default)]
+struct GroupState {
+    metadata: Option<Event>,
+    admins: Option<Event>,
+    members: Option<Event>,
+    roles: Option<Event>,
+}
+
+fn tag_value(event: &Event, name: &str) -> Option<String> {
+    event
+        .tags
+        .iter()
+        .map(|t| t.as_slice())
+        .find(|t| t.first().map(String::as_str) == Some(name))
+        .and_then(|t| t.get(1).cloned())
+}
+
+fn tag_rows<'a>(event: &'a Event, name: &'a str) -> impl Iterator<Item = &'a [String]> {
+    event
+        .tags
+        .iter()
+        .map(|t| t.as_slice())
+        .filter(move |t| t.first().map(String::as_str) == Some(name))
+}
+
+/// Queries the group relay through a short-lived client, so it does not join
+/// the active client's pool.
+async fn fetch_from(
+    client: &Client,
+    relay: &str,
+    filter: Filter,
+    timeout: u64,
+) -> Result<Vec<Event>> {
+    relays::with_relays(client, &[relay.to_string()], |scoped| async move {
+        let events = scoped
+            .fetch_events(filter, Duration::from_secs(timeout))
+            .await?;
+        Ok(events.into_iter().collect())
+    })
+    .await
+}
+
+/// The key the relay signs group state with, from its NIP-11 `pubkey`.
+async fn relay_pubkey(relay: &str) -> Result<PublicKey> {
+    let pubkey = nip11::relay_info(relay)
+        .await?
+        .pubkey
+        .ok_or_else(|| {
+            anyhow!("{relay} publishes no NIP-11 pubkey, so its group state cannot be verified")
+        })?;
+    Ok(PublicKey::parse(&pubkey)?)
+}
+
+/// Latest 39000-39003 events per group ID signed by the relay itself.
+async fn group_states(
+    client: &Client,
+    relay: &str,
+    group_id: Option<&str>,
+    timeout: u64,
+) -> Result<BTreeMap<String, GroupState>> {
+    let author = relay_pubkey(relay).await?;
+    let mut filter = Filter::new()
+        .kinds([GROUP_METADATA, GROUP_ADMINS, GROUP_MEMBERS, GROUP_ROLES].map(Kind::from))
+        .author(author);
+    if let Some(id) = group_id {
+        filter = filter.identifier(id);
+    }
+    let events = fetch_from(client, relay, filter, timeout).await?;
+    Ok(collect_states(events, &author))
+}
+
+/// Keeps the newest state event of each kind per group. Relays are not
+/// trusted to honour the author filter, so events not signed by `author`
+/// are dropped.
+fn collect_states(events: Vec<Event>, author: &PublicKey) -> BTreeMap<String, GroupState> {
+    let mut groups: BTreeMap<String, GroupState> = BTreeMap::new();
+    for event in events.into_iter().filter(|e| e.pubkey == *author) {
+        let Some(id) = event.tags.identifier().map(str::to_string) else {
+            continue;
+        };
+        let state = groups.entry(id).or_default();
+        let slot = match event.kind.as_u16() {
+            GROUP_METADATA => &mut state.metadata,
+            GROUP_ADMINS => &mut state.admins,
+            GROUP_MEMBERS => &mut state.members,
+            _ => &mut state.roles,
+        };
+        if slot
+            .as_ref()
+            .is_none_or(|e| e.created_at < event.created_at)
+        {
+            *slot = Some(event);
+        }
+    }
+    groups
+}
+
+fn admins(state: &GroupState) -> Vec<GroupAdmin> {
+    state
+        .admins
+        .iter()
+        .flat_map(|e| tag_rows(e, "p"))
+        .filter_map(|row| {
+            Some(GroupAdmin {
+                pubkey: row.get(1)?.clone(),
+                display_name: None,
+                roles: row.iter().skip(2).cloned().collect(),
+            })
+        })
+        .collect()
+}
+
+fn member_keys(state: &GroupState) -> Option<Vec<String>> {
+    state.members.as_ref().map(|e| {
+        tag_rows(e, "p")
+            .filter_map(|row| row.get(1).cloned())
+            .collect()
+    })
+}
+
+fn group_info(id: String, state: &GroupState) -> GroupInfo {
+    let metadata = state.metadata.as_ref();
+    GroupInfo {
+        name: metadata.and_then(|e| tag_value(e, "name")),
+        about: metadata.and_then(|e| tag_value(e, "about")),
+        picture: metadata.and_then(|e| tag_value(e, "picture")),
+        flags: metadata
+            .map(|e| {
+                e.tags
+                    .iter()
+                    .filter_map(|t| t.as_slice().first())
+                    .filter(|name| GROUP_FLAGS.contains(&name.as_str()))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default(),
+        admins: admins(state),
+        member_count: member_keys(state).map(|m| m.len()),
+        roles: state
+            .roles
+            .iter()
+            .flat_map(|e| tag_rows(e, "role"))
+            .filter_map(|row| {
+                Some(GroupRole {
+                    name: row.get(1)?.clone(),
+                    description: row.get(2).cloned(),
+                })
+            })
+            .collect(),
+        id,
+    }
+}
+
+pub async fn list_groups(client: &Client, args: GroupsListArgs) -> Result<Vec<GroupInfo>> {
+    let timeout = args.timeout_secs.unwrap_or(10);
+    let groups = group_states(client, &args.relay, None, timeout).await?;
+    let mut infos: Vec<GroupInfo> = groups
+        .into_iter()
+        .map(|(id, state)| group_info(id, &state))
+        .collect();
+    let admins: Vec<PublicKey> = infos
+        .iter()
+        .flat_map(|g| &g.admins)
+        .filter_map(|a| PublicKey::from_hex(&a.pubkey).ok())
+        .collect();
+    let names = profiles::display_names(client, admins, timeout).await?;
+    for admin in infos.iter_mut().flat_map(|g| &mut g.admins) {
+        admin.display_name = PublicKey::from_hex(&admin.pubkey)
+            .ok()
+            .and_then(|pk| names.get(&pk).cloned());
+    }
+    Ok(infos)
+}
+
+/// Recent chat messages and threads posted to a group, newest first.
+pub async fn group_messages(client: &Client, args: GroupMessagesArgs) -> Result<Vec<GroupMessage>> {
+    let timeout = args.timeout_secs.unwrap_or(10);
+    let kinds = args.kinds.unwrap_or_else(|| vec![GROUP_CHAT, GROUP_THREAD]);
+    let mut filter = Filter::new()
+        .kinds(kinds.into_iter().map(Kind::from))
+        .custom_tag(
+            SingleLetterTag::lowercase(Alphabet::H),
+            args.group_id.clone(),
+        )
+        .limit(args.limit.unwrap_or(50));
+    if let Some(since) = args.since {
+        filter = filter.since(Timestamp::from(since));
+    }
+    if let Some(until) = args.until {
+        filter = filter.until(Timestamp::from(until));
+    }
+    let mut events = fetch_from(client, &args.relay, filter, timeout).await?;
+    events = relays::visible_events(client, events).await;
+    events.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.id.cmp(&b.id)));
+
+    let names: HashMap<PublicKey, String> =
+        profiles::display_names(client, events.iter().map(|e| e.pubkey), timeout).await?;
+    Ok(events
+        .into_iter()
+        .map(|e| GroupMessage {
+            id: e.id.to_hex(),
+            kind: e.kind.as_u16(),
+            pubkey: e.pubkey.to_hex(),
+            author_name: names.get(&e.pubkey).cloned(),
+            created_at: e.created_at.as_secs(),
+            title: tag_value(&e, "title"),
+            reply_to: tag_value(&e, "q").or_else(|| tag_value(&e, "e")),
+            content: e.content,
+        })
+        .collect())
+}
+
+/// Whether a key is a member or admin of the relay's groups, per the
+/// relay-published 39001/39002 lists.
+pub async fn membership(
+    client: &Client,
+    args: GroupMembershipArgs,
+) -> Result<Vec<GroupMembership>> {
+    let timeout = args.timeout_secs.unwrap_or(10);
+    let pubkey = match &args.pubkey {
+        Some(pk) => nip05::resolve_pubkey(pk).await?,
+        None => client.signer().await?.get_public_key().await?,
+    };
+    let hex = pubkey.to_hex();
+    let groups = group_states(client, &args.relay, args.group_id.as_deref(), timeout).await?;
+
+    let mut join_filter = Filter::new().kind(Kind::from(JOIN_REQUEST)).author(pubkey);
+    if let Some(id) = &args.group_id {
+        join_filter = join_filter.custom_tag(SingleLetterTag::lowercase(Alphabet::H), id.clone());
+    }
+    let mut join_requests: HashMap<String, u64> = HashMap::new();
+    for event in fetch_from(client, &args.relay, join_filter, timeout).await? {
+        if let Some(group) = tag_value(&event, "h") {
+            let at = join_requests.entry(group).or_default();
+            *at = (*at).max(event.created_at.as_secs());
+        }
+    }
+
+    let display_name = profiles::display_names(client, [pubkey], timeout)
+        .await?
+        .remove(&pubkey);
+    let mut rows = Vec::new();
+    for (id, state) in &groups {
+        let mut row = membership_row(&hex, id, state, join_requests.get(id).copied());
+        let involved = row.admin || row.member == Some(true) || row.join_requested_at.is_some();
+        if args.group_id.is_none() && !involved {
+            continue;
+        }
+        row.display_name = display_name.clone();
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Membership of the key `hex` in one group. Admins count as members whether
+/// or not the relay's member list names them.
+fn membership_row(
+    hex: &str,
+    id: &str,
+    state: &GroupState,
+    join_requested_at: Option<u64>,
+) -> GroupMembership {
+    let admin = admins(state).into_iter().find(|a| a.pubkey == hex);
+    let member = member_keys(state).map(|m| m.iter().any(|k| k == hex));
+    GroupMembership {
+        pubkey: hex.to_string(),
+        display_name: None,
+        group_id: id.to_string(),
+        name: state.metadata.as_ref().and_then(|e| tag_value(e, "name")),
+        member: if admin.is_some() { Some(true) } else { member },
+        admin: admin.is_some(),
+        roles: admin.map(|a| a.roles).unwrap_or_default(),
+        join_requested_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_event(signer: &Keys, kind: u16, group: &str, at: u64, tags: Vec<Vec<&str>>) -> Event {
+        EventBuilder::new(Kind::from(kind), "")
+            .tag(Tag::identifier(group))
+            .tags(tags.into_iter().map(|t| Tag::parse(t).unwrap()))
+            .custom_created_at(Timestamp::from(at))
+            .sign_with_keys(signer)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_relay_signed_group_state() {
+        let relay = Keys::generate();
+        let forger = Keys::generate();
+        let admin = Keys::generate().public_key().to_hex();
+        let member = Keys::generate().public_key().to_hex();
+        let events = vec![
+            state_event(&relay, GROUP_METADATA, "pizza", 1, vec![vec!["name", "Old name"]]),
+            state_event(
+                &relay,
+                GROUP_METADATA,
+                "pizza",
+                2,
+                vec![
+                    vec!["name", "Pizza Lovers"],
+                    vec!["about", "Slices only"],
+                    vec!["private"],
+                    vec!["closed"],
+                    vec!["unknown-flag"],
+                ],
+            ),
+            state_event(
+                &relay,
+                GROUP_ADMINS,
+                "pizza",
+                2,
+                vec![vec!["p", &admin, "ceo", "moderator"]],
+            ),
+            state_event(
+                &relay,
+                GROUP_MEMBERS,
+                "pizza",
+                2,
+                vec![vec!["p", &admin], vec!["p", &member]],
+            ),
+            state_event(
+                &relay,
+                GROUP_ROLES,
+                "pizza",
+                2,
+                vec![vec!["role", "ceo", "Runs the place"]],
+            ),
+            state_event(&forger, GROUP_METADATA, "pizza", 3, vec![vec!["name", "Hijacked"]]),
+            state_event(&forger, GROUP_METADATA, "forged", 3, vec![vec!["name", "Forged"]]),
+        ];
+
+        let groups = collect_states(events, &relay.public_key());
+        assert_eq!(groups.keys().collect::<Vec<_>>(), ["pizza"]);
+        let info = group_info("pizza".to_string(), &groups["pizza"]);
+        assert_eq!(info.name.as_deref(), Some("Pizza Lovers"));
+        assert_eq!(info.about.as_deref(), Some("Slices only"));
+        assert_eq!(info.flags, ["private", "closed"]);
+        assert_eq!(info.admins.len(), 1);
+        assert_eq!(info.admins[0].pubkey, admin);
+        assert_eq!(info.admins[0].roles, ["ceo", "moderator"]);
+        assert_eq!(info.member_count, Some(2));
+        assert_eq!(info.roles[0].name, "ceo");
+        assert_eq!(info.roles[0].description.as_deref(), Some("Runs the place"));
+    }
+
+    #[test]
+    fn membership_from_admin_and_member_lists() {
+        let relay = Keys::generate();
+        let admin = Keys::generate().public_key().to_hex();
+        let member = Keys::generate().public_key().to_hex();
+        let stranger = Keys::generate().public_key().to_hex();
+        let events = vec![
+            state_event(&relay, GROUP_ADMINS, "listed", 1, vec![vec!["p", &admin, "moderator"]]),
+            state_event(&relay, GROUP_MEMBERS, "listed", 1, vec![vec!["p", &member]]),
+            state_event(&relay, GROUP_ADMINS, "unlisted", 1, vec![vec!["p", &admin]]),
+        ];
+        let groups = collect_states(events, &relay.public_key());
+
+        let row = membership_row(&admin, "listed", &groups["listed"], None);
+        assert!(row.admin);
+        assert_eq!(row.member, Some(true));
+        assert_eq!(row.roles, ["moderator"]);
+
+        let row = membership_row(&member, "listed", &groups["listed"], Some(5));
+        assert!(!row.admin);
+        assert_eq!(row.member, Some(true));
+        assert_eq!(row.join_requested_at, Some(5));
+
+        let row = membership_row(&stranger, "listed", &groups["listed"], None);
+        assert_eq!(row.member, Some(false));
+
+        let row = membership_row(&stranger, "unlisted", &groups["unlisted"], None);
+        assert_eq!(row.member, None);
+        let row = membership_row(&admin, "unlisted", &groups["unlisted"], None);
+        assert_eq!(row.member, Some(true));
+    }
+}
@@ -0,0 +1,42 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const USER_AGENT: &str = concat!("goostr/", env!("CARGO_PKG_VERSION"));
+
+static HTTP: OnceLock<reqwest::Client> = OnceLock::new();
+static NO_REDIRECT: OnceLock<reqwest::Client> = OnceLock::new();
+
+pub fn client() -> &'static reqwest::Client {
+    HTTP.get_or_init(|| {
+        reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(Duration::from_secs(15))
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// Client that never follows redirects, for lookups whose answer must come
+/// from the host that was asked, such as NIP-05 `nostr.json`.
+pub fn no_redirect_client() -> &'static reqwest::Client {
+    NO_REDIRECT.get_or_init(|| {
+        reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(Duration::from_secs(15))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// Everything is fetched over HTTPS. Local hosts may use plain HTTP in tests,
+/// or when `GOOSTR_ALLOW_HTTP_LOCALHOST` is set for a local stand-in server.
+pub fn scheme_for(domain: &str) -> &'static str {
+    let host = domain.split(':').next().unwrap_or(domain);
+    let local = host == "localhost" || host == "127.0.0.1";
+    if local && (cfg!(test) || std::env::var_os("GOOSTR_ALLOW_HTTP_LOCALHOST").is_some()) {
+        "http"
+    } else {
+        "https"
+    }
+}
@@ -1,15 +1,46 @@
+pub mod articles;
+pub mod cashu;
 pub mod cli;
 pub mod config;
+pub mod content;
+pub mod count;
+pub mod deletions;
 pub mod error;
+pub mod feed;
+pub mod follow_guard;
+pub mod follow_lists;
 pub mod follows;
+pub mod groups;
+pub mod http;
 pub mod keys;
+pub mod lists;
 pub mod logging;
 pub mod metadata;
+pub mod mint;
+pub mod nip05;
+pub mod nip11;
 pub mod nostr_client;
+pub mod notifications;
+pub mod nwc;
+pub mod outbox;
+pub mod pagination;
+pub mod pow;
+pub mod profiles;
+pub mod progress;
+pub mod publish_options;
+pub mod rebroadcast;
 pub mod relays;
+pub mod repost;
+pub mod schedule;
+pub mod search;
 pub mod secrets;
 pub mod server;
 pub mod settings;
 pub mod storage;
 pub mod subscriptions;
+pub mod sync;
+#[cfg(test)]
+mod test_relay;
+pub mod thread;
 pub mod util;
+pub mod zaps;
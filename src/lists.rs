@@ -0,0 +1,479 @@
+use crate::error::GoostrError;
+use crate::relays;
+use anyhow::{bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const FETCH_TIMEOUT_SECS: u64 = 10;
+const MUTE_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+
+static MUTES: OnceLock<Mutex<HashMap<PublicKey, (Instant, MuteSet)>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ListKind {
+    /// Kind 10000
+    Mute,
+    /// Kind 10001
+    Pins,
+    /// Kind 10003
+    Bookmarks,
+    /// Kind 10006
+    BlockedRelays,
+    /// Kind 30000, addressed by `identifier`
+    FollowSet,
+    /// Kind 30002, addressed by `identifier`
+    RelaySet,
+}
+
+impl ListKind {
+    pub fn kind(self) -> Kind {
+        Kind::from(match self {
+            ListKind::Mute => 10000,
+            ListKind::Pins => 10001,
+            ListKind::Bookmarks => 10003,
+            ListKind::BlockedRelays => 10006,
+            ListKind::FollowSet => 30000,
+            ListKind::RelaySet => 30002,
+        })
+    }
+
+    fn is_set(self) -> bool {
+        matches!(self, ListKind::FollowSet | ListKind::RelaySet)
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListGetArgs {
+    pub list: ListKind,
+    /// `d` identifier, required for follow and relay sets
+    pub identifier: Option<String>,
+    /// Whose list to read (default: active key; private items only for the active key)
+    pub pubkey: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListEditArgs {
+    pub list: ListKind,
+    /// `d` identifier, required for follow and relay sets
+    pub identifier: Option<String>,
+    /// Tags to add, e.g. `[["p", "<hex>"], ["t", "spam"], ["word", "crypto"]]`
+    pub add: Option<Vec<Vec<String>>>,
+    /// Tags to remove, matched on name and value
+    pub remove: Option<Vec<Vec<String>>>,
+    /// Apply `add` to the private, self-encrypted part of the list (default: false)
+    pub private: Option<bool>,
+    /// Set title for follow and relay sets
+    pub title: Option<String>,
+    /// Publish even if the current list could not be fetched, starting a new one (default: false)
+    pub force: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListSetsArgs {
+    /// `follow_set` or `relay_set`
+    pub list: ListKind,
+    /// Whose sets to list (default: active key)
+    pub pubkey: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ListContents {
+    pub kind: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    pub public: Vec<Vec<String>>,
+    pub private: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListSetRow {
+    pub identifier: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub items: usize,
+    pub created_at: u64,
+}
+
+/// Authors, hashtags, words and threads muted by a key (NIP-51 kind 10000).
+#[derive(Debug, Clone, Default)]
+pub struct MuteSet {
+    pubkeys: HashSet<PublicKey>,
+    hashtags: HashSet<String>,
+    words: Vec<String>,
+    threads: HashSet<EventId>,
+}
+
+impl MuteSet {
+    fn from_items<'a>(items: impl IntoIterator<Item = &'a Vec<String>>) -> Self {
+        let mut set = MuteSet::default();
+        for item in items {
+            let (Some(name), Some(value)) = (item.first(), item.get(1)) else {
+                continue;
+            };
+            match name.as_str() {
+                "p" => set.pubkeys.extend(PublicKey::from_hex(value).ok()),
+                "t" => {
+                    set.hashtags.insert(value.to_lowercase());
+                }
+                "word" => set.words.push(value.to_lowercase()),
+                "e" => set.threads.extend(EventId::from_hex(value).ok()),
+                _ => {}
+            }
+        }
+        set
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pubkeys.is_empty()
+            && self.hashtags.is_empty()
+            && self.words.is_empty()
+            && self.threads.is_empty()
+    }
+
+    pub fn mutes(&self, event: &Event) -> bool {
+        if self.pubkeys.contains(&event.pubkey) || self.threads.contains(&event.id) {
+            return true;
+        }
+        let tags_hit = event.tags.iter().map(|t| t.as_slice()).any(|t| {
+            match (t.first().map(String::as_str), t.get(1)) {
+                (Some("t"), Some(v)) => self.hashtags.contains(&v.to_lowercase()),
+                (Some("e"), Some(v)) => EventId::from_hex(v).is_ok_and(|id| self.threads.contains(&id)),
+                _ => false,
+            }
+        });
+        if tags_hit {
+            return true;
+        }
+        if self.words.is_empty() {
+            return false;
+        }
+        let content = event.content.to_lowercase();
+        self.words.iter().any(|w| contains_word(&content, w))
+    }
+}
+
+/// True when `word` appears in `text` as a whole word (or phrase): muting
+/// "cat" hides "my cat" but not "concatenate".
+fn contains_word(text: &str, word: &str) -> bool {
+    if word.is_empty() {
+        return false;
+    }
+    text.match_indices(word).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + word.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+fn require_identifier(list: ListKind, identifier: Option<String>) -> Result<Option<String>> {
+    match (list.is_set(), identifier) {
+        (true, None) => bail!("identifier is required for follow and relay sets"),
+        (true, Some(d)) => Ok(Some(d)),
+        (false, _) => Ok(None),
+    }
+}
+
+async fn latest_list(
+    client: &Client,
+    author: &PublicKey,
+    list: ListKind,
+    identifier: Option<&str>,
+) -> Result<Option<Event>> {
+    let mut filter = Filter::new().kind(list.kind()).author(*author);
+    if let Some(d) = identifier {
+        filter = filter.identifier(d);
+    }
+    let events = relays::fetch_events(client, filter, FETCH_TIMEOUT_SECS).await?;
+    Ok(events.into_iter().max_by_key(|e| (e.created_at, e.id)))
+}
+
+async fn decrypt_private(client: &Client, event: &Event) -> Result<Vec<Vec<String>>> {
+    if event.content.is_empty() {
+        return Ok(Vec::new());
+    }
+    let signer = client.signer().await?;
+    let me = signer.get_public_key().await?;
+    if event.pubkey != me {
+        return Ok(Vec::new());
+    }
+    let plaintext = match signer.nip44_decrypt(&me, &event.content).await {
+        Ok(p) => p,
+        Err(_) => signer.nip04_decrypt(&me, &event.content).await?,
+    };
+    Ok(serde_json::from_str(&plaintext)?)
+}
+
+fn public_items(event: &Event) -> Vec<Vec<String>> {
+    event
+        .tags
+        .iter()
+        .map(|t| t.as_slice().to_vec())
+        .filter(|t| !matches!(t.first().map(String::as_str), Some("d" | "title")))
+        .collect()
+}
+
+async fn author_or_me(client: &Client, pubkey: Option<String>) -> Result<PublicKey> {
+    match pubkey {
+        Some(pk) => crate::nip05::resolve_pubkey(&pk).await,
+        None => Ok(client.signer().await?.get_public_key().await?),
+    }
+}
+
+pub async fn get_list(client: &Client, args: ListGetArgs) -> Result<ListContents> {
+    let identifier = require_identifier(args.list, args.identifier)?;
+    let author = author_or_me(client, args.pubkey).await?;
+    let Some(event) = latest_list(client, &author, args.list, identifier.as_deref()).await? else {
+        return Ok(ListContents {
+            kind: args.list.kind().as_u16(),
+            identifier,
+            ..Default::default()
+        });
+    };
+    let private = decrypt_private(client, &event).await?;
+    if args.list == ListKind::Mute {
+        remember_mutes(&author, &event, &private).await;
+    }
+    Ok(ListContents {
+        kind: event.kind.as_u16(),
+        identifier,
+        event_id: Some(event.id.to_hex()),
+        created_at: Some(event.created_at.as_secs()),
+        public: public_items(&event),
+        private,
+    })
+}
+
+fn same_item(a: &[String], b: &[String]) -> bool {
+    a.first() == b.first() && a.get(1) == b.get(1)
+}
+
+pub async fn edit_list(client: &Client, args: ListEditArgs) -> Result<relays::SendResult> {
+    let identifier = require_identifier(args.list, args.identifier)?;
+    let signer = client.signer().await?;
+    let me = signer.get_public_key().await?;
+    let existing = latest_list(client, &me, args.list, identifier.as_deref()).await?;
+    // No list and a relay timeout look the same; rebuilding from nothing
+    // would wipe a list that exists but did not arrive in time.
+    if existing.is_none() && !args.force.unwrap_or(false) {
+        return Err(GoostrError::ListUnavailable {
+            kind: args.list.kind().as_u16(),
+        }
+        .into());
+    }
+
+    let (mut public, mut private, mut title) = match &existing {
+        Some(event) => (
+            public_items(event),
+            decrypt_private(client, event).await?,
+            event
+                .tags
+                .iter()
+                .map(|t| t.as_slice())
+                .find(|t| t.first().map(String::as_str) == Some("title"))
+                .and_then(|t| t.get(1).cloned()),
+        ),
+        None => (Vec::new(), Vec::new(), None),
+    };
+
+    for item in args.remove.unwrap_or_default() {
+        public.retain(|t| !same_item(t, &item));
+        private.retain(|t| !same_item(t, &item));
+    }
+    let target = if args.private.unwrap_or(false) {
+        &mut private
+    } else {
+        &mut public
+    };
+    for item in args.add.unwrap_or_default() {
+        if item.len() < 2 {
+            bail!("list items need at least a tag name and a value");
+        }
+        if !target.iter().any(|t| same_item(t, &item)) {
+            target.push(item);
+        }
+    }
+    if args.title.is_some() {
+        title = args.title;
+    }
+
+    let content = if private.is_empty() {
+        String::new()
+    } else {
+        signer
+            .nip44_encrypt(&me, &serde_json::to_string(&private)?)
+            .await?
+    };
+    let mut tags = Vec::new();
+    if let Some(d) = &identifier {
+        tags.push(Tag::identifier(d.clone()));
+        if let Some(title) = &title {
+            tags.push(Tag::parse(["title", title.as_str()])?);
+        }
+    }
+    for item in &public {
+        tags.push(Tag::parse(item.clone())?);
+    }
+    let builder = EventBuilder::new(args.list.kind(), content).tags(tags);
+    let result = relays::publish_event_builder(client, builder, None).await?;
+    if args.list == ListKind::Mute {
+        invalidate_mutes(&me).await;
+    }
+    Ok(result)
+}
+
+pub async fn list_sets(client: &Client, args: ListSetsArgs) -> Result<Vec<ListSetRow>> {
+    if !args.list.is_set() {
+        bail!("only follow_set and relay_set have multiple lists");
+    }
+    let author = author_or_me(client, args.pubkey).await?;
+    let filter = Filter::new().kind(args.list.kind()).author(author);
+    let mut newest: HashMap<String, Event> = HashMap::new();
+    for event in relays::fetch_events(client, filter, FETCH_TIMEOUT_SECS).await? {
+        let Some(d) = event.tags.identifier().map(str::to_string) else {
+            continue;
+        };
+        match newest.get(&d) {
+            Some(existing) if existing.created_at >= event.created_at => {}
+            _ => {
+                newest.insert(d, event);
+            }
+        }
+    }
+    let mut rows: Vec<ListSetRow> = newest
+        .into_iter()
+        .map(|(identifier, event)| ListSetRow {
+            title: event
+                .tags
+                .iter()
+                .map(|t| t.as_slice())
+                .find(|t| t.first().map(String::as_str) == Some("title"))
+                .and_then(|t| t.get(1).cloned()),
+            items: public_items(&event).len(),
+            created_at: event.created_at.as_secs(),
+            identifier,
+        })
+        .collect();
+    rows.sort_by(|a, b| a.identifier.cmp(&b.identifier));
+    Ok(rows)
+}
+
+fn mute_cache() -> &'static Mutex<HashMap<PublicKey, (Instant, MuteSet)>> {
+    MUTES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+async fn remember_mutes(me: &PublicKey, event: &Event, private: &[Vec<String>]) {
+    let public = public_items(event);
+    let set = MuteSet::from_items(public.iter().chain(private.iter()));
+    mute_cache().lock().await.insert(*me, (Instant::now(), set));
+}
+
+async fn invalidate_mutes(me: &PublicKey) {
+    mute_cache().lock().await.remove(me);
+}
+
+pub async fn mute_set(client: &Client) -> Result<(PublicKey, MuteSet)> {
+    let me = client.signer().await?.get_public_key().await?;
+    if let Some((fetched, set)) = mute_cache().lock().await.get(&me) {
+        if fetched.elapsed() < MUTE_CACHE_TTL {
+            return Ok((me, set.clone()));
+        }
+    }
+    let set = match latest_list(client, &me, ListKind::Mute, None).await? {
+        Some(event) => {
+            let private = decrypt_private(client, &event).await.unwrap_or_default();
+            let public = public_items(&event);
+            MuteSet::from_items(public.iter().chain(private.iter()))
+        }
+        None => MuteSet::default(),
+    };
+    mute_cache()
+        .lock()
+        .await
+        .insert(me, (Instant::now(), set.clone()));
+    Ok((me, set))
+}
+
+/// Drops events matched by the active key's mute list. The key's own events
+/// are never hidden. Without a signer, events pass through unchanged.
+pub async fn apply_mutes(client: &Client, events: Vec<Event>) -> Vec<Event> {
+    let Ok((me, set)) = mute_set(client).await else {
+        return events;
+    };
+    if set.is_empty() {
+        return events;
+    }
+    events
+        .into_iter()
+        .filter(|e| e.pubkey == me || !set.mutes(e))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, value: &str) -> Vec<String> {
+        vec![name.to_string(), value.to_string()]
+    }
+
+    fn note(keys: &Keys, content: &str, tags: Vec<Tag>) -> Event {
+        EventBuilder::text_note(content)
+            .tags(tags)
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn mutes_authors_hashtags_and_threads() {
+        let muted = Keys::generate();
+        let other = Keys::generate();
+        let thread = note(&other, "root", vec![]);
+        let items = [
+            item("p", &muted.public_key().to_hex()),
+            item("t", "Spam"),
+            item("e", &thread.id.to_hex()),
+            item("p", "not a key"),
+        ];
+        let set = MuteSet::from_items(items.iter());
+        assert!(!set.is_empty());
+
+        assert!(set.mutes(&note(&muted, "hello", vec![])));
+        assert!(set.mutes(&note(&other, "buy now", vec![Tag::hashtag("SPAM")])));
+        assert!(set.mutes(&thread));
+        let reply = note(&other, "reply", vec![Tag::event(thread.id)]);
+        assert!(set.mutes(&reply));
+        assert!(!set.mutes(&note(&other, "fine", vec![Tag::hashtag("nostr")])));
+        assert!(MuteSet::default().is_empty());
+    }
+
+    #[test]
+    fn word_mutes_respect_word_boundaries() {
+        let keys = Keys::generate();
+        let items = [item("word", "Cat"), item("word", "bad phrase")];
+        let set = MuteSet::from_items(items.iter());
+
+        assert!(set.mutes(&note(&keys, "my CAT sleeps", vec![])));
+        assert!(set.mutes(&note(&keys, "cat.", vec![])));
+        assert!(set.mutes(&note(&keys, "what a bad phrase!", vec![])));
+        assert!(!set.mutes(&note(&keys, "concatenate the strings", vec![])));
+        assert!(!set.mutes(&note(&keys, "cats are fine", vec![])));
+        assert!(!set.mutes(&note(&keys, "a bad phrasebook", vec![])));
+    }
+
+    #[test]
+    fn word_matches_survive_multibyte_neighbours() {
+        assert!(contains_word("é cat é", "cat"));
+        assert!(!contains_word("écat", "cat"));
+        assert!(!contains_word("anything", ""));
+    }
+}
@@ -5,7 +5,7 @@ use tracing::info;
 
 use goostr::{
     cli::{Cli, Command},
-    config, logging, server, util,
+    config, logging, nwc, server, util,
 };
 
 #[tokio::main]
@@ -62,6 +62,28 @@ async fn main() -> Result<()> {
             }
             println!("Config path: {}", config::path().display());
         }
+        Some(Command::NwcApprove { token: None }) => {
+            let pending = nwc::pending_payments().await?;
+            if pending.is_empty() {
+                println!("No payments waiting for approval");
+            }
+            for payment in pending {
+                println!(
+                    "{}  {} sats  {}",
+                    payment.token,
+                    payment.msats / 1_000,
+                    payment.invoice
+                );
+            }
+        }
+        Some(Command::NwcApprove { token: Some(token) }) => {
+            let payment = nwc::approve(&token).await?;
+            println!(
+                "Approved payment of {} sats for {}",
+                payment.msats / 1_000,
+                payment.invoice
+            );
+        }
     }
 
     info!("goostr runtime stopped");
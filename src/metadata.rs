@@ -11,8 +11,8 @@ pub async fn publish_metadata(client: &Client, profile: &ProfileMetadata) -> Res
         .map_err(|e| anyhow::anyhow!(e))
 }
 
+/// Kind 0 for `pubkey`, served from the profile cache and filling it on a miss.
 pub async fn fetch_metadata(client: &Client, pubkey: &PublicKey) -> Result<Option<Metadata>> {
-    core_metadata::fetch_metadata(client, pubkey)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+    let mut found = crate::profiles::resolve_many(client, [*pubkey], 10, false).await?;
+    Ok(found.remove(pubkey).map(|(metadata, _)| metadata))
 }
@@ -0,0 +1,430 @@
+use crate::http;
+use anyhow::{anyhow, bail, Result};
+use nostr::hashes::{sha256::Hash as Sha256Hash, Hash};
+use nostr::secp256k1::{self, Scalar};
+use nostr::util::SECP256K1;
+use nostr_sdk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+const HASH_TO_CURVE_DOMAIN: &[u8] = b"Secp256k1_HashToCurve_Cashu_";
+const UNIT: &str = "sat";
+
+/// A Cashu proof (NUT-00). `witness` carries the NUT-11 signatures needed to
+/// spend P2PK-locked proofs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proof {
+    pub id: String,
+    pub amount: u64,
+    pub secret: String,
+    #[serde(rename = "C")]
+    pub c: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub witness: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlindedMessage {
+    pub amount: u64,
+    pub id: String,
+    #[serde(rename = "B_")]
+    pub b: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlindSignature {
+    pub amount: u64,
+    pub id: String,
+    #[serde(rename = "C_")]
+    pub c: String,
+}
+
+/// The mint's active keyset for sats: one public key per amount.
+#[derive(Debug, Clone)]
+pub struct Keyset {
+    pub id: String,
+    pub keys: BTreeMap<u64, secp256k1::PublicKey>,
+    pub input_fee_ppk: u64,
+}
+
+impl Keyset {
+    /// Fee the mint charges for spending `inputs` proofs (NUT-02).
+    pub fn fee(&self, inputs: usize) -> u64 {
+        (self.input_fee_ppk * inputs as u64).div_ceil(1_000)
+    }
+}
+
+/// Everything the wallet needs from a mint. `HttpMint` talks to a real mint;
+/// tests can supply their own implementation through a `MintConnector`.
+pub trait Mint: Send + Sync {
+    fn url(&self) -> &str;
+
+    fn keyset(&self) -> BoxedFuture<'_, Result<Keyset>>;
+
+    fn swap<'a>(
+        &'a self,
+        inputs: &'a [Proof],
+        outputs: &'a [BlindedMessage],
+    ) -> BoxedFuture<'a, Result<Vec<BlindSignature>>>;
+}
+
+pub trait MintConnector: Send + Sync {
+    fn connect(&self, url: &str) -> Arc<dyn Mint>;
+}
+
+/// Connects to mints over their HTTP API (NUT-01, NUT-02, NUT-03).
+pub struct HttpMints;
+
+impl MintConnector for HttpMints {
+    fn connect(&self, url: &str) -> Arc<dyn Mint> {
+        Arc::new(HttpMint {
+            url: url.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+pub struct HttpMint {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct KeysResponse {
+    keysets: Vec<KeysetKeys>,
+}
+
+#[derive(Deserialize)]
+struct KeysetKeys {
+    id: String,
+    unit: String,
+    keys: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct KeysetsResponse {
+    keysets: Vec<KeysetInfo>,
+}
+
+#[derive(Deserialize)]
+struct KeysetInfo {
+    id: String,
+    unit: String,
+    active: bool,
+    #[serde(default)]
+    input_fee_ppk: u64,
+}
+
+#[derive(Serialize)]
+struct SwapRequest<'a> {
+    inputs: &'a [Proof],
+    outputs: &'a [BlindedMessage],
+}
+
+#[derive(Deserialize)]
+struct SwapResponse {
+    signatures: Vec<BlindSignature>,
+}
+
+impl HttpMint {
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T> {
+        Ok(http::client()
+            .get(format!("{}{path}", self.url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    async fn fetch_keyset(&self) -> Result<Keyset> {
+        let infos: KeysetsResponse = self.get("/v1/keysets").await?;
+        let info = infos
+            .keysets
+            .into_iter()
+            .find(|k| k.active && k.unit == UNIT)
+            .ok_or_else(|| anyhow!("mint {} has no active {UNIT} keyset", self.url))?;
+        let keys: KeysResponse = self.get(&format!("/v1/keys/{}", info.id)).await?;
+        let keyset = keys
+            .keysets
+            .into_iter()
+            .find(|k| k.id == info.id && k.unit == UNIT)
+            .ok_or_else(|| anyhow!("mint {} did not return keyset {}", self.url, info.id))?;
+        let mut parsed = BTreeMap::new();
+        for (amount, key) in keyset.keys {
+            parsed.insert(amount.parse()?, secp256k1::PublicKey::from_slice(&hex::decode(key)?)?);
+        }
+        Ok(Keyset {
+            id: keyset.id,
+            keys: parsed,
+            input_fee_ppk: info.input_fee_ppk,
+        })
+    }
+
+    async fn post_swap(&self, inputs: &[Proof], outputs: &[BlindedMessage]) -> Result<Vec<BlindSignature>> {
+        let response = http::client()
+            .post(format!("{}/v1/swap", self.url))
+            .json(&SwapRequest { inputs, outputs })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!("mint swap failed ({status}): {body}");
+        }
+        Ok(response.json::<SwapResponse>().await?.signatures)
+    }
+}
+
+impl Mint for HttpMint {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn keyset(&self) -> BoxedFuture<'_, Result<Keyset>> {
+        Box::pin(self.fetch_keyset())
+    }
+
+    fn swap<'a>(
+        &'a self,
+        inputs: &'a [Proof],
+        outputs: &'a [BlindedMessage],
+    ) -> BoxedFuture<'a, Result<Vec<BlindSignature>>> {
+        Box::pin(self.post_swap(inputs, outputs))
+    }
+}
+
+fn hash_to_curve(message: &[u8]) -> Result<secp256k1::PublicKey> {
+    let msg_hash = Sha256Hash::hash(&[HASH_TO_CURVE_DOMAIN, message].concat());
+    for counter in 0u32..=u16::MAX as u32 {
+        let mut data = msg_hash.to_byte_array().to_vec();
+        data.extend(counter.to_le_bytes());
+        let mut candidate = [0x02u8; 33];
+        candidate[1..].copy_from_slice(Sha256Hash::hash(&data).as_byte_array());
+        if let Ok(point) = secp256k1::PublicKey::from_slice(&candidate) {
+            return Ok(point);
+        }
+    }
+    bail!("no curve point found for secret")
+}
+
+fn random_scalar() -> Result<secp256k1::SecretKey> {
+    Ok(secp256k1::SecretKey::from_slice(
+        &SecretKey::generate().to_secret_bytes(),
+    )?)
+}
+
+pub fn random_hex() -> String {
+    SecretKey::generate().to_secret_hex()
+}
+
+/// A blinded output waiting for the mint's signature.
+pub struct PendingOutput {
+    pub message: BlindedMessage,
+    secret: String,
+    r: secp256k1::SecretKey,
+}
+
+/// Splits an amount into the power-of-two denominations the keyset supports.
+pub fn split_amount(amount: u64, keyset: &Keyset) -> Result<Vec<u64>> {
+    let mut parts = Vec::new();
+    let mut remaining = amount;
+    for denomination in keyset.keys.keys().rev() {
+        while remaining >= *denomination && *denomination > 0 {
+            parts.push(*denomination);
+            remaining -= denomination;
+        }
+    }
+    if remaining > 0 {
+        bail!("keyset cannot represent {amount} sats");
+    }
+    Ok(parts)
+}
+
+/// Blinds `secret` for an output of `amount` (NUT-00: B_ = Y + rG).
+pub fn blind(secret: String, amount: u64, keyset: &Keyset) -> Result<PendingOutput> {
+    blind_with(secret, amount, keyset, random_scalar()?)
+}
+
+fn blind_with(
+    secret: String,
+    amount: u64,
+    keyset: &Keyset,
+    r: secp256k1::SecretKey,
+) -> Result<PendingOutput> {
+    let y = hash_to_curve(secret.as_bytes())?;
+    let b = y.combine(&secp256k1::PublicKey::from_secret_key(SECP256K1, &r))?;
+    Ok(PendingOutput {
+        message: BlindedMessage {
+            amount,
+            id: keyset.id.clone(),
+            b: hex::encode(b.serialize()),
+        },
+        secret,
+        r,
+    })
+}
+
+/// Unblinds the mint's signatures into proofs (C = C_ - rK).
+pub fn unblind(
+    outputs: Vec<PendingOutput>,
+    signatures: Vec<BlindSignature>,
+    keyset: &Keyset,
+) -> Result<Vec<Proof>> {
+    if outputs.len() != signatures.len() {
+        bail!("mint returned {} signatures for {} outputs", signatures.len(), outputs.len());
+    }
+    outputs
+        .into_iter()
+        .zip(signatures)
+        .map(|(output, signature)| {
+            let k = keyset
+                .keys
+                .get(&signature.amount)
+                .ok_or_else(|| anyhow!("no mint key for amount {}", signature.amount))?;
+            let c_blind = secp256k1::PublicKey::from_slice(&hex::decode(&signature.c)?)?;
+            let rk = k.mul_tweak(SECP256K1, &Scalar::from(output.r))?;
+            let c = c_blind.combine(&rk.negate(SECP256K1))?;
+            Ok(Proof {
+                id: signature.id,
+                amount: signature.amount,
+                secret: output.secret,
+                c: hex::encode(c.serialize()),
+                witness: None,
+            })
+        })
+        .collect()
+}
+
+/// NUT-10/11 secret locking a proof to `pubkey_hex` (compressed, `02...`).
+pub fn p2pk_secret(pubkey_hex: &str) -> String {
+    serde_json::json!([
+        "P2PK",
+        { "nonce": random_hex(), "data": pubkey_hex, "tags": [] }
+    ])
+    .to_string()
+}
+
+/// The compressed pubkey a P2PK secret is locked to, if it is one.
+pub fn p2pk_lock(secret: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(secret).ok()?;
+    let parts = value.as_array()?;
+    if parts.first()?.as_str()? != "P2PK" {
+        return None;
+    }
+    Some(parts.get(1)?.get("data")?.as_str()?.to_lowercase())
+}
+
+/// Adds the NUT-11 witness: a Schnorr signature over SHA256(secret).
+pub fn sign_p2pk(proof: &mut Proof, keys: &Keys) {
+    let digest = Sha256Hash::hash(proof.secret.as_bytes());
+    let message = secp256k1::Message::from_digest(digest.to_byte_array());
+    let signature = keys.sign_schnorr(&message);
+    proof.witness = Some(serde_json::json!({ "signatures": [signature.to_string()] }).to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(hex: &str) -> secp256k1::PublicKey {
+        secp256k1::PublicKey::from_slice(&hex::decode(hex).unwrap()).unwrap()
+    }
+
+    fn scalar(hex: &str) -> secp256k1::SecretKey {
+        secp256k1::SecretKey::from_slice(&hex::decode(hex).unwrap()).unwrap()
+    }
+
+    fn keyset(k: &secp256k1::SecretKey) -> Keyset {
+        Keyset {
+            id: "009a1f293253e41e".to_string(),
+            keys: BTreeMap::from([(1, secp256k1::PublicKey::from_secret_key(SECP256K1, k))]),
+            input_fee_ppk: 0,
+        }
+    }
+
+    // NUT-00 test vectors
+    #[test]
+    fn hash_to_curve_vectors() {
+        for (message, y) in [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "024cce997d3b518f739663b757deaec95bcd9473c30a14ac2fd04023a739d1a725",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "022e7158e11c9506f1aa4248bf531298daa7febd6194f003edcd9b93ade6253acf",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                "026cdbe15362df59cd1dd3c9c11de8aedac2106eca69236ecd9fbe117af897be4f",
+            ),
+        ] {
+            let message = hex::decode(message).unwrap();
+            assert_eq!(hash_to_curve(&message).unwrap(), point(y));
+        }
+    }
+
+    #[test]
+    fn blinded_message_vector() {
+        let r = scalar("0000000000000000000000000000000000000000000000000000000000000001");
+        let output = blind_with("test_message".to_string(), 1, &keyset(&r), r).unwrap();
+        assert_eq!(
+            output.message.b,
+            "025cc16fe33b953e2ace39653efb3e7a7049711ae1d8a2f7a9108753f1cdea742b"
+        );
+    }
+
+    #[test]
+    fn unblinding_vector() {
+        let r = scalar("0000000000000000000000000000000000000000000000000000000000000001");
+        let mut keyset = keyset(&r);
+        keyset.keys.insert(
+            1,
+            point("020000000000000000000000000000000000000000000000000000000000000001"),
+        );
+        let output = blind_with("test_message".to_string(), 1, &keyset, r).unwrap();
+        let signature = BlindSignature {
+            amount: 1,
+            id: keyset.id.clone(),
+            c: "02a9acc1e48c25eeeb9289b5031cc57da9fe72f3fe2861d264bdc074209b107ba2".to_string(),
+        };
+        let proofs = unblind(vec![output], vec![signature], &keyset).unwrap();
+        assert_eq!(
+            proofs[0].c,
+            "03c724d7e6a5443b39ac8acf11f40420adc4f99a02e7cc1b57703d9391f6d129cd"
+        );
+    }
+
+    #[test]
+    fn unblinded_proof_verifies_against_the_mint_key() {
+        let k = scalar("7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f");
+        let keyset = keyset(&k);
+        let output = blind("secret".to_string(), 1, &keyset).unwrap();
+        let b = point(&output.message.b);
+        let c_blind = b.mul_tweak(SECP256K1, &Scalar::from(k)).unwrap();
+        let signature = BlindSignature {
+            amount: 1,
+            id: keyset.id.clone(),
+            c: hex::encode(c_blind.serialize()),
+        };
+        let proof = unblind(vec![output], vec![signature], &keyset).unwrap().remove(0);
+        // C = kY
+        let y = hash_to_curve(b"secret").unwrap();
+        assert_eq!(point(&proof.c), y.mul_tweak(SECP256K1, &Scalar::from(k)).unwrap());
+    }
+
+    #[test]
+    fn splits_into_keyset_denominations() {
+        let k = scalar("0000000000000000000000000000000000000000000000000000000000000001");
+        let mut keyset = keyset(&k);
+        let key = keyset.keys[&1];
+        for amount in [2, 4, 8, 16] {
+            keyset.keys.insert(amount, key);
+        }
+        assert_eq!(split_amount(13, &keyset).unwrap(), [8, 4, 1]);
+        assert_eq!(split_amount(0, &keyset).unwrap(), Vec::<u64>::new());
+        keyset.keys.remove(&1);
+        assert!(split_amount(3, &keyset).is_err());
+        assert_eq!(p2pk_lock(&p2pk_secret("02AB")), Some("02ab".to_string()));
+    }
+}
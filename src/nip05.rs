@@ -0,0 +1,269 @@
+use crate::http;
+use crate::profiles;
+use anyhow::{anyhow, bail, Result};
+use nostr_sdk::prelude::*;
+use rmcp::model::JsonObject;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Argument names that hold a public key in upstream tools. NIP-05
+/// identifiers in these are resolved before the call is forwarded.
+const PUBKEY_FIELDS: &[&str] = &[
+    "pubkey",
+    "npub",
+    "author_npub",
+    "event_pubkey",
+    "reply_to_pubkey",
+    "root_event_pubkey",
+    "parent_event_pubkey",
+    "mentioned_pubkeys",
+    "pubkeys",
+];
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Nip05ResolveArgs {
+    /// NIP-05 identifier, e.g. `alice@example.com` or `example.com` for `_@example.com`
+    pub identifier: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Nip05VerifyArgs {
+    /// Public key to check (hex, npub or NIP-05 identifier)
+    pub pubkey: String,
+    /// Identifier to check against; defaults to the `nip05` claimed in the profile
+    pub identifier: Option<String>,
+    /// Query timeout in seconds for fetching the profile (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Nip05Resolution {
+    pub identifier: String,
+    pub pubkey: String,
+    pub npub: String,
+    pub relays: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Nip05Verification {
+    pub pubkey: String,
+    pub identifier: Option<String>,
+    pub verified: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_pubkey: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NostrJson {
+    #[serde(default)]
+    names: HashMap<String, String>,
+    #[serde(default)]
+    relays: HashMap<String, Vec<String>>,
+}
+
+pub fn looks_like_nip05(input: &str) -> bool {
+    let input = input.trim();
+    input.contains('@') && !input.starts_with("npub") && !input.starts_with("nostr:")
+}
+
+fn split_identifier(identifier: &str) -> Result<(String, String)> {
+    let identifier = identifier.trim().to_lowercase();
+    let (name, domain) = match identifier.split_once('@') {
+        Some((name, domain)) => (name.to_string(), domain.to_string()),
+        None => ("_".to_string(), identifier.clone()),
+    };
+    if domain.is_empty() || domain.contains('/') {
+        bail!("invalid NIP-05 identifier: {identifier}");
+    }
+    Ok((if name.is_empty() { "_".to_string() } else { name }, domain))
+}
+
+fn well_known_url(name: &str, domain: &str) -> Result<reqwest::Url> {
+    let scheme = http::scheme_for(domain);
+    let base = format!("{scheme}://{domain}/.well-known/nostr.json");
+    Ok(reqwest::Url::parse_with_params(&base, [("name", name)])?)
+}
+
+pub async fn resolve(identifier: &str) -> Result<Nip05Resolution> {
+    let (name, domain) = split_identifier(identifier)?;
+    // NIP-05 forbids following redirects: the answer must come from the
+    // domain in the identifier.
+    let response = http::no_redirect_client()
+        .get(well_known_url(&name, &domain)?)
+        .send()
+        .await?;
+    if response.status().is_redirection() {
+        bail!("{domain} answered nostr.json with a redirect, which NIP-05 does not allow");
+    }
+    let doc: NostrJson = response.error_for_status()?.json().await?;
+    let hex = doc
+        .names
+        .get(&name)
+        .ok_or_else(|| anyhow!("{name}@{domain} is not listed in nostr.json"))?;
+    let pubkey = PublicKey::from_hex(hex)?;
+    let relays = doc
+        .relays
+        .get(hex)
+        .cloned()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|r| RelayUrl::parse(r).is_ok())
+        .collect();
+    Ok(Nip05Resolution {
+        identifier: format!("{name}@{domain}"),
+        pubkey: pubkey.to_hex(),
+        npub: pubkey.to_bech32()?,
+        relays,
+    })
+}
+
+/// Parses hex, npub, nprofile or a NIP-05 identifier into a public key.
+pub async fn resolve_pubkey(input: &str) -> Result<PublicKey> {
+    let input = input.trim().trim_start_matches("nostr:");
+    if looks_like_nip05(input) {
+        let resolved = resolve(input).await?;
+        return Ok(PublicKey::from_hex(&resolved.pubkey)?);
+    }
+    if let Ok(Nip19::Profile(profile)) = Nip19::from_bech32(input) {
+        return Ok(profile.public_key);
+    }
+    PublicKey::parse(input).map_err(|e| anyhow!("invalid public key {input}: {e}"))
+}
+
+async fn rewrite_value(value: &mut serde_json::Value) -> Result<()> {
+    match value {
+        serde_json::Value::String(s) if looks_like_nip05(s) => {
+            *s = resolve_pubkey(s).await?.to_hex();
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                if let serde_json::Value::String(s) = item {
+                    if looks_like_nip05(s) {
+                        *s = resolve_pubkey(s).await?.to_hex();
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replaces NIP-05 identifiers in well-known pubkey arguments with hex keys.
+pub async fn resolve_pubkey_args(arguments: &mut JsonObject) -> Result<()> {
+    for field in PUBKEY_FIELDS {
+        if let Some(value) = arguments.get_mut(*field) {
+            rewrite_value(value).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn verify(client: &Client, args: Nip05VerifyArgs) -> Result<Nip05Verification> {
+    let pubkey = resolve_pubkey(&args.pubkey).await?;
+    let identifier = match args.identifier {
+        Some(identifier) => Some(identifier),
+        None => profiles::resolve_many(client, [pubkey], args.timeout_secs.unwrap_or(10), true)
+            .await?
+            .remove(&pubkey)
+            .and_then(|(metadata, _)| metadata.nip05),
+    };
+    let Some(identifier) = identifier else {
+        return Ok(Nip05Verification {
+            pubkey: pubkey.to_hex(),
+            identifier: None,
+            verified: false,
+            resolved_pubkey: None,
+            error: Some("profile does not claim a nip05 identifier".to_string()),
+        });
+    };
+    Ok(match resolve(&identifier).await {
+        Ok(resolution) => Nip05Verification {
+            pubkey: pubkey.to_hex(),
+            verified: resolution.pubkey == pubkey.to_hex(),
+            identifier: Some(resolution.identifier),
+            resolved_pubkey: Some(resolution.pubkey),
+            error: None,
+        },
+        Err(e) => Nip05Verification {
+            pubkey: pubkey.to_hex(),
+            identifier: Some(identifier),
+            verified: false,
+            resolved_pubkey: None,
+            error: Some(e.to_string()),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    const PUBKEY: &str = "b0635d6a9851d3aed0cd6c495b282167acf761729078d975fc341b22650b07b9";
+
+    /// Serves one canned HTTP response and hands back the request line.
+    async fn stand_in(response: String) -> (String, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let n = socket.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_string();
+            let _ = tx.send(request.lines().next().unwrap_or_default().to_string());
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        (addr.to_string(), rx)
+    }
+
+    fn ok_json(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[tokio::test]
+    async fn resolves_against_local_stand_in() {
+        let body = format!(
+            r#"{{"names":{{"bob":"{PUBKEY}"}},"relays":{{"{PUBKEY}":["wss://relay.example.com"]}}}}"#
+        );
+        let (host, request) = stand_in(ok_json(&body)).await;
+        let resolved = resolve(&format!("bob@{host}")).await.unwrap();
+        assert_eq!(resolved.pubkey, PUBKEY);
+        assert_eq!(resolved.relays, vec!["wss://relay.example.com".to_string()]);
+        assert!(request.await.unwrap().contains("/.well-known/nostr.json?name=bob "));
+    }
+
+    #[tokio::test]
+    async fn refuses_redirects() {
+        let (host, _) = stand_in(
+            "HTTP/1.1 302 Found\r\nlocation: https://elsewhere.example/\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                .to_string(),
+        )
+        .await;
+        let err = resolve(&format!("bob@{host}")).await.unwrap_err();
+        assert!(err.to_string().contains("redirect"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn name_is_percent_encoded() {
+        let (host, request) = stand_in(ok_json(r#"{"names":{}}"#)).await;
+        let _ = resolve(&format!("a&b=c#d@{host}")).await;
+        let line = request.await.unwrap();
+        assert!(line.contains("name=a%26b%3Dc%23d "), "{line}");
+    }
+
+    #[test]
+    fn only_local_hosts_may_use_http() {
+        assert_eq!(http::scheme_for("example.com"), "https");
+        assert_eq!(http::scheme_for("localhost:8080"), "http");
+    }
+}
@@ -0,0 +1,65 @@
+use crate::http;
+use anyhow::{anyhow, Result};
+use nostr::nips::nip11::RelayInformationDocument;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+/// Failed lookups are remembered for a shorter time so a relay without a
+/// document is not asked again on every publish.
+const FAILURE_TTL: Duration = Duration::from_secs(10 * 60);
+
+type Lookup = std::result::Result<RelayInformationDocument, String>;
+
+static CACHE: OnceLock<Mutex<HashMap<String, (Instant, Lookup)>>> = OnceLock::new();
+
+fn info_url(relay: &str) -> Result<String> {
+    let url = if let Some(rest) = relay.strip_prefix("wss://") {
+        format!("https://{rest}")
+    } else if let Some(rest) = relay.strip_prefix("ws://") {
+        format!("http://{rest}")
+    } else {
+        return Err(anyhow!("not a websocket relay url: {relay}"));
+    };
+    Ok(url)
+}
+
+async fn fetch_info(relay: &str) -> Result<RelayInformationDocument> {
+    Ok(http::client()
+        .get(info_url(relay)?)
+        .header("Accept", "application/nostr+json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+/// Fetches a relay's NIP-11 information document, cached for an hour
+/// (failures for ten minutes).
+pub async fn relay_info(relay: &str) -> Result<RelayInformationDocument> {
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some((fetched, lookup)) = cache.lock().await.get(relay) {
+        let ttl = if lookup.is_ok() { CACHE_TTL } else { FAILURE_TTL };
+        if fetched.elapsed() < ttl {
+            return lookup.clone().map_err(|e| anyhow!(e));
+        }
+    }
+    let lookup: Lookup = fetch_info(relay).await.map_err(|e| e.to_string());
+    cache
+        .lock()
+        .await
+        .insert(relay.to_string(), (Instant::now(), lookup.clone()));
+    lookup.map_err(|e| anyhow!(e))
+}
+
+pub async fn supports_nip(relay: &str, nip: u16) -> bool {
+    match relay_info(relay).await {
+        Ok(doc) => doc
+            .supported_nips
+            .is_some_and(|nips| nips.contains(&nip)),
+        Err(_) => false,
+    }
+}
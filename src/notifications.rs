@@ -0,0 +1,297 @@
+use crate::profiles;
+use crate::relays;
+use crate::settings;
+use crate::thread;
+use crate::zaps::{self, ZapReceiptRow};
+use anyhow::{bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+const DEFAULT_LOOKBACK_SECS: u64 = 7 * 24 * 60 * 60;
+const DEFAULT_LIMIT: usize = 500;
+const PAGE_SIZE: usize = 250;
+const SNIPPET_CHARS: usize = 140;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NotificationsArgs {
+    /// Only include notifications after this Unix timestamp (default: last seen marker, or 7 days ago)
+    pub since: Option<u64>,
+    /// Maximum number of events to fetch per relay (default: 500)
+    pub limit: Option<usize>,
+    /// Mark everything returned as read; only happens when every notification since `since` was fetched (default: false)
+    pub mark_read: Option<bool>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NotificationsMarkReadArgs {
+    /// Unix timestamp to mark read up to (default: now)
+    pub up_to: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Author {
+    pub pubkey: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TextNotification {
+    pub id: String,
+    pub kind: u16,
+    pub author: Author,
+    pub created_at: u64,
+    pub snippet: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_reply_to: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TargetGroup {
+    pub count: usize,
+    pub authors: Vec<Author>,
+    /// Reaction content (`+`, `-`, emoji) to count
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub reactions: BTreeMap<String, usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zapped_msats: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationDigest {
+    pub since: u64,
+    pub newest: Option<u64>,
+    pub counts: BTreeMap<&'static str, usize>,
+    pub replies: Vec<TextNotification>,
+    pub mentions: Vec<TextNotification>,
+    /// Keyed by the ID of our event that was reacted to, reposted or zapped
+    pub reactions: BTreeMap<String, TargetGroup>,
+    pub reposts: BTreeMap<String, TargetGroup>,
+    pub zaps: BTreeMap<String, TargetGroup>,
+    /// Every relay was paged back to `since`; when false older notifications
+    /// were left out and `mark_read` does not advance the marker
+    pub complete: bool,
+    pub marked_read: bool,
+}
+
+fn snippet(content: &str) -> String {
+    let mut out: String = content.chars().take(SNIPPET_CHARS).collect();
+    if content.chars().count() > SNIPPET_CHARS {
+        out.push('…');
+    }
+    out
+}
+
+fn last_e_tag(event: &Event) -> Option<String> {
+    event
+        .tags
+        .iter()
+        .map(|t| t.as_slice())
+        .filter(|t| t.first().map(String::as_str) == Some("e"))
+        .filter_map(|t| t.get(1).cloned())
+        .last()
+}
+
+/// Pages every relay back to `since`, up to `limit` events each. The flag is
+/// true only when every relay answered and ran dry before the limit.
+async fn fetch_window(
+    client: &Client,
+    filter: &Filter,
+    limit: usize,
+    timeout: u64,
+) -> Result<(Vec<Event>, bool)> {
+    let targets = relays::get_relay_urls(client).await;
+    if targets.is_empty() {
+        bail!("no relays to fetch notifications from");
+    }
+    let timeout = Duration::from_secs(timeout);
+    let page_size = PAGE_SIZE.min(limit).max(1);
+    let mut seen = HashSet::new();
+    let mut events = Vec::new();
+    let mut complete = true;
+    let mut last_error = None;
+    let mut answered = 0;
+    for relay in &targets {
+        match relays::fetch_all_from(client, relay, filter, page_size, limit, timeout).await {
+            Ok((batch, done)) => {
+                answered += 1;
+                complete &= done;
+                events.extend(batch.into_iter().filter(|e| seen.insert(e.id)));
+            }
+            Err(e) => {
+                complete = false;
+                last_error = Some(e);
+            }
+        }
+    }
+    match last_error {
+        Some(e) if answered == 0 => Err(e),
+        _ => Ok((events, complete)),
+    }
+}
+
+pub async fn digest(client: &Client, args: NotificationsArgs) -> Result<NotificationDigest> {
+    let me = client.signer().await?.get_public_key().await?;
+    let store = settings::local().await?;
+    let since = match args.since {
+        Some(since) => since,
+        None => store.get(&me).await.notifications_seen_at.unwrap_or_else(|| {
+            Timestamp::now()
+                .as_secs()
+                .saturating_sub(DEFAULT_LOOKBACK_SECS)
+        }),
+    };
+    let timeout = args.timeout_secs.unwrap_or(10);
+    let limit = args.limit.unwrap_or(DEFAULT_LIMIT).max(1);
+
+    let filter = Filter::new()
+        .pubkey(me)
+        .kinds([
+            Kind::TextNote,
+            Kind::Comment,
+            Kind::Reaction,
+            Kind::Repost,
+            Kind::GenericRepost,
+            Kind::ZapReceipt,
+        ])
+        .since(Timestamp::from(since.saturating_add(1)));
+    let (fetched, complete) = fetch_window(client, &filter, limit, timeout).await?;
+    let newest = fetched.iter().map(|e| e.created_at.as_secs()).max();
+    let mut events: Vec<Event> = relays::visible_events(client, fetched)
+        .await
+        .into_iter()
+        .filter(|e| e.pubkey != me)
+        .collect();
+    events.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+    // Receipts are verified like `nostr_zaps_receipts` does; forged or
+    // mismatched ones are counted but never credited.
+    let mut zap_rows: HashMap<EventId, ZapReceiptRow> = HashMap::new();
+    if events.iter().any(|e| e.kind == Kind::ZapReceipt) {
+        let zapper = zaps::zapper_pubkey(client, &me).await.map_err(|e| e.to_string());
+        for event in events.iter().filter(|e| e.kind == Kind::ZapReceipt) {
+            zap_rows.insert(event.id, zaps::check_receipt(event, Some(&zapper)));
+        }
+    }
+    let zap_sender = |id: &EventId| {
+        zap_rows
+            .get(id)
+            .filter(|row| row.valid)
+            .and_then(|row| row.sender.as_deref())
+            .and_then(|s| PublicKey::from_hex(s).ok())
+    };
+
+    let actors = events.iter().filter_map(|e| match e.kind {
+        Kind::ZapReceipt => zap_sender(&e.id),
+        _ => Some(e.pubkey),
+    });
+    let names = profiles::display_names(client, actors.collect::<Vec<_>>(), timeout).await?;
+    let author = |pk: PublicKey| Author {
+        pubkey: pk.to_hex(),
+        name: names.get(&pk).cloned(),
+    };
+
+    let mut replies = Vec::new();
+    let mut mentions = Vec::new();
+    let mut reactions: BTreeMap<String, TargetGroup> = BTreeMap::new();
+    let mut reposts: BTreeMap<String, TargetGroup> = BTreeMap::new();
+    let mut zaps: BTreeMap<String, TargetGroup> = BTreeMap::new();
+    let mut unverified_zaps = 0;
+
+    for event in &events {
+        match event.kind {
+            Kind::TextNote | Kind::Comment => {
+                let (_, parent) = thread::thread_refs(event);
+                let row = TextNotification {
+                    id: event.id.to_hex(),
+                    kind: event.kind.as_u16(),
+                    author: author(event.pubkey),
+                    created_at: event.created_at.as_secs(),
+                    snippet: snippet(&event.content),
+                    in_reply_to: parent.map(|p| p.to_hex()),
+                };
+                if row.in_reply_to.is_some() {
+                    replies.push(row);
+                } else {
+                    mentions.push(row);
+                }
+            }
+            Kind::Reaction => {
+                let Some(target) = last_e_tag(event) else { continue };
+                let group = reactions.entry(target).or_default();
+                group.count += 1;
+                group.authors.push(author(event.pubkey));
+                let content = if event.content.is_empty() { "+" } else { &event.content };
+                *group.reactions.entry(content.to_string()).or_default() += 1;
+            }
+            Kind::Repost | Kind::GenericRepost => {
+                let Some(target) = last_e_tag(event) else { continue };
+                let group = reposts.entry(target).or_default();
+                group.count += 1;
+                group.authors.push(author(event.pubkey));
+            }
+            Kind::ZapReceipt => {
+                let (Some(zapper), Some(row)) = (zap_sender(&event.id), zap_rows.get(&event.id))
+                else {
+                    unverified_zaps += 1;
+                    continue;
+                };
+                let target = last_e_tag(event).unwrap_or_else(|| "profile".to_string());
+                let group = zaps.entry(target).or_default();
+                group.count += 1;
+                group.authors.push(author(zapper));
+                *group.zapped_msats.get_or_insert(0) += row.amount_msats.unwrap_or(0);
+            }
+            _ => {}
+        }
+    }
+
+    // The marker only moves over a window known to be complete, and never on
+    // an empty one: an empty answer may be a relay that failed to respond.
+    let marked_read = match newest {
+        Some(seen) if args.mark_read.unwrap_or(false) && complete => {
+            store
+                .update(&me, |s| s.notifications_seen_at = Some(seen.max(since)))
+                .await?;
+            true
+        }
+        _ => false,
+    };
+
+    let counts = BTreeMap::from([
+        ("replies", replies.len()),
+        ("mentions", mentions.len()),
+        ("reactions", reactions.values().map(|g| g.count).sum()),
+        ("reposts", reposts.values().map(|g| g.count).sum()),
+        ("zaps", zaps.values().map(|g| g.count).sum()),
+        ("unverified_zaps", unverified_zaps),
+    ]);
+
+    Ok(NotificationDigest {
+        since,
+        newest,
+        counts,
+        replies,
+        mentions,
+        reactions,
+        reposts,
+        zaps,
+        complete,
+        marked_read,
+    })
+}
+
+pub async fn mark_read(client: &Client, args: NotificationsMarkReadArgs) -> Result<u64> {
+    let me = client.signer().await?.get_public_key().await?;
+    let up_to = args.up_to.unwrap_or_else(|| Timestamp::now().as_secs());
+    let state = settings::local()
+        .await?
+        .update(&me, |s| s.notifications_seen_at = Some(up_to))
+        .await?;
+    Ok(state.notifications_seen_at.unwrap_or(up_to))
+}
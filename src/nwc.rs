@@ -0,0 +1,926 @@
+use crate::{secrets, storage, util, zaps};
+use anyhow::{anyhow, bail, Result};
+use nostr::nips::nip47::{
+    ListTransactionsRequest, LookupInvoiceResponse, MakeInvoiceRequest, MakeInvoiceResponse,
+    NostrWalletConnectURI, PayInvoiceRequest, PayInvoiceResponse, Request, Response,
+    TransactionType,
+};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, OnceCell};
+use tracing::warn;
+
+const NWC_SECRET_LABEL: &str = "nwc-uri";
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+/// How long a payment preview's confirmation token stays usable.
+const PREVIEW_TTL_SECS: u64 = 10 * 60;
+const DAILY_CAP_ENV: &str = "GOOSTR_NWC_DAILY_CAP_SATS";
+const REQUIRE_CONFIRMATION_ENV: &str = "GOOSTR_NWC_REQUIRE_CONFIRMATION";
+
+static POLICY: OnceCell<Arc<WalletPolicyStore>> = OnceCell::const_new();
+static APPROVALS: OnceCell<Arc<ApprovalStore>> = OnceCell::const_new();
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NwcConnectArgs {
+    /// `nostr+walletconnect://...` connection string from the wallet service
+    pub uri: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NwcPolicyArgs {
+    /// Lower the daily cap until goostr restarts; it can never be raised above the operator's cap
+    pub daily_cap_sats: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NwcPayInvoiceArgs {
+    /// BOLT11 invoice
+    pub invoice: String,
+    /// Amount in sats, required only for invoices without an amount
+    pub amount_sats: Option<u64>,
+    /// Token from a preview of this same invoice, once the operator has approved it with `goostr nwc-approve`
+    pub confirmation_token: Option<String>,
+    /// Seconds to wait for the wallet service (default: 60)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NwcMakeInvoiceArgs {
+    /// Amount in sats
+    pub amount_sats: u64,
+    pub description: Option<String>,
+    /// Invoice expiry in seconds
+    pub expiry_secs: Option<u64>,
+    /// Seconds to wait for the wallet service (default: 30)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NwcBalanceArgs {
+    /// Seconds to wait for the wallet service (default: 30)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NwcTransactionType {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NwcListTransactionsArgs {
+    /// Unix timestamp - only transactions after this time
+    pub from: Option<u64>,
+    /// Unix timestamp - only transactions before this time
+    pub until: Option<u64>,
+    /// Maximum number of transactions (default: 20)
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+    /// Include unpaid invoices (default: false)
+    pub unpaid: Option<bool>,
+    /// Only incoming or only outgoing transactions
+    #[serde(rename = "type")]
+    pub transaction_type: Option<NwcTransactionType>,
+    /// Seconds to wait for the wallet service (default: 30)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NwcConnection {
+    pub wallet_pubkey: String,
+    pub relays: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lud16: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Spend {
+    at: u64,
+    msats: u64,
+    invoice: String,
+    /// Reserved before the request went out and not yet confirmed by the
+    /// wallet; still counts against the cap.
+    #[serde(default)]
+    unconfirmed: bool,
+}
+
+/// Limits set by whoever runs goostr, through its environment (e.g. `goostr
+/// install --env`). Nothing reachable over MCP can raise the cap or turn
+/// confirmation off.
+#[derive(Debug, Clone, Copy)]
+pub struct OperatorPolicy {
+    pub daily_cap_msats: Option<u64>,
+    pub require_confirmation: bool,
+}
+
+impl OperatorPolicy {
+    pub fn from_env() -> Self {
+        let cap = std::env::var(DAILY_CAP_ENV).ok();
+        let confirm = std::env::var(REQUIRE_CONFIRMATION_ENV).ok();
+        Self::parse(cap.as_deref(), confirm.as_deref())
+    }
+
+    /// An unparsable cap counts as no cap, so payments stay blocked.
+    fn parse(cap: Option<&str>, require_confirmation: Option<&str>) -> Self {
+        Self {
+            daily_cap_msats: cap
+                .and_then(|c| c.trim().parse::<u64>().ok())
+                .map(|sats| sats.saturating_mul(1_000)),
+            require_confirmation: !matches!(
+                require_confirmation.map(|v| v.trim().to_lowercase()).as_deref(),
+                Some("0" | "false" | "no" | "off")
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct WalletPolicy {
+    spends: Vec<Spend>,
+    /// Cap lowered through `nostr_nwc_policy`; kept in memory only, so a
+    /// restart goes back to the operator's cap.
+    #[serde(skip)]
+    lowered_cap_msats: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PolicyView {
+    pub daily_cap_sats: Option<u64>,
+    /// Cap set by the operator through `GOOSTR_NWC_DAILY_CAP_SATS`
+    pub operator_cap_sats: Option<u64>,
+    pub require_confirmation: bool,
+    pub spent_today_sats: u64,
+    /// Part of today's spending the wallet never confirmed (timeouts)
+    pub unconfirmed_today_sats: u64,
+}
+
+/// A payment preview waiting for the operator. Written to its own file so
+/// `goostr nwc-approve`, run from a terminal, can approve it while the
+/// server is running.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingPayment {
+    pub token: String,
+    pub invoice: String,
+    pub msats: u64,
+    pub expires_at: u64,
+    pub approved: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Approvals {
+    pending: Vec<PendingPayment>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum PayInvoiceOutcome {
+    /// Nothing was paid. Once the operator runs `goostr nwc-approve <token>`,
+    /// repeat the call with `confirmation_token` to pay.
+    ConfirmationRequired {
+        confirmation_token: String,
+        expires_at: u64,
+        amount_sats: u64,
+        spent_today_sats: u64,
+        daily_cap_sats: Option<u64>,
+    },
+    Paid {
+        amount_sats: u64,
+        #[serde(flatten)]
+        response: PayInvoiceResponse,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct BalanceResult {
+    pub balance_sats: u64,
+    pub balance_msats: u64,
+}
+
+fn start_of_day(now: u64) -> u64 {
+    now - now % SECONDS_PER_DAY
+}
+
+impl WalletPolicy {
+    fn spent_today(&self, now: u64) -> u64 {
+        let since = start_of_day(now);
+        self.spends
+            .iter()
+            .filter(|s| s.at >= since)
+            .map(|s| s.msats)
+            .sum()
+    }
+
+    /// The operator's cap, or the lowered one when that is smaller.
+    fn cap(&self, operator: &OperatorPolicy) -> Option<u64> {
+        match (operator.daily_cap_msats, self.lowered_cap_msats) {
+            (Some(cap), Some(lowered)) => Some(cap.min(lowered)),
+            (cap, _) => cap,
+        }
+    }
+
+    /// Lowers the cap for the rest of this run; raising it is refused.
+    fn lower_cap(&mut self, operator: &OperatorPolicy, msats: u64) -> Result<()> {
+        match self.cap(operator) {
+            None => bail!("no daily cap is configured; the operator sets {DAILY_CAP_ENV}"),
+            Some(cap) if msats > cap => bail!(
+                "the daily cap can only be lowered here (currently {} sats); the operator raises it with {DAILY_CAP_ENV}",
+                cap / 1_000
+            ),
+            Some(_) => self.lowered_cap_msats = Some(msats),
+        }
+        Ok(())
+    }
+
+    fn view(&self, operator: &OperatorPolicy) -> PolicyView {
+        let now = Timestamp::now().as_secs();
+        let since = start_of_day(now);
+        let unconfirmed: u64 = self
+            .spends
+            .iter()
+            .filter(|s| s.unconfirmed && s.at >= since)
+            .map(|s| s.msats)
+            .sum();
+        PolicyView {
+            daily_cap_sats: self.cap(operator).map(|m| m / 1_000),
+            operator_cap_sats: operator.daily_cap_msats.map(|m| m / 1_000),
+            require_confirmation: operator.require_confirmation,
+            spent_today_sats: self.spent_today(now) / 1_000,
+            unconfirmed_today_sats: unconfirmed / 1_000,
+        }
+    }
+
+    /// Refuses a payment that is not covered by a daily cap, would exceed
+    /// it, or repeats an invoice already paid or in flight today.
+    fn check(&self, operator: &OperatorPolicy, invoice: &str, msats: u64, now: u64) -> Result<u64> {
+        let cap = self.cap(operator).ok_or_else(|| {
+            anyhow!("no daily cap is configured; the operator sets {DAILY_CAP_ENV} before anything is paid")
+        })?;
+        let since = start_of_day(now);
+        if self.spends.iter().any(|s| s.invoice == invoice && s.at >= since) {
+            bail!("this invoice was already paid (or is awaiting the wallet) today");
+        }
+        let spent = self.spent_today(now);
+        if spent.saturating_add(msats) > cap {
+            bail!(
+                "payment of {} sats would exceed the daily cap of {} sats ({} already spent today)",
+                msats / 1_000,
+                cap / 1_000,
+                spent / 1_000
+            );
+        }
+        Ok(spent)
+    }
+
+    /// Records the payment before the request goes out, so a wallet that
+    /// pays but never answers still counts against the cap.
+    fn reserve(&mut self, invoice: &str, msats: u64, now: u64) {
+        self.spends.retain(|s| s.at >= start_of_day(now));
+        self.spends.push(Spend {
+            at: now,
+            msats,
+            invoice: invoice.to_string(),
+            unconfirmed: true,
+        });
+    }
+
+    fn settle(&mut self, invoice: &str, at: u64) {
+        if let Some(spend) = self
+            .spends
+            .iter_mut()
+            .find(|s| s.invoice == invoice && s.at == at)
+        {
+            spend.unconfirmed = false;
+        }
+    }
+
+    fn release(&mut self, invoice: &str, at: u64) {
+        self.spends.retain(|s| !(s.invoice == invoice && s.at == at));
+    }
+}
+
+impl Approvals {
+    fn issue(&mut self, invoice: &str, msats: u64, now: u64) -> (String, u64) {
+        self.pending.retain(|p| p.expires_at > now);
+        let token = SecretKey::generate().to_secret_hex();
+        let expires_at = now + PREVIEW_TTL_SECS;
+        self.pending.push(PendingPayment {
+            token: token.clone(),
+            invoice: invoice.to_string(),
+            msats,
+            expires_at,
+            approved: false,
+        });
+        (token, expires_at)
+    }
+
+    fn approve(&mut self, token: &str, now: u64) -> Result<PendingPayment> {
+        let pending = self
+            .pending
+            .iter_mut()
+            .find(|p| p.token == token && p.expires_at > now)
+            .ok_or_else(|| anyhow!("no pending payment with that token (or it expired)"))?;
+        pending.approved = true;
+        Ok(pending.clone())
+    }
+
+    /// Consumes a confirmation token, which must have been approved by the
+    /// operator and come from a preview of the same invoice and amount. A
+    /// token is spent by any attempt to use it.
+    fn redeem(&mut self, token: &str, invoice: &str, msats: u64, now: u64) -> Result<()> {
+        let index = self
+            .pending
+            .iter()
+            .position(|p| p.token == token)
+            .ok_or_else(|| anyhow!("unknown or expired confirmation_token; preview the payment again"))?;
+        if !self.pending[index].approved && self.pending[index].expires_at > now {
+            bail!("payment not approved yet; the operator runs `goostr nwc-approve {token}`");
+        }
+        let pending = self.pending.remove(index);
+        if pending.expires_at <= now {
+            bail!("confirmation_token expired; preview the payment again");
+        }
+        if pending.invoice != invoice || pending.msats != msats {
+            bail!("confirmation_token was issued for a different invoice or amount");
+        }
+        Ok(())
+    }
+}
+
+/// Pending payment previews, shared between the server and `goostr
+/// nwc-approve`. Every change re-reads the file so neither process works on
+/// a stale copy.
+pub struct ApprovalStore {
+    path: PathBuf,
+    pass: Vec<u8>,
+    lock: Mutex<()>,
+}
+
+impl ApprovalStore {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        Ok(Self::open(path, util::ensure_keystore_secret()?))
+    }
+
+    fn open(path: PathBuf, pass: Vec<u8>) -> Self {
+        Self {
+            path,
+            pass,
+            lock: Mutex::new(()),
+        }
+    }
+
+    async fn update<T>(&self, f: impl FnOnce(&mut Approvals) -> Result<T>) -> Result<T> {
+        let _guard = self.lock.lock().await;
+        let mut approvals: Approvals = if self.path.exists() {
+            storage::decrypt_from_file(&self.path, &self.pass)?
+        } else {
+            Approvals::default()
+        };
+        let result = f(&mut approvals);
+        util::ensure_parent_dir(&self.path)?;
+        storage::encrypt_to_file(&self.path, &self.pass, &approvals)?;
+        result
+    }
+}
+
+pub async fn shared_approvals() -> Result<Arc<ApprovalStore>> {
+    APPROVALS
+        .get_or_try_init(|| async {
+            ApprovalStore::load(util::nostr_wallet_approvals_path()).map(Arc::new)
+        })
+        .await
+        .cloned()
+}
+
+/// Payment previews waiting for approval, for `goostr nwc-approve`.
+pub async fn pending_payments() -> Result<Vec<PendingPayment>> {
+    let now = Timestamp::now().as_secs();
+    shared_approvals()
+        .await?
+        .update(|approvals| {
+            approvals.pending.retain(|p| p.expires_at > now);
+            Ok(approvals.pending.clone())
+        })
+        .await
+}
+
+/// Approves a previewed payment so the next `nostr_nwc_pay_invoice` call
+/// with its token pays it. Only reachable from the command line.
+pub async fn approve(token: &str) -> Result<PendingPayment> {
+    let now = Timestamp::now().as_secs();
+    shared_approvals()
+        .await?
+        .update(|approvals| approvals.approve(token.trim(), now))
+        .await
+}
+
+/// Spending policy and the ledger of payments made through NWC. Each
+/// payment is reserved in the ledger under the state lock before it is
+/// sent, so two calls cannot both fit under the daily cap.
+pub struct WalletPolicyStore {
+    path: PathBuf,
+    pass: Vec<u8>,
+    operator: OperatorPolicy,
+    state: Mutex<WalletPolicy>,
+}
+
+impl WalletPolicyStore {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        Self::open(path, util::ensure_keystore_secret()?, OperatorPolicy::from_env())
+    }
+
+    fn open(path: PathBuf, pass: Vec<u8>, operator: OperatorPolicy) -> Result<Self> {
+        let state = if path.exists() {
+            storage::decrypt_from_file(&path, &pass)?
+        } else {
+            WalletPolicy::default()
+        };
+        Ok(Self {
+            path,
+            pass,
+            operator,
+            state: Mutex::new(state),
+        })
+    }
+
+    fn persist(&self, state: &WalletPolicy) -> Result<()> {
+        util::ensure_parent_dir(&self.path)?;
+        storage::encrypt_to_file(&self.path, &self.pass, state)
+    }
+}
+
+pub async fn shared_policy() -> Result<Arc<WalletPolicyStore>> {
+    POLICY
+        .get_or_try_init(|| async {
+            WalletPolicyStore::load(util::nostr_wallet_policy_path()).map(Arc::new)
+        })
+        .await
+        .cloned()
+}
+
+fn connection_view(uri: &NostrWalletConnectURI) -> NwcConnection {
+    NwcConnection {
+        wallet_pubkey: uri.public_key.to_hex(),
+        relays: uri.relays.iter().map(|r| r.to_string()).collect(),
+        lud16: uri.lud16.clone(),
+    }
+}
+
+pub fn connect(args: NwcConnectArgs) -> Result<NwcConnection> {
+    let uri = NostrWalletConnectURI::parse(args.uri.trim())?;
+    secrets::set(NWC_SECRET_LABEL, &uri.to_string())?;
+    Ok(connection_view(&uri))
+}
+
+pub fn disconnect() -> Result<()> {
+    secrets::delete(NWC_SECRET_LABEL)
+}
+
+fn stored_uri() -> Result<NostrWalletConnectURI> {
+    let uri = secrets::get(NWC_SECRET_LABEL)?
+        .ok_or_else(|| anyhow!("no wallet connected; use nostr_nwc_connect first"))?;
+    Ok(NostrWalletConnectURI::parse(&uri)?)
+}
+
+pub fn status() -> Result<Option<NwcConnection>> {
+    match secrets::get(NWC_SECRET_LABEL)? {
+        Some(uri) => Ok(Some(connection_view(&NostrWalletConnectURI::parse(&uri)?))),
+        None => Ok(None),
+    }
+}
+
+/// Sends one NIP-47 request over the wallet's relays with the connection's
+/// own key and waits for the matching kind 23195 response.
+async fn call(uri: &NostrWalletConnectURI, request: Request, timeout_secs: u64) -> Result<Response> {
+    let event = request.to_event(uri)?;
+    let client = Client::new(Keys::new(uri.secret.clone()));
+    for relay in &uri.relays {
+        client.add_relay(relay.clone()).await?;
+    }
+    client.connect().await;
+
+    let filter = Filter::new()
+        .kind(Kind::WalletConnectResponse)
+        .author(uri.public_key)
+        .event(event.id);
+    let mut notifications = client.notifications();
+    client.subscribe(filter, None).await?;
+    client.send_event(&event).await?;
+
+    let wait = async {
+        while let Ok(notification) = notifications.recv().await {
+            if let RelayPoolNotification::Event { event: reply, .. } = notification {
+                let answers = reply.tags.event_ids().any(|id| *id == event.id);
+                if reply.kind == Kind::WalletConnectResponse && answers {
+                    return Ok(Response::from_event(uri, &reply)?);
+                }
+            }
+        }
+        bail!("wallet relay connection closed")
+    };
+    let response = tokio::time::timeout(Duration::from_secs(timeout_secs), wait).await;
+    client.shutdown().await;
+    response.map_err(|_| anyhow!("wallet did not respond within {timeout_secs}s"))?
+}
+
+pub async fn policy(args: NwcPolicyArgs) -> Result<PolicyView> {
+    let store = shared_policy().await?;
+    let mut state = store.state.lock().await;
+    if let Some(cap) = args.daily_cap_sats {
+        state.lower_cap(&store.operator, cap.saturating_mul(1_000))?;
+    }
+    Ok(state.view(&store.operator))
+}
+
+pub async fn pay_invoice(args: NwcPayInvoiceArgs) -> Result<PayInvoiceOutcome> {
+    let uri = stored_uri()?;
+    pay(&uri, &*shared_policy().await?, &*shared_approvals().await?, args).await
+}
+
+async fn pay(
+    uri: &NostrWalletConnectURI,
+    store: &WalletPolicyStore,
+    approvals: &ApprovalStore,
+    args: NwcPayInvoiceArgs,
+) -> Result<PayInvoiceOutcome> {
+    let invoice = args.invoice.trim().trim_start_matches("lightning:").to_string();
+    let (msats, amount_override) = match (zaps::invoice_amount_msats(&invoice)?, args.amount_sats) {
+        (Some(msats), None) => (msats, None),
+        (Some(msats), Some(sats)) if msats == sats.saturating_mul(1_000) => (msats, None),
+        (Some(_), Some(_)) => bail!("amount_sats does not match the invoice amount"),
+        (None, Some(sats)) => {
+            let msats = sats.saturating_mul(1_000);
+            (msats, Some(msats))
+        }
+        (None, None) => bail!("invoice has no amount; pass amount_sats"),
+    };
+
+    let now = Timestamp::now().as_secs();
+    {
+        let mut state = store.state.lock().await;
+        let spent = state.check(&store.operator, &invoice, msats, now)?;
+        if store.operator.require_confirmation {
+            match args.confirmation_token.as_deref() {
+                Some(token) => {
+                    approvals
+                        .update(|a| a.redeem(token, &invoice, msats, now))
+                        .await?
+                }
+                None => {
+                    let (confirmation_token, expires_at) =
+                        approvals.update(|a| Ok(a.issue(&invoice, msats, now))).await?;
+                    return Ok(PayInvoiceOutcome::ConfirmationRequired {
+                        confirmation_token,
+                        expires_at,
+                        amount_sats: msats / 1_000,
+                        spent_today_sats: spent / 1_000,
+                        daily_cap_sats: state.cap(&store.operator).map(|m| m / 1_000),
+                    });
+                }
+            }
+        }
+        state.reserve(&invoice, msats, now);
+        store.persist(&state)?;
+    }
+
+    let request = Request::pay_invoice(PayInvoiceRequest {
+        id: None,
+        invoice: invoice.clone(),
+        amount: amount_override,
+    });
+    // Only an explicit error response from the wallet releases the
+    // reservation; after a timeout the payment may still have gone through.
+    let response = call(uri, request, args.timeout_secs.unwrap_or(60))
+        .await
+        .map_err(|e| anyhow!("{e}; the payment stays reserved against today's cap"))?;
+    let mut state = store.state.lock().await;
+    if let Some(error) = &response.error {
+        state.release(&invoice, now);
+        if let Err(e) = store.persist(&state) {
+            warn!(error = %e, "failed to release refused NWC payment");
+        }
+        bail!("wallet refused payment ({:?}): {}", error.code, error.message);
+    }
+    let response = response
+        .to_pay_invoice()
+        .map_err(|e| anyhow!("unexpected wallet response: {e}"))?;
+    state.settle(&invoice, now);
+    if let Err(e) = store.persist(&state) {
+        warn!(error = %e, "payment succeeded but the spending ledger could not be saved");
+    }
+    Ok(PayInvoiceOutcome::Paid {
+        amount_sats: msats / 1_000,
+        response,
+    })
+}
+
+pub async fn make_invoice(args: NwcMakeInvoiceArgs) -> Result<MakeInvoiceResponse> {
+    let uri = stored_uri()?;
+    let request = Request::make_invoice(MakeInvoiceRequest {
+        amount: args.amount_sats.saturating_mul(1_000),
+        description: args.description,
+        description_hash: None,
+        expiry: args.expiry_secs,
+    });
+    call(&uri, request, args.timeout_secs.unwrap_or(30))
+        .await?
+        .to_make_invoice()
+        .map_err(|e| anyhow!("wallet error: {e}"))
+}
+
+pub async fn get_balance(args: NwcBalanceArgs) -> Result<BalanceResult> {
+    let uri = stored_uri()?;
+    let balance = call(&uri, Request::get_balance(), args.timeout_secs.unwrap_or(30))
+        .await?
+        .to_get_balance()
+        .map_err(|e| anyhow!("wallet error: {e}"))?;
+    Ok(BalanceResult {
+        balance_sats: balance.balance / 1_000,
+        balance_msats: balance.balance,
+    })
+}
+
+pub async fn list_transactions(args: NwcListTransactionsArgs) -> Result<Vec<LookupInvoiceResponse>> {
+    let uri = stored_uri()?;
+    let request = Request::list_transactions(ListTransactionsRequest {
+        from: args.from.map(Timestamp::from),
+        until: args.until.map(Timestamp::from),
+        limit: Some(args.limit.unwrap_or(20)),
+        offset: args.offset,
+        unpaid: args.unpaid,
+        transaction_type: args.transaction_type.map(|t| match t {
+            NwcTransactionType::Incoming => TransactionType::Incoming,
+            NwcTransactionType::Outgoing => TransactionType::Outgoing,
+        }),
+    });
+    call(&uri, request, args.timeout_secs.unwrap_or(30))
+        .await?
+        .to_list_transactions()
+        .map_err(|e| anyhow!("wallet error: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_relay::TestRelay;
+    use nostr::nips::nip04;
+    use std::sync::Mutex as StdMutex;
+
+    const INVOICE: &str = "lnbc2500u1...";
+    const NOW: u64 = 1_700_000_000;
+    // BOLT11 spec vectors (250,000 and 2,000,000 sats)
+    const INVOICE_2500U: &str = "lnbc2500u1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpu9qrsgquk0rl77nj30yxdy8j9vdx85fkpmdla2087ne0xh8nhedh8w27kyke0lp53ut353s06fv3qfegext0eh0ymjpf39tuven09sam30g4vgpfna3rh";
+    const INVOICE_20M: &str = "lnbc20m1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqhp58yjmdan79s6qqdhdzgynm4zwqd5d7xmw5fk98klysy043l2ahrqs9qrsgq7ea976txfraylvgzuxs8kgcw23ezlrszfnh8r6qtfpr6cxga50aj6txm9rxrydzd06dfeawfk6swupvz4erwnyutnjq7x39ymw6j38gp7ynn44";
+
+    fn operator(cap_sats: Option<u64>, require_confirmation: bool) -> OperatorPolicy {
+        OperatorPolicy {
+            daily_cap_msats: cap_sats.map(|sats| sats * 1_000),
+            require_confirmation,
+        }
+    }
+
+    #[test]
+    fn operator_policy_from_environment_values() {
+        let policy = OperatorPolicy::parse(Some("5000"), None);
+        assert_eq!(policy.daily_cap_msats, Some(5_000_000));
+        assert!(policy.require_confirmation);
+        let policy = OperatorPolicy::parse(Some("lots"), Some("false"));
+        assert_eq!(policy.daily_cap_msats, None);
+        assert!(!policy.require_confirmation);
+        assert!(OperatorPolicy::parse(None, Some("yes")).require_confirmation);
+    }
+
+    #[test]
+    fn payments_need_an_operator_cap() {
+        let policy = WalletPolicy::default();
+        assert!(policy.check(&operator(None, true), INVOICE, 1_000, NOW).is_err());
+        assert!(policy.check(&operator(Some(0), true), INVOICE, 1_000, NOW).is_err());
+        assert!(policy.check(&operator(Some(1), true), INVOICE, 1_000, NOW).is_ok());
+    }
+
+    #[test]
+    fn the_cap_can_only_be_lowered() {
+        let operator = operator(Some(100), true);
+        let mut policy = WalletPolicy::default();
+        assert!(policy.lower_cap(&operator, 200_000).is_err());
+        policy.lower_cap(&operator, 50_000).unwrap();
+        assert_eq!(policy.cap(&operator), Some(50_000));
+        assert!(policy.lower_cap(&operator, 80_000).is_err());
+        assert!(policy.check(&operator, INVOICE, 60_000, NOW).is_err());
+        assert!(WalletPolicy::default().lower_cap(&self::operator(None, true), 0).is_err());
+    }
+
+    #[test]
+    fn reservations_count_against_the_cap() {
+        let operator = operator(Some(100), true);
+        let mut policy = WalletPolicy::default();
+        policy.reserve("a", 60_000, NOW);
+        assert!(policy.check(&operator, "b", 50_000, NOW).is_err());
+        assert!(policy.check(&operator, "a", 1_000, NOW).is_err());
+        policy.release("a", NOW);
+        assert!(policy.check(&operator, "b", 50_000, NOW).is_ok());
+
+        policy.reserve("b", 50_000, NOW);
+        policy.settle("b", NOW);
+        assert!(!policy.spends[0].unconfirmed);
+        assert_eq!(policy.spent_today(NOW), 50_000);
+        assert_eq!(policy.spent_today(NOW + SECONDS_PER_DAY), 0);
+    }
+
+    #[test]
+    fn tokens_need_approval_and_are_bound_to_their_invoice() {
+        let mut approvals = Approvals::default();
+        let (token, _) = approvals.issue("a", 1_000, NOW);
+        // Unapproved tokens are refused but stay usable once approved.
+        assert!(approvals.redeem(&token, "a", 1_000, NOW).is_err());
+        approvals.approve(&token, NOW).unwrap();
+        assert!(approvals.redeem(&token, "b", 1_000, NOW).is_err());
+        // A rejected approved token is spent too.
+        assert!(approvals.redeem(&token, "a", 1_000, NOW).is_err());
+
+        let (token, _) = approvals.issue("a", 1_000, NOW);
+        approvals.approve(&token, NOW).unwrap();
+        assert!(approvals.redeem(&token, "a", 2_000, NOW).is_err());
+        let (token, _) = approvals.issue("a", 1_000, NOW);
+        approvals.approve(&token, NOW).unwrap();
+        assert!(approvals
+            .redeem(&token, "a", 1_000, NOW + PREVIEW_TTL_SECS)
+            .is_err());
+        let (token, _) = approvals.issue("a", 1_000, NOW);
+        assert!(approvals.approve(&token, NOW + PREVIEW_TTL_SECS).is_err());
+        approvals.approve(&token, NOW).unwrap();
+        assert!(approvals.redeem(&token, "a", 1_000, NOW).is_ok());
+        assert!(approvals.redeem(&token, "a", 1_000, NOW).is_err());
+    }
+
+    /// A wallet service answering NIP-47 requests on the test relay: it
+    /// pays every invoice except those in `refuse` and reports a balance.
+    struct MockWallet {
+        keys: Keys,
+        paid: Arc<StdMutex<Vec<String>>>,
+    }
+
+    impl MockWallet {
+        async fn start(refuse: &'static [&'static str]) -> (Self, TestRelay) {
+            let keys = Keys::generate();
+            let paid = Arc::new(StdMutex::new(Vec::new()));
+            let wallet = keys.clone();
+            let ledger = paid.clone();
+            let relay = TestRelay::with_handler(move |request| {
+                if request.kind != Kind::WalletConnectRequest {
+                    return Vec::new();
+                }
+                let json = nip04::decrypt(wallet.secret_key(), &request.pubkey, &request.content)
+                    .unwrap();
+                let request_json: serde_json::Value = serde_json::from_str(&json).unwrap();
+                let method = request_json["method"].as_str().unwrap_or_default();
+                let reply = match method {
+                    "pay_invoice" => {
+                        let invoice = request_json["params"]["invoice"].as_str().unwrap();
+                        if refuse.contains(&invoice) {
+                            serde_json::json!({
+                                "result_type": method,
+                                "error": { "code": "INSUFFICIENT_BALANCE", "message": "no funds" },
+                            })
+                        } else {
+                            ledger.lock().unwrap().push(invoice.to_string());
+                            serde_json::json!({
+                                "result_type": method,
+                                "result": { "preimage": "00".repeat(32) },
+                            })
+                        }
+                    }
+                    _ => serde_json::json!({
+                        "result_type": method,
+                        "result": { "balance": 21_000_000 },
+                    }),
+                };
+                let content =
+                    nip04::encrypt(wallet.secret_key(), &request.pubkey, reply.to_string())
+                        .unwrap();
+                let response = EventBuilder::new(Kind::WalletConnectResponse, content)
+                    .tags([Tag::event(request.id), Tag::public_key(request.pubkey)])
+                    .sign_with_keys(&wallet)
+                    .unwrap();
+                vec![response]
+            })
+            .await;
+            (Self { keys, paid }, relay)
+        }
+
+        fn uri(&self, relay: &TestRelay) -> NostrWalletConnectURI {
+            NostrWalletConnectURI::new(
+                self.keys.public_key(),
+                vec![RelayUrl::parse(&relay.url).unwrap()],
+                SecretKey::generate(),
+                None,
+            )
+        }
+    }
+
+    fn stores(name: &str, operator: OperatorPolicy) -> (WalletPolicyStore, ApprovalStore) {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let policy = dir.join(format!("goostr-nwc-{name}-{id}.enc"));
+        let approvals = dir.join(format!("goostr-nwc-approvals-{name}-{id}.enc"));
+        let _ = std::fs::remove_file(&policy);
+        let _ = std::fs::remove_file(&approvals);
+        (
+            WalletPolicyStore::open(policy, b"test".to_vec(), operator).unwrap(),
+            ApprovalStore::open(approvals, b"test".to_vec()),
+        )
+    }
+
+    fn pay_args(invoice: &str, confirmation_token: Option<String>) -> NwcPayInvoiceArgs {
+        NwcPayInvoiceArgs {
+            invoice: invoice.to_string(),
+            amount_sats: None,
+            confirmation_token,
+            timeout_secs: Some(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn call_round_trips_through_the_wallet_relay() {
+        let (wallet, relay) = MockWallet::start(&[]).await;
+        let balance = call(&wallet.uri(&relay), Request::get_balance(), 5)
+            .await
+            .unwrap()
+            .to_get_balance()
+            .unwrap();
+        assert_eq!(balance.balance, 21_000_000);
+    }
+
+    #[tokio::test]
+    async fn pays_within_the_cap_and_releases_refused_payments() {
+        let (wallet, relay) = MockWallet::start(&[INVOICE_20M]).await;
+        let uri = wallet.uri(&relay);
+        let (store, approvals) = stores("cap", operator(Some(3_000_000), false));
+
+        let outcome = pay(&uri, &store, &approvals, pay_args(INVOICE_2500U, None))
+            .await
+            .unwrap();
+        assert!(matches!(outcome, PayInvoiceOutcome::Paid { amount_sats: 250_000, .. }));
+        assert_eq!(*wallet.paid.lock().unwrap(), [INVOICE_2500U]);
+        // Paying the same invoice twice in a day is refused locally.
+        assert!(pay(&uri, &store, &approvals, pay_args(INVOICE_2500U, None))
+            .await
+            .is_err());
+
+        // The wallet refuses; the reservation is released again.
+        let err = pay(&uri, &store, &approvals, pay_args(INVOICE_20M, None))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("no funds"), "{err}");
+        let state = store.state.lock().await;
+        assert_eq!(state.spent_today(Timestamp::now().as_secs()), 250_000_000);
+    }
+
+    #[tokio::test]
+    async fn confirmation_waits_for_the_operator() {
+        let (wallet, relay) = MockWallet::start(&[]).await;
+        let uri = wallet.uri(&relay);
+        let (store, approvals) = stores("confirm", operator(Some(1_000_000), true));
+
+        let outcome = pay(&uri, &store, &approvals, pay_args(INVOICE_2500U, None))
+            .await
+            .unwrap();
+        let PayInvoiceOutcome::ConfirmationRequired {
+            confirmation_token, ..
+        } = outcome
+        else {
+            panic!("paid without confirmation");
+        };
+        // The agent can't confirm its own preview.
+        let err = pay(
+            &uri,
+            &store,
+            &approvals,
+            pay_args(INVOICE_2500U, Some(confirmation_token.clone())),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("not approved"), "{err}");
+        assert!(wallet.paid.lock().unwrap().is_empty());
+
+        let now = Timestamp::now().as_secs();
+        approvals
+            .update(|a| a.approve(&confirmation_token, now))
+            .await
+            .unwrap();
+        let outcome = pay(
+            &uri,
+            &store,
+            &approvals,
+            pay_args(INVOICE_2500U, Some(confirmation_token)),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(outcome, PayInvoiceOutcome::Paid { .. }));
+        assert_eq!(*wallet.paid.lock().unwrap(), [INVOICE_2500U]);
+    }
+}
@@ -0,0 +1,537 @@
+use crate::keys::KeyStore;
+use crate::nostr_client;
+use crate::relays::{self, SendResult};
+use crate::settings::SettingsStore;
+use crate::{storage, util};
+use anyhow::{anyhow, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, OnceCell};
+use tracing::{debug, warn};
+
+const BASE_BACKOFF_SECS: u64 = 30;
+const MAX_BACKOFF_SECS: u64 = 6 * 60 * 60;
+const RETRY_TICK_SECS: u64 = 30;
+
+static OUTBOX: OnceCell<Arc<Outbox>> = OnceCell::const_new();
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingDelivery {
+    pub attempts: u32,
+    /// The relay was named in `to_relays` rather than taken from the pool,
+    /// so it is retried even though it is not a configured relay
+    #[serde(default)]
+    pub explicit: bool,
+    pub last_error: String,
+    pub next_attempt_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub event: Event,
+    pub queued_at: u64,
+    pub relays: BTreeMap<String, PendingDelivery>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OutboxFile {
+    entries: BTreeMap<String, OutboxEntry>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct OutboxListArgs {
+    /// Include the full signed event JSON for each entry
+    pub include_event: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct OutboxRetryArgs {
+    /// Hex event ID to retry; retries every pending entry when omitted
+    pub event_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct OutboxDropArgs {
+    /// Hex event ID of the entry to drop
+    pub event_id: String,
+    /// Only stop retrying these relays; drops the whole entry when omitted
+    pub relays: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutboxRow {
+    pub event_id: String,
+    pub kind: u16,
+    pub queued_at: u64,
+    pub relays: BTreeMap<String, PendingDelivery>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<Event>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RetryReport {
+    pub attempted: usize,
+    pub delivered: Vec<String>,
+    pub failed: BTreeMap<String, String>,
+    /// Deliveries abandoned because the relay was removed from the pool
+    pub dropped: Vec<String>,
+    pub remaining: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DropResult {
+    pub event_id: String,
+    pub dropped_relays: Vec<String>,
+    pub remaining: usize,
+}
+
+enum Attempt {
+    Delivered,
+    Failed(String),
+    /// The relay left the pool, so the delivery is no longer wanted
+    Abandoned,
+}
+
+pub struct Outbox {
+    path: PathBuf,
+    pass: Vec<u8>,
+    state: Mutex<OutboxFile>,
+}
+
+fn backoff_secs(attempts: u32) -> u64 {
+    BASE_BACKOFF_SECS
+        .saturating_mul(1u64 << attempts.min(16))
+        .min(MAX_BACKOFF_SECS)
+}
+
+fn parse_event_id(id: &str) -> Result<EventId> {
+    EventId::parse(id).map_err(|e| anyhow!("invalid event id {id}: {e}"))
+}
+
+impl Outbox {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        Self::open(path, util::ensure_keystore_secret()?)
+    }
+
+    fn open(path: PathBuf, pass: Vec<u8>) -> Result<Self> {
+        let state = if path.exists() {
+            storage::decrypt_from_file(&path, &pass)?
+        } else {
+            OutboxFile::default()
+        };
+        Ok(Self {
+            path,
+            pass,
+            state: Mutex::new(state),
+        })
+    }
+
+    fn persist(&self, state: &OutboxFile) -> Result<()> {
+        util::ensure_parent_dir(&self.path)?;
+        storage::encrypt_to_file(&self.path, &self.pass, state)
+    }
+
+    pub async fn enqueue(
+        &self,
+        event: &Event,
+        failed: &HashMap<RelayUrl, String>,
+        explicit: bool,
+    ) -> Result<()> {
+        if failed.is_empty() {
+            return Ok(());
+        }
+        let now = Timestamp::now().as_secs();
+        let mut state = self.state.lock().await;
+        let entry = state
+            .entries
+            .entry(event.id.to_hex())
+            .or_insert_with(|| OutboxEntry {
+                event: event.clone(),
+                queued_at: now,
+                relays: BTreeMap::new(),
+            });
+        for (url, err) in failed {
+            entry
+                .relays
+                .entry(url.to_string())
+                .and_modify(|d| d.last_error = err.clone())
+                .or_insert_with(|| PendingDelivery {
+                    attempts: 1,
+                    explicit,
+                    last_error: err.clone(),
+                    next_attempt_at: now + backoff_secs(1),
+                });
+        }
+        self.persist(&state)
+    }
+
+    pub async fn list(&self, include_event: bool) -> Vec<OutboxRow> {
+        let state = self.state.lock().await;
+        state
+            .entries
+            .iter()
+            .map(|(id, entry)| OutboxRow {
+                event_id: id.clone(),
+                kind: entry.event.kind.as_u16(),
+                queued_at: entry.queued_at,
+                relays: entry.relays.clone(),
+                event: include_event.then(|| entry.event.clone()),
+            })
+            .collect()
+    }
+
+    pub async fn len(&self) -> usize {
+        self.state.lock().await.entries.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.state.lock().await.entries.is_empty()
+    }
+
+    async fn due(&self, now: u64, only: Option<&str>) -> Vec<(Event, Vec<(String, bool)>)> {
+        let state = self.state.lock().await;
+        state
+            .entries
+            .iter()
+            .filter(|(id, _)| only.is_none_or(|o| o == id.as_str()))
+            .filter_map(|(_, entry)| {
+                let relays: Vec<(String, bool)> = entry
+                    .relays
+                    .iter()
+                    .filter(|(_, d)| only.is_some() || d.next_attempt_at <= now)
+                    .map(|(url, d)| (url.clone(), d.explicit))
+                    .collect();
+                (!relays.is_empty()).then(|| (entry.event.clone(), relays))
+            })
+            .collect()
+    }
+
+    async fn record_attempt(
+        &self,
+        event_id: &EventId,
+        relay: &str,
+        outcome: Attempt,
+    ) -> Result<()> {
+        let now = Timestamp::now().as_secs();
+        let mut state = self.state.lock().await;
+        let key = event_id.to_hex();
+        let Some(entry) = state.entries.get_mut(&key) else {
+            return Ok(());
+        };
+        match outcome {
+            Attempt::Delivered | Attempt::Abandoned => {
+                entry.relays.remove(relay);
+            }
+            Attempt::Failed(err) => {
+                if let Some(d) = entry.relays.get_mut(relay) {
+                    d.attempts = d.attempts.saturating_add(1);
+                    d.last_error = err;
+                    d.next_attempt_at = now + backoff_secs(d.attempts);
+                }
+            }
+        }
+        if entry.relays.is_empty() {
+            state.entries.remove(&key);
+        }
+        self.persist(&state)
+    }
+
+    pub async fn drop_entry(&self, args: OutboxDropArgs) -> Result<DropResult> {
+        let event_id = parse_event_id(&args.event_id)?;
+        let key = event_id.to_hex();
+        let mut state = self.state.lock().await;
+        let entry = state
+            .entries
+            .get_mut(&key)
+            .ok_or_else(|| anyhow!("no outbox entry for {key}"))?;
+        let dropped_relays: Vec<String> = match args.relays {
+            Some(relays) => relays
+                .into_iter()
+                .filter_map(|r| {
+                    let url = RelayUrl::parse(&r).map(|u| u.to_string()).unwrap_or(r);
+                    entry.relays.remove(&url).map(|_| url)
+                })
+                .collect(),
+            None => std::mem::take(&mut entry.relays).into_keys().collect(),
+        };
+        let remaining = entry.relays.len();
+        if remaining == 0 {
+            state.entries.remove(&key);
+        }
+        self.persist(&state)?;
+        Ok(DropResult {
+            event_id: key,
+            dropped_relays,
+            remaining,
+        })
+    }
+}
+
+pub async fn shared() -> Result<Arc<Outbox>> {
+    OUTBOX
+        .get_or_try_init(|| async { Outbox::load(util::nostr_outbox_path()).map(Arc::new) })
+        .await
+        .cloned()
+}
+
+/// Queues the relays an event failed on. `explicit` marks relays that came
+/// from `to_relays` rather than the pool.
+pub async fn record_failures(
+    event: &Event,
+    failed: &HashMap<RelayUrl, String>,
+    explicit: bool,
+) -> Result<()> {
+    if failed.is_empty() {
+        return Ok(());
+    }
+    shared().await?.enqueue(event, failed, explicit).await
+}
+
+/// Queues the failures of a publish that only reported a `SendResult`, as
+/// the upstream publishers do. The event is read back from the client
+/// database, where the relay pool saves every event it sends, and relays
+/// outside the pool are taken to have come from `to_relays`. Errors are
+/// logged rather than returned: the event is already out.
+pub async fn record_result(client: &Client, result: &SendResult) {
+    let mut pooled: HashMap<RelayUrl, String> = HashMap::new();
+    let mut explicit: HashMap<RelayUrl, String> = HashMap::new();
+    for (url, err) in &result.failed {
+        let Ok(url) = RelayUrl::parse(url) else {
+            continue;
+        };
+        if client.relay(&url).await.is_ok() {
+            pooled.insert(url, err.clone());
+        } else {
+            explicit.insert(url, err.clone());
+        }
+    }
+    if pooled.is_empty() && explicit.is_empty() {
+        return;
+    }
+    let event = match EventId::parse(&result.id) {
+        Ok(id) => client.database().event_by_id(&id).await.ok().flatten(),
+        Err(_) => None,
+    };
+    let Some(event) = event else {
+        warn!(event_id = %result.id, "event not in the local database, cannot queue its failed relays");
+        return;
+    };
+    for (failed, explicit) in [(pooled, false), (explicit, true)] {
+        if let Err(e) = record_failures(&event, &failed, explicit).await {
+            warn!(event_id = %result.id, error = %e, "failed to queue undelivered relays");
+        }
+    }
+}
+
+async fn send_to_relay(client: &Client, event: &Event, relay: &str) -> Attempt {
+    let pooled = client.relay(relay).await.is_ok();
+    let output = if pooled {
+        client
+            .send_event_to([relay], event)
+            .await
+            .map_err(anyhow::Error::from)
+    } else {
+        relays::with_relays(client, &[relay.to_string()], |scoped| async move {
+            Ok(scoped.send_event_to([relay], event).await?)
+        })
+        .await
+    };
+    match output {
+        Err(e) => Attempt::Failed(e.to_string()),
+        Ok(output) => match output.failed.into_values().next() {
+            Some(err) => Attempt::Failed(err),
+            None if output.success.is_empty() => {
+                Attempt::Failed("relay did not acknowledge event".to_string())
+            }
+            None => Attempt::Delivered,
+        },
+    }
+}
+
+/// Retries deliveries whose backoff has elapsed. When `event_id` is given the
+/// backoff is ignored for that entry and every pending relay is attempted.
+pub async fn retry(client: &Client, event_id: Option<&str>) -> Result<RetryReport> {
+    let outbox = shared().await?;
+    let only = event_id
+        .map(parse_event_id)
+        .transpose()?
+        .map(|id| id.to_hex());
+    let due = outbox.due(Timestamp::now().as_secs(), only.as_deref()).await;
+    let pool: Vec<String> = client
+        .relays()
+        .await
+        .into_keys()
+        .map(|u| u.to_string())
+        .collect();
+    let mut report = RetryReport::default();
+    for (event, relays) in due {
+        for (relay, explicit) in relays {
+            let label = format!("{}@{}", event.id.to_hex(), relay);
+            let outcome = if !explicit && !pool.contains(&relay) {
+                report.dropped.push(label);
+                Attempt::Abandoned
+            } else {
+                report.attempted += 1;
+                let outcome = send_to_relay(client, &event, &relay).await;
+                match &outcome {
+                    Attempt::Failed(err) => {
+                        report.failed.insert(label, err.clone());
+                    }
+                    _ => report.delivered.push(label),
+                }
+                outcome
+            };
+            outbox.record_attempt(&event.id, &relay, outcome).await?;
+        }
+    }
+    report.remaining = outbox.len().await;
+    Ok(report)
+}
+
+pub async fn run_retry_loop(keystore: Arc<KeyStore>, settings: Arc<SettingsStore>) {
+    let mut tick = tokio::time::interval(Duration::from_secs(RETRY_TICK_SECS));
+    loop {
+        tick.tick().await;
+        match shared().await {
+            Ok(outbox) if outbox.is_empty().await => continue,
+            Ok(_) => {}
+            Err(e) => {
+                warn!(error = %e, "outbox unavailable");
+                continue;
+            }
+        }
+        let active = match nostr_client::ensure_client(keystore.clone(), settings.clone()).await {
+            Ok(active) => active,
+            Err(e) => {
+                debug!(error = %e, "outbox retry skipped");
+                continue;
+            }
+        };
+        match retry(&active.client, None).await {
+            Ok(report) if report.attempted > 0 => debug!(
+                attempted = report.attempted,
+                delivered = report.delivered.len(),
+                remaining = report.remaining,
+                "outbox retry pass"
+            ),
+            Ok(_) => {}
+            Err(e) => warn!(error = %e, "outbox retry failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_outbox(name: &str) -> Outbox {
+        let path =
+            std::env::temp_dir().join(format!("goostr-outbox-{name}-{}.enc", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        Outbox::open(path, b"test-pass".to_vec()).unwrap()
+    }
+
+    fn signed_note() -> Event {
+        EventBuilder::text_note("hello")
+            .sign_with_keys(&Keys::generate())
+            .unwrap()
+    }
+
+    fn failures(urls: &[&str]) -> HashMap<RelayUrl, String> {
+        urls.iter()
+            .map(|u| (RelayUrl::parse(u).unwrap(), "timeout".to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn backoff_doubles_and_caps() {
+        assert_eq!(backoff_secs(0), 30);
+        assert_eq!(backoff_secs(1), 60);
+        assert_eq!(backoff_secs(2), 120);
+        assert_eq!(backoff_secs(5), 960);
+        assert_eq!(backoff_secs(10), MAX_BACKOFF_SECS);
+        assert_eq!(backoff_secs(u32::MAX), MAX_BACKOFF_SECS);
+    }
+
+    #[tokio::test]
+    async fn enqueue_dedupes_by_event_id() {
+        let outbox = temp_outbox("dedupe");
+        let event = signed_note();
+        outbox
+            .enqueue(&event, &failures(&["wss://a.example"]), false)
+            .await
+            .unwrap();
+        outbox
+            .enqueue(
+                &event,
+                &failures(&["wss://a.example", "wss://b.example"]),
+                false,
+            )
+            .await
+            .unwrap();
+        let rows = outbox.list(false).await;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].relays.len(), 2);
+        assert_eq!(rows[0].relays["wss://a.example"].attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn attempts_back_off_and_clear_on_delivery() {
+        let outbox = temp_outbox("attempts");
+        let event = signed_note();
+        outbox
+            .enqueue(
+                &event,
+                &failures(&["wss://a.example", "wss://b.example"]),
+                false,
+            )
+            .await
+            .unwrap();
+        let now = Timestamp::now().as_secs();
+        assert!(outbox.due(now, None).await.is_empty());
+        assert_eq!(outbox.due(now + backoff_secs(1), None).await[0].1.len(), 2);
+
+        outbox
+            .record_attempt(
+                &event.id,
+                "wss://a.example",
+                Attempt::Failed("refused".into()),
+            )
+            .await
+            .unwrap();
+        let rows = outbox.list(false).await;
+        let a = &rows[0].relays["wss://a.example"];
+        assert_eq!(a.attempts, 2);
+        assert_eq!(a.last_error, "refused");
+        assert!(a.next_attempt_at >= now + backoff_secs(2));
+
+        outbox
+            .record_attempt(&event.id, "wss://b.example", Attempt::Delivered)
+            .await
+            .unwrap();
+        outbox
+            .record_attempt(&event.id, "wss://a.example", Attempt::Abandoned)
+            .await
+            .unwrap();
+        assert!(outbox.is_empty().await);
+    }
+
+    #[tokio::test]
+    async fn state_survives_reload() {
+        let outbox = temp_outbox("reload");
+        let event = signed_note();
+        outbox
+            .enqueue(&event, &failures(&["wss://a.example"]), true)
+            .await
+            .unwrap();
+        let reloaded = Outbox::open(outbox.path.clone(), b"test-pass".to_vec()).unwrap();
+        let rows = reloaded.list(false).await;
+        assert_eq!(rows[0].event_id, event.id.to_hex());
+        assert!(rows[0].relays["wss://a.example"].explicit);
+    }
+}
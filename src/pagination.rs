@@ -0,0 +1,288 @@
+use crate::profiles;
+use crate::relays;
+use anyhow::{anyhow, bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const DEFAULT_MAX_EVENTS: usize = 1000;
+const LIST_LOOKBACK_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ListPreset {
+    MyNotes,
+    MentionsMe,
+    MyMetadata,
+    ByAuthor,
+    ByKind,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListEventsArgs {
+    /// Preset: my_notes, mentions_me, my_metadata, by_author or by_kind
+    pub preset: ListPreset,
+    /// Maximum number of events to return
+    pub limit: Option<usize>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+    /// Only return events after this Unix timestamp (default: 7 days ago)
+    pub since: Option<u64>,
+    /// Only return events before this Unix timestamp
+    pub until: Option<u64>,
+    /// Event kind, required for `by_kind`
+    pub kind: Option<u16>,
+    /// Author (npub, hex or NIP-05), required for `by_author`, optional for `by_kind`
+    pub author_npub: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct QueryEventsArgs {
+    /// Array of NIP-01 filter objects
+    pub filters: Vec<serde_json::Value>,
+    /// Maximum number of events per page (alias of `page_size` for single-page queries)
+    pub limit: Option<usize>,
+    /// Number of events per page (default: `limit` or 100)
+    pub page_size: Option<usize>,
+    /// Continuation token from a previous page
+    pub cursor: Option<String>,
+    /// Hard ceiling on events returned across all pages (default: 1000)
+    pub max_events: Option<usize>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventsPage {
+    pub events: Vec<Event>,
+    /// Display names of the page's authors, by hex public key
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub author_names: BTreeMap<String, String>,
+    /// Pass back as `cursor` to get the next page; absent once a page comes back empty
+    pub next_cursor: Option<String>,
+    /// True when the `max_events` ceiling stopped pagination
+    pub truncated: bool,
+}
+
+/// Position after the last event handed out: everything older than `until`,
+/// plus events at exactly `until` whose id sorts after `last_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub until: Timestamp,
+    pub last_id: EventId,
+    pub returned: usize,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        format!("{}:{}:{}", self.until.as_secs(), self.last_id.to_hex(), self.returned)
+    }
+
+    pub fn decode(token: &str) -> Result<Self> {
+        let mut parts = token.splitn(3, ':');
+        let (Some(until), Some(id), Some(returned)) = (parts.next(), parts.next(), parts.next())
+        else {
+            bail!("malformed cursor");
+        };
+        Ok(Self {
+            until: Timestamp::from(until.parse::<u64>().map_err(|_| anyhow!("malformed cursor"))?),
+            last_id: EventId::parse(id).map_err(|_| anyhow!("malformed cursor"))?,
+            returned: returned.parse().map_err(|_| anyhow!("malformed cursor"))?,
+        })
+    }
+
+    fn admits(&self, event: &Event) -> bool {
+        event.created_at < self.until || (event.created_at == self.until && event.id > self.last_id)
+    }
+}
+
+async fn preset_filter(client: &Client, args: &ListEventsArgs) -> Result<Filter> {
+    if let (Some(since), Some(until)) = (args.since, args.until) {
+        if since > until {
+            bail!("since must be <= until");
+        }
+    }
+    if args.limit == Some(0) {
+        bail!("limit must be > 0");
+    }
+    let since = Timestamp::from(
+        args.since
+            .unwrap_or_else(|| Timestamp::now().as_secs().saturating_sub(LIST_LOOKBACK_SECS)),
+    );
+    let until = args.until.map(Timestamp::from);
+    let author = match &args.author_npub {
+        Some(author) => Some(crate::nip05::resolve_pubkey(author).await?),
+        None => None,
+    };
+    let me = || async { Ok::<_, anyhow::Error>(client.signer().await?.get_public_key().await?) };
+    let mut filter = match args.preset {
+        ListPreset::MyNotes => {
+            relays::subscription_targets_my_notes(me().await?, Some(since), until).await
+        }
+        ListPreset::MentionsMe => {
+            relays::subscription_targets_mentions_me(me().await?, Some(since), until).await
+        }
+        ListPreset::MyMetadata => relays::subscription_targets_my_metadata(me().await?).await,
+        ListPreset::ByAuthor => {
+            let Some(author) = author else {
+                bail!("author_npub is required for by_author");
+            };
+            Filter::new().author(author).since(since)
+        }
+        ListPreset::ByKind => {
+            let Some(kind) = args.kind else {
+                bail!("kind is required for by_kind");
+            };
+            let mut filter = Filter::new().kind(Kind::from(kind)).since(since);
+            if let Some(author) = author {
+                filter = filter.author(author);
+            }
+            filter
+        }
+    };
+    if let (ListPreset::ByAuthor | ListPreset::ByKind, Some(until)) = (args.preset, until) {
+        filter = filter.until(until);
+    }
+    if let Some(limit) = args.limit {
+        filter = filter.limit(limit);
+    }
+    Ok(filter)
+}
+
+/// `nostr_events_list`, replacing the upstream tool of the same name so its
+/// presets hide the same events every other user-facing query does.
+pub async fn list_events(client: &Client, args: ListEventsArgs) -> Result<Vec<Event>> {
+    let filter = preset_filter(client, &args).await?;
+    let events = relays::fetch_events(client, filter, args.timeout_secs.unwrap_or(10)).await?;
+    let mut events = relays::visible_events(client, events).await;
+    sort_events(&mut events);
+    if let Some(limit) = args.limit {
+        events.truncate(limit);
+    }
+    Ok(events)
+}
+
+/// Newest first, ties broken by ascending id so paging is deterministic.
+pub fn sort_events(events: &mut [Event]) {
+    events.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.id.cmp(&b.id)));
+}
+
+pub async fn query_page(client: &Client, args: QueryEventsArgs) -> Result<EventsPage> {
+    if args.filters.is_empty() {
+        bail!("filters must not be empty");
+    }
+    let page_size = args
+        .page_size
+        .or(args.limit)
+        .unwrap_or(DEFAULT_PAGE_SIZE);
+    if page_size == 0 {
+        bail!("page_size must be > 0");
+    }
+    let max_events = args.max_events.unwrap_or(DEFAULT_MAX_EVENTS);
+    let cursor = args.cursor.as_deref().map(Cursor::decode).transpose()?;
+    let returned = cursor.as_ref().map_or(0, |c| c.returned);
+    let budget = max_events.saturating_sub(returned).min(page_size);
+    if budget == 0 {
+        return Ok(EventsPage {
+            events: Vec::new(),
+            author_names: BTreeMap::new(),
+            next_cursor: None,
+            truncated: true,
+        });
+    }
+    let timeout = args.timeout_secs.unwrap_or(10);
+
+    // The cursor walks the raw relay results; filtering only decides which
+    // of the events in this window are shown. Otherwise a page whose events
+    // are all muted, deleted or expired would look like the end.
+    let mut seen = HashSet::new();
+    let mut window = Vec::new();
+    for value in &args.filters {
+        let mut filter = Filter::from_json(value.to_string())?;
+        if let Some(c) = &cursor {
+            filter = filter.until(c.until);
+        }
+        // Over-fetch by one page so events sharing the boundary second that
+        // were already returned do not starve the next page.
+        let filter = filter.limit(budget * 2);
+        for event in relays::fetch_events(client, filter, timeout).await? {
+            if cursor.as_ref().is_none_or(|c| c.admits(&event)) && seen.insert(event.id) {
+                window.push(event);
+            }
+        }
+    }
+    sort_events(&mut window);
+    window.truncate(budget);
+
+    // Relays may cap `limit` below what was asked for, so a short page does
+    // not prove the end: pagination stops only once a page comes back empty.
+    let position = window.last().map(|last| (last.created_at, last.id));
+    let events = relays::visible_events(client, window).await;
+    let total = returned + events.len();
+    let truncated = position.is_some() && total >= max_events;
+    let next_cursor = match position {
+        Some((until, last_id)) if !truncated => Some(
+            Cursor {
+                until,
+                last_id,
+                returned: total,
+            }
+            .encode(),
+        ),
+        _ => None,
+    };
+    let author_names = profiles::display_names(client, events.iter().map(|e| e.pubkey), timeout)
+        .await?
+        .into_iter()
+        .map(|(pk, name)| (pk.to_hex(), name))
+        .collect();
+    Ok(EventsPage {
+        events,
+        author_names,
+        next_cursor,
+        truncated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_at(keys: &Keys, at: u64) -> Event {
+        EventBuilder::text_note(format!("at {at}"))
+            .custom_created_at(Timestamp::from(at))
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let keys = Keys::generate();
+        let event = note_at(&keys, 1_700_000_000);
+        let cursor = Cursor {
+            until: event.created_at,
+            last_id: event.id,
+            returned: 42,
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+        assert!(Cursor::decode("nope").is_err());
+    }
+
+    #[test]
+    fn cursor_admits_older_events_and_later_ties() {
+        let keys = Keys::generate();
+        let mut tied = vec![note_at(&keys, 100), note_at(&Keys::generate(), 100)];
+        sort_events(&mut tied);
+        let cursor = Cursor {
+            until: tied[0].created_at,
+            last_id: tied[0].id,
+            returned: 1,
+        };
+        assert!(!cursor.admits(&tied[0]));
+        assert!(cursor.admits(&tied[1]));
+        assert!(cursor.admits(&note_at(&keys, 99)));
+        assert!(!cursor.admits(&note_at(&keys, 101)));
+    }
+}
@@ -0,0 +1,371 @@
+use crate::nip11;
+use crate::progress::Progress;
+use crate::relays::{self, SendResult};
+use anyhow::{bail, Result};
+use nostr::nips::nip13;
+use nostr_sdk::prelude::*;
+use rmcp::service::RequestContext;
+use rmcp::RoleServer;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+const PROGRESS_INTERVAL_MS: u64 = 500;
+const ATTEMPT_BATCH: u64 = 1024;
+/// Mining with nobody waiting on it (scheduled posts) gives up after this.
+const BACKGROUND_TIME_BUDGET_SECS: u64 = 120;
+/// Relay-advertised requirements above this are not chased: they would tie
+/// up the CPU for hours, so the relay is left to reject the event instead.
+const MAX_RELAY_DIFFICULTY: u8 = 28;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PowMineArgs {
+    /// Event kind (default: 1)
+    pub kind: Option<u16>,
+    pub content: String,
+    pub tags: Option<Vec<Vec<String>>>,
+    /// Unix timestamp for the event (default: now)
+    pub created_at: Option<u64>,
+    /// Target difficulty in leading zero bits (default: highest NIP-11 `min_pow_difficulty` of the target relays)
+    pub difficulty: Option<u8>,
+    /// Worker threads (default and maximum: available CPU cores)
+    pub threads: Option<usize>,
+    /// Give up after this many seconds
+    pub time_budget_secs: Option<u64>,
+    /// Publish the mined event after signing (default: false)
+    pub publish: Option<bool>,
+    /// Specific relay URLs to publish to; also used to look up the required difficulty
+    pub to_relays: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PowMineResult {
+    pub event: Event,
+    pub difficulty: u8,
+    pub attempts: u64,
+    pub elapsed_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send: Option<SendResult>,
+}
+
+pub struct Mined {
+    pub event: UnsignedEvent,
+    pub difficulty: u8,
+    pub attempts: u64,
+    pub elapsed_ms: u64,
+}
+
+/// Runs NIP-13 mining on worker threads. The search is abandoned when the
+/// MCP request (or, in the background, the owner's token) is cancelled or
+/// the time budget runs out, and progress is reported as the best
+/// difficulty reached so far.
+#[derive(Clone)]
+pub struct Miner {
+    progress: Progress,
+    cancel: CancellationToken,
+    threads: usize,
+    time_budget: Option<Duration>,
+}
+
+struct Search {
+    stop: AtomicBool,
+    attempts: AtomicU64,
+    best: AtomicU8,
+    found: Mutex<Option<(u128, u8)>>,
+}
+
+fn default_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+fn is_nonce(tag: &Tag) -> bool {
+    tag.as_slice().first().map(String::as_str) == Some("nonce")
+}
+
+impl Miner {
+    pub fn from_context(context: &RequestContext<RoleServer>) -> Self {
+        Self {
+            progress: Progress::from_context(context),
+            cancel: context.ct.clone(),
+            threads: default_threads(),
+            time_budget: None,
+        }
+    }
+
+    pub fn background(cancel: CancellationToken) -> Self {
+        Self {
+            progress: Progress::disabled(),
+            cancel,
+            threads: default_threads(),
+            time_budget: Some(Duration::from_secs(BACKGROUND_TIME_BUDGET_SECS)),
+        }
+    }
+
+    pub fn threads(mut self, threads: Option<usize>) -> Self {
+        if let Some(threads) = threads {
+            self.threads = threads.clamp(1, default_threads());
+        }
+        self
+    }
+
+    /// Overrides the time budget when `secs` is given.
+    pub fn time_budget(mut self, secs: Option<u64>) -> Self {
+        if let Some(secs) = secs {
+            self.time_budget = Some(Duration::from_secs(secs));
+        }
+        self
+    }
+
+    pub async fn mine(&self, template: UnsignedEvent, difficulty: u8) -> Result<Mined> {
+        let started = Instant::now();
+        let base: Vec<Tag> = template.tags.iter().filter(|t| !is_nonce(t)).cloned().collect();
+        let search = Arc::new(Search {
+            stop: AtomicBool::new(false),
+            attempts: AtomicU64::new(0),
+            best: AtomicU8::new(0),
+            found: Mutex::new(None),
+        });
+
+        let workers: Vec<JoinHandle<()>> = (0..self.threads)
+            .map(|worker| {
+                let search = search.clone();
+                let base = base.clone();
+                let template = template.clone();
+                let stride = self.threads as u128;
+                std::thread::spawn(move || {
+                    let mut nonce = worker as u128;
+                    let mut batch = 0u64;
+                    while !search.stop.load(Ordering::Relaxed) {
+                        let mut tags = base.clone();
+                        tags.push(Tag::pow(nonce, difficulty));
+                        let tags = Tags::from_list(tags);
+                        let id = EventId::new(
+                            &template.pubkey,
+                            &template.created_at,
+                            &template.kind,
+                            &tags,
+                            &template.content,
+                        );
+                        let bits = nip13::get_leading_zero_bits(id.as_bytes());
+                        search.best.fetch_max(bits, Ordering::Relaxed);
+                        if bits >= difficulty {
+                            let mut found = search.found.lock().unwrap_or_else(|e| e.into_inner());
+                            found.get_or_insert((nonce, bits));
+                            search.stop.store(true, Ordering::Relaxed);
+                        }
+                        batch += 1;
+                        if batch == ATTEMPT_BATCH {
+                            search.attempts.fetch_add(batch, Ordering::Relaxed);
+                            batch = 0;
+                        }
+                        nonce += stride;
+                    }
+                    search.attempts.fetch_add(batch, Ordering::Relaxed);
+                })
+            })
+            .collect();
+
+        let deadline = self.time_budget.map(|budget| started + budget);
+        let mut tick = tokio::time::interval(Duration::from_millis(PROGRESS_INTERVAL_MS));
+        let outcome = loop {
+            tokio::select! {
+                _ = tick.tick() => {}
+                _ = self.cancel.cancelled() => break Err("cancelled"),
+            }
+            if search.stop.load(Ordering::Relaxed) {
+                break Ok(());
+            }
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                break Err("time budget exhausted");
+            }
+            let best = search.best.load(Ordering::Relaxed);
+            let attempts = search.attempts.load(Ordering::Relaxed);
+            self.progress
+                .report(
+                    f64::from(best),
+                    Some(f64::from(difficulty)),
+                    format!("{attempts} hashes, best {best}/{difficulty} bits"),
+                )
+                .await;
+        };
+        search.stop.store(true, Ordering::Relaxed);
+        tokio::task::spawn_blocking(move || {
+            for worker in workers {
+                let _ = worker.join();
+            }
+        })
+        .await?;
+
+        let attempts = search.attempts.load(Ordering::Relaxed);
+        if let Err(reason) = outcome {
+            bail!(
+                "proof of work {reason} after {attempts} hashes (best {} of {difficulty} bits)",
+                search.best.load(Ordering::Relaxed)
+            );
+        }
+        let (nonce, achieved) = search
+            .found
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .expect("search stopped with a nonce");
+        let mut tags = base;
+        tags.push(Tag::pow(nonce, difficulty));
+        let mut event = UnsignedEvent::new(
+            template.pubkey,
+            template.created_at,
+            template.kind,
+            tags,
+            template.content,
+        );
+        event.ensure_id();
+        Ok(Mined {
+            difficulty: achieved,
+            event,
+            attempts,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+}
+
+/// Highest NIP-11 `min_pow_difficulty` among the target relays, or the
+/// client's write relays when none are given, capped at
+/// `MAX_RELAY_DIFFICULTY`. Documents are fetched concurrently; relays
+/// without one count as 0.
+pub async fn required_difficulty(client: &Client, to_relays: Option<&[String]>) -> u8 {
+    let urls: Vec<String> = match to_relays {
+        Some(urls) if !urls.is_empty() => urls.to_vec(),
+        _ => client
+            .relays()
+            .await
+            .into_iter()
+            .filter(|(_, relay)| relay.flags().has_write())
+            .map(|(url, _)| url.to_string())
+            .collect(),
+    };
+    let docs = futures::future::join_all(urls.iter().map(|url| nip11::relay_info(url))).await;
+    docs.into_iter()
+        .filter_map(|doc| doc.ok()?.limitation?.min_pow_difficulty)
+        .map(|min| min.clamp(0, i32::from(MAX_RELAY_DIFFICULTY)) as u8)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Difficulty to mine for a publish that asked for `requested`: at least what
+/// the target relays require, so events are not rejected by relays that
+/// demand proof of work even when none was asked for.
+pub async fn target_difficulty(
+    client: &Client,
+    requested: Option<u8>,
+    to_relays: Option<&[String]>,
+) -> u8 {
+    requested
+        .unwrap_or(0)
+        .max(required_difficulty(client, to_relays).await)
+}
+
+/// Builds, mines when proof of work was requested or the target relays
+/// require it, and signs an event. The difficulty is the larger of the two.
+pub async fn sign_mined(
+    client: &Client,
+    miner: &Miner,
+    builder: EventBuilder,
+    requested: Option<u8>,
+    to_relays: Option<&[String]>,
+) -> Result<Event> {
+    let signer = client.signer().await?;
+    let unsigned = builder.build(signer.get_public_key().await?);
+    let difficulty = target_difficulty(client, requested, to_relays).await;
+    let unsigned = if difficulty > 0 {
+        miner.mine(unsigned, difficulty).await?.event
+    } else {
+        unsigned
+    };
+    Ok(signer.sign_event(unsigned).await?)
+}
+
+pub async fn mine_template(client: &Client, miner: &Miner, args: PowMineArgs) -> Result<PowMineResult> {
+    let tags = args
+        .tags
+        .unwrap_or_default()
+        .into_iter()
+        .map(Tag::parse)
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let mut builder = EventBuilder::new(Kind::from(args.kind.unwrap_or(1)), args.content).tags(tags);
+    if let Some(created_at) = args.created_at {
+        builder = builder.custom_created_at(Timestamp::from(created_at));
+    }
+    let difficulty = match args.difficulty {
+        Some(d) => d,
+        None => required_difficulty(client, args.to_relays.as_deref()).await,
+    };
+    if difficulty == 0 {
+        bail!("no difficulty given and the target relays do not require proof of work");
+    }
+    let signer = client.signer().await?;
+    let unsigned = builder.build(signer.get_public_key().await?);
+    let mined = miner
+        .clone()
+        .threads(args.threads)
+        .time_budget(args.time_budget_secs)
+        .mine(unsigned, difficulty)
+        .await?;
+    let event = signer.sign_event(mined.event).await?;
+    let send = if args.publish.unwrap_or(false) {
+        Some(relays::publish_event(client, &event, args.to_relays).await?)
+    } else {
+        None
+    };
+    Ok(PowMineResult {
+        event,
+        difficulty: mined.difficulty,
+        attempts: mined.attempts,
+        elapsed_ms: mined.elapsed_ms,
+        send,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves one NIP-11 document advertising `min_pow_difficulty`.
+    async fn relay_requiring(difficulty: u8) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let body = format!(r#"{{"limitation":{{"min_pow_difficulty":{difficulty}}}}}"#);
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/nostr+json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        format!("ws://{addr}")
+    }
+
+    #[tokio::test]
+    async fn relay_minimum_applies_without_a_requested_difficulty() {
+        let client = Client::new(Keys::generate());
+        client.add_relay(relay_requiring(12).await).await.unwrap();
+        assert_eq!(target_difficulty(&client, None, None).await, 12);
+        assert_eq!(target_difficulty(&client, Some(4), None).await, 12);
+        assert_eq!(target_difficulty(&client, Some(20), None).await, 20);
+
+        let strict = vec![relay_requiring(40).await];
+        assert_eq!(
+            target_difficulty(&client, None, Some(&strict)).await,
+            MAX_RELAY_DIFFICULTY
+        );
+    }
+}
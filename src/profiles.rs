@@ -0,0 +1,244 @@
+use crate::nip05;
+use crate::relays;
+use anyhow::Result;
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const CACHE_TTL: Duration = Duration::from_secs(15 * 60);
+/// Upper bound on cached profiles; expired entries go first, then the least
+/// recently fetched.
+const CACHE_CAPACITY: usize = 5_000;
+const AUTHOR_BATCH: usize = 250;
+
+static CACHE: OnceLock<Mutex<HashMap<PublicKey, CachedProfile>>> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+struct CachedProfile {
+    metadata: Metadata,
+    created_at: Timestamp,
+    fetched_at: Instant,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FetchManyArgs {
+    /// Public keys as hex, npub or NIP-05 identifier
+    pub pubkeys: Vec<String>,
+    /// Bypass the cache and always ask relays (default: false)
+    pub refresh: Option<bool>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+/// Unknown fields are refused rather than ignored: the upstream tool this
+/// replaces took a key `label`, and silently fetching the active key's
+/// profile instead would answer the wrong question.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FetchProfileArgs {
+    /// Public key as hex, npub or NIP-05 identifier (default: active key)
+    pub pubkey: Option<String>,
+    /// Bypass the cache and always ask relays (default: false)
+    pub refresh: Option<bool>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileRow {
+    pub pubkey: String,
+    pub npub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+}
+
+fn cache() -> &'static Mutex<HashMap<PublicKey, CachedProfile>> {
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn evict(cache: &mut HashMap<PublicKey, CachedProfile>) {
+    if cache.len() <= CACHE_CAPACITY {
+        return;
+    }
+    cache.retain(|_, p| p.fetched_at.elapsed() < CACHE_TTL);
+    if cache.len() <= CACHE_CAPACITY {
+        return;
+    }
+    let mut by_age: Vec<(Instant, PublicKey)> =
+        cache.iter().map(|(pk, p)| (p.fetched_at, *pk)).collect();
+    by_age.sort();
+    let excess = cache.len() - CACHE_CAPACITY;
+    for (_, pk) in by_age.into_iter().take(excess) {
+        cache.remove(&pk);
+    }
+}
+
+/// Best human-readable name in a profile: `display_name`, then `name`.
+pub fn display_name(metadata: &Metadata) -> Option<String> {
+    metadata
+        .display_name
+        .clone()
+        .filter(|n| !n.is_empty())
+        .or_else(|| metadata.name.clone().filter(|n| !n.is_empty()))
+}
+
+/// Stores a kind 0 event in the cache unless a newer one is already there.
+pub async fn observe(event: &Event) {
+    if event.kind != Kind::Metadata {
+        return;
+    }
+    let Ok(metadata) = Metadata::from_json(&event.content) else {
+        return;
+    };
+    let mut cache = cache().lock().await;
+    match cache.get(&event.pubkey) {
+        Some(existing) if existing.created_at > event.created_at => {}
+        _ => {
+            cache.insert(
+                event.pubkey,
+                CachedProfile {
+                    metadata,
+                    created_at: event.created_at,
+                    fetched_at: Instant::now(),
+                },
+            );
+            evict(&mut cache);
+        }
+    }
+}
+
+/// Resolves many profiles at once, only asking relays for pubkeys that are
+/// missing from the cache or older than the TTL.
+pub async fn resolve_many(
+    client: &Client,
+    pubkeys: impl IntoIterator<Item = PublicKey>,
+    timeout: u64,
+    refresh: bool,
+) -> Result<HashMap<PublicKey, (Metadata, Timestamp)>> {
+    let wanted: HashSet<PublicKey> = pubkeys.into_iter().collect();
+    let stale: Vec<PublicKey> = {
+        let cache = cache().lock().await;
+        wanted
+            .iter()
+            .filter(|pk| {
+                refresh
+                    || cache
+                        .get(pk)
+                        .is_none_or(|p| p.fetched_at.elapsed() >= CACHE_TTL)
+            })
+            .copied()
+            .collect()
+    };
+    for chunk in stale.chunks(AUTHOR_BATCH) {
+        let filter = Filter::new()
+            .kind(Kind::Metadata)
+            .authors(chunk.iter().copied());
+        for event in relays::fetch_events(client, filter, timeout).await? {
+            observe(&event).await;
+        }
+    }
+    let cache = cache().lock().await;
+    Ok(wanted
+        .iter()
+        .filter_map(|pk| {
+            cache
+                .get(pk)
+                .map(|p| (*pk, (p.metadata.clone(), p.created_at)))
+        })
+        .collect())
+}
+
+pub async fn display_names(
+    client: &Client,
+    pubkeys: impl IntoIterator<Item = PublicKey>,
+    timeout: u64,
+) -> Result<HashMap<PublicKey, String>> {
+    Ok(resolve_many(client, pubkeys, timeout, false)
+        .await?
+        .into_iter()
+        .filter_map(|(pk, (metadata, _))| display_name(&metadata).map(|n| (pk, n)))
+        .collect())
+}
+
+pub async fn fetch_many(client: &Client, args: FetchManyArgs) -> Result<Vec<ProfileRow>> {
+    let mut pubkeys = Vec::with_capacity(args.pubkeys.len());
+    for pk in &args.pubkeys {
+        pubkeys.push(nip05::resolve_pubkey(pk).await?);
+    }
+    let mut resolved = resolve_many(
+        client,
+        pubkeys.iter().copied(),
+        args.timeout_secs.unwrap_or(10),
+        args.refresh.unwrap_or(false),
+    )
+    .await?;
+    Ok(pubkeys
+        .into_iter()
+        .map(|pk| {
+            let found = resolved.remove(&pk);
+            profile_row(pk, found)
+        })
+        .collect())
+}
+
+fn profile_row(pk: PublicKey, found: Option<(Metadata, Timestamp)>) -> ProfileRow {
+    ProfileRow {
+        pubkey: pk.to_hex(),
+        npub: pk.to_bech32().unwrap_or_default(),
+        display_name: found.as_ref().and_then(|(m, _)| display_name(m)),
+        created_at: found.as_ref().map(|(_, t)| t.as_secs()),
+        metadata: found.map(|(m, _)| m),
+    }
+}
+
+/// Single-profile lookup behind `nostr_metadata_fetch`, sharing the cache
+/// with `fetch_many`.
+pub async fn fetch_one(client: &Client, args: FetchProfileArgs) -> Result<ProfileRow> {
+    let pubkey = match &args.pubkey {
+        Some(pk) => nip05::resolve_pubkey(pk).await?,
+        None => client.signer().await?.get_public_key().await?,
+    };
+    let mut resolved = resolve_many(
+        client,
+        [pubkey],
+        args.timeout_secs.unwrap_or(10),
+        args.refresh.unwrap_or(false),
+    )
+    .await?;
+    Ok(profile_row(pubkey, resolved.remove(&pubkey)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cache_stays_bounded() {
+        for i in 0..CACHE_CAPACITY + 10 {
+            let keys = Keys::generate();
+            let event = EventBuilder::metadata(&Metadata::new().name(format!("n{i}")))
+                .sign_with_keys(&keys)
+                .unwrap();
+            observe(&event).await;
+        }
+        assert!(cache().lock().await.len() <= CACHE_CAPACITY);
+    }
+
+    #[test]
+    fn fetch_args_refuse_a_label() {
+        let err = serde_json::from_value::<FetchProfileArgs>(serde_json::json!({"label": "alice"}))
+            .unwrap_err();
+        assert!(err.to_string().contains("label"), "{err}");
+        let args: FetchProfileArgs = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert!(args.pubkey.is_none());
+    }
+}
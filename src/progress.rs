@@ -0,0 +1,39 @@
+use rmcp::model::{ProgressNotificationParam, ProgressToken};
+use rmcp::service::RequestContext;
+use rmcp::{Peer, RoleServer};
+
+/// Sends MCP progress notifications for a tool call. Does nothing when the
+/// caller did not ask for progress by sending a progress token.
+#[derive(Clone)]
+pub struct Progress {
+    target: Option<(Peer<RoleServer>, ProgressToken)>,
+}
+
+impl Progress {
+    pub fn from_context(context: &RequestContext<RoleServer>) -> Self {
+        Self {
+            target: context
+                .meta
+                .get_progress_token()
+                .map(|token| (context.peer.clone(), token)),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self { target: None }
+    }
+
+    pub async fn report(&self, progress: f64, total: Option<f64>, message: impl Into<String>) {
+        let Some((peer, token)) = &self.target else {
+            return;
+        };
+        let _ = peer
+            .notify_progress(ProgressNotificationParam {
+                progress_token: token.clone(),
+                progress,
+                total,
+                message: Some(message.into()),
+            })
+            .await;
+    }
+}
@@ -0,0 +1,366 @@
+use crate::pow::{self, Miner};
+use anyhow::{bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+const SCOPED_CONNECT_TIMEOUT_SECS: u64 = 5;
+
+/// Options accepted by every goostr publishing tool on top of its own args.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+pub struct PublishOptions {
+    /// Unix timestamp after which relays and clients should drop the event (NIP-40)
+    pub expires_at: Option<u64>,
+    /// Seconds from now after which the event expires (NIP-40); alternative to `expires_at`
+    pub expires_in: Option<u64>,
+    /// Mark the content as sensitive (NIP-36); the value is the reason, which may be empty
+    pub content_warning: Option<String>,
+}
+
+/// Upstream publishing args with goostr's `PublishOptions` flattened alongside.
+/// `pow` is declared here rather than left to the upstream args so that it is
+/// consumed before `args` is deserialized: upstream would otherwise mine the
+/// event itself, only for the tagging signer to mine it again.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Published<T> {
+    /// Proof-of-work difficulty (NIP-13), raised to what the write relays require
+    pub pow: Option<u8>,
+    #[serde(flatten)]
+    pub options: PublishOptions,
+    #[serde(flatten)]
+    pub args: T,
+}
+
+impl PublishOptions {
+    pub fn tags(&self) -> Result<Vec<Tag>> {
+        let now = Timestamp::now().as_secs();
+        let expires_at = match (self.expires_at, self.expires_in) {
+            (Some(_), Some(_)) => bail!("provide either expires_at or expires_in, not both"),
+            (Some(at), None) if at <= now => bail!("expires_at {at} is in the past"),
+            (Some(at), None) => Some(at),
+            (None, Some(0)) => bail!("expires_in must be greater than 0"),
+            (None, Some(secs)) => Some(now.saturating_add(secs)),
+            (None, None) => None,
+        };
+        let mut tags = Vec::new();
+        if let Some(at) = expires_at {
+            tags.push(Tag::expiration(Timestamp::from(at)));
+        }
+        if let Some(reason) = &self.content_warning {
+            let reason = reason.trim();
+            if reason.is_empty() {
+                tags.push(Tag::parse(["content-warning"])?);
+            } else {
+                tags.push(Tag::parse(["content-warning", reason])?);
+            }
+        }
+        Ok(tags)
+    }
+}
+
+/// Signs through the real signer, appending extra tags to the one event being
+/// published and mining it: the first one signed that is not a NIP-42 AUTH or
+/// another ephemeral event. Everything else is passed through untouched.
+/// Mining happens once, here, after the tags are final; a nonce already on
+/// the event is dropped and its target kept if it is higher.
+struct TaggingSigner {
+    inner: Arc<dyn NostrSigner>,
+    extra: Vec<Tag>,
+    difficulty: u8,
+    miner: Miner,
+    tagged: AtomicBool,
+}
+
+impl std::fmt::Debug for TaggingSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TaggingSigner")
+            .field("extra", &self.extra)
+            .field("difficulty", &self.difficulty)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TaggingSigner {
+    fn claims(&self, unsigned: &UnsignedEvent) -> bool {
+        if unsigned.kind == Kind::Authentication || unsigned.kind.is_ephemeral() {
+            return false;
+        }
+        !self.tagged.swap(true, Ordering::SeqCst)
+    }
+
+    async fn retag(&self, unsigned: UnsignedEvent) -> Result<UnsignedEvent> {
+        let is_nonce = |t: &Tag| t.as_slice().first().map(String::as_str) == Some("nonce");
+        let target = unsigned
+            .tags
+            .iter()
+            .find(|t| is_nonce(t))
+            .and_then(|t| t.as_slice().get(2).and_then(|d| d.parse::<u8>().ok()));
+        let mut tags: Vec<Tag> = unsigned.tags.iter().filter(|t| !is_nonce(t)).cloned().collect();
+        for tag in &self.extra {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+        let mut retagged = UnsignedEvent::new(
+            unsigned.pubkey,
+            unsigned.created_at,
+            unsigned.kind,
+            tags,
+            unsigned.content,
+        );
+        let difficulty = target.unwrap_or(0).max(self.difficulty);
+        if difficulty > 0 {
+            return Ok(self.miner.mine(retagged, difficulty).await?.event);
+        }
+        retagged.ensure_id();
+        Ok(retagged)
+    }
+}
+
+impl NostrSigner for TaggingSigner {
+    fn backend(&self) -> SignerBackend<'_> {
+        self.inner.backend()
+    }
+
+    fn get_public_key(&self) -> BoxedFuture<'_, Result<PublicKey, SignerError>> {
+        self.inner.get_public_key()
+    }
+
+    fn sign_event(&self, unsigned: UnsignedEvent) -> BoxedFuture<'_, Result<Event, SignerError>> {
+        Box::pin(async move {
+            if !self.claims(&unsigned) {
+                return self.inner.sign_event(unsigned).await;
+            }
+            let retagged = self
+                .retag(unsigned)
+                .await
+                .map_err(|e| SignerError::backend(std::io::Error::other(e.to_string())))?;
+            self.inner.sign_event(retagged).await
+        })
+    }
+
+    fn nip04_encrypt<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+        content: &'a str,
+    ) -> BoxedFuture<'a, Result<String, SignerError>> {
+        self.inner.nip04_encrypt(public_key, content)
+    }
+
+    fn nip04_decrypt<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+        encrypted_content: &'a str,
+    ) -> BoxedFuture<'a, Result<String, SignerError>> {
+        self.inner.nip04_decrypt(public_key, encrypted_content)
+    }
+
+    fn nip44_encrypt<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+        content: &'a str,
+    ) -> BoxedFuture<'a, Result<String, SignerError>> {
+        self.inner.nip44_encrypt(public_key, content)
+    }
+
+    fn nip44_decrypt<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+        payload: &'a str,
+    ) -> BoxedFuture<'a, Result<String, SignerError>> {
+        self.inner.nip44_decrypt(public_key, payload)
+    }
+}
+
+/// Client the upstream publishers sign through when there are tags or proof
+/// of work to add. It is kept between calls, on the same relays (with the
+/// same read/write roles) and database as the active client, so publishing
+/// doesn't reconnect every time; it is rebuilt when either changes.
+struct Publisher {
+    database: Arc<dyn NostrDatabase>,
+    relays: Vec<(RelayUrl, bool, bool)>,
+    client: Client,
+}
+
+static PUBLISHER: Mutex<Option<Publisher>> = Mutex::const_new(None);
+
+async fn relay_roles(client: &Client) -> Vec<(RelayUrl, bool, bool)> {
+    let mut roles: Vec<_> = client
+        .relays()
+        .await
+        .into_iter()
+        .map(|(url, relay)| {
+            let flags = relay.flags();
+            (url, flags.has_read(), flags.has_write())
+        })
+        .collect();
+    roles.sort();
+    roles
+}
+
+async fn connect_publisher(
+    client: &Client,
+    relays: Vec<(RelayUrl, bool, bool)>,
+) -> Result<Publisher> {
+    let database = client.database().clone();
+    let publisher = Client::builder().database(database.clone()).build();
+    for (url, read, write) in &relays {
+        let opts = RelayOptions::new().read(*read).write(*write);
+        publisher.pool().add_relay(url, opts).await?;
+    }
+    publisher.connect().await;
+    publisher
+        .wait_for_connection(Duration::from_secs(SCOPED_CONNECT_TIMEOUT_SECS))
+        .await;
+    Ok(Publisher {
+        database,
+        relays,
+        client: publisher,
+    })
+}
+
+/// Runs an upstream publisher with the option tags and proof of work applied.
+/// The upstream functions sign through the client they are given, so when
+/// there is anything to add they get the shared publishing client with a
+/// signer that adds it. Such publishes take turns, since the signer is set
+/// on that client for the duration of each one.
+pub async fn with_publish_options<T, F, Fut>(
+    client: &Client,
+    miner: &Miner,
+    options: &PublishOptions,
+    pow: Option<u8>,
+    publish: F,
+) -> Result<T>
+where
+    F: FnOnce(Client) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let extra = options.tags()?;
+    let difficulty = pow::target_difficulty(client, pow, None).await;
+    if extra.is_empty() && difficulty == 0 {
+        return publish(client.clone()).await;
+    }
+    let signer = TaggingSigner {
+        inner: client.signer().await?,
+        extra,
+        difficulty,
+        miner: miner.clone(),
+        tagged: AtomicBool::new(false),
+    };
+    let mut publisher = PUBLISHER.lock().await;
+    let relays = relay_roles(client).await;
+    let scoped = match publisher.as_ref() {
+        Some(current)
+            if Arc::ptr_eq(&current.database, client.database()) && current.relays == relays =>
+        {
+            current.client.clone()
+        }
+        _ => {
+            if let Some(stale) = publisher.take() {
+                stale.client.shutdown().await;
+            }
+            publisher.insert(connect_publisher(client, relays).await?).client.clone()
+        }
+    };
+    scoped.set_signer(signer).await;
+    let result = publish(scoped.clone()).await;
+    scoped.unset_signer().await;
+    result
+}
+
+/// Drops events whose NIP-40 expiration has passed.
+pub fn drop_expired(events: Vec<Event>) -> Vec<Event> {
+    events.into_iter().filter(|e| !e.is_expired()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_util::sync::CancellationToken;
+
+    fn signer(difficulty: u8) -> TaggingSigner {
+        TaggingSigner {
+            inner: Arc::new(Keys::generate()),
+            extra: vec![Tag::parse(["content-warning"]).unwrap()],
+            difficulty,
+            miner: Miner::background(CancellationToken::new()),
+            tagged: AtomicBool::new(false),
+        }
+    }
+
+    #[tokio::test]
+    async fn only_the_published_event_is_tagged() {
+        let signer = signer(0);
+        let pk = signer.get_public_key().await.unwrap();
+        let relay = RelayUrl::parse("wss://relay.example.com").unwrap();
+        let auth = EventBuilder::auth("challenge", relay).build(pk);
+        let auth = signer.sign_event(auth).await.unwrap();
+        assert!(auth.tags.iter().all(|t| t.as_slice()[0] != "content-warning"));
+
+        let note = signer.sign_event(EventBuilder::text_note("hi").build(pk)).await.unwrap();
+        assert!(note.tags.iter().any(|t| t.as_slice()[0] == "content-warning"));
+
+        let second = signer.sign_event(EventBuilder::text_note("again").build(pk)).await.unwrap();
+        assert!(second.tags.iter().all(|t| t.as_slice()[0] != "content-warning"));
+    }
+
+    #[tokio::test]
+    async fn mines_once_after_tagging() {
+        let signer = signer(4);
+        let pk = signer.get_public_key().await.unwrap();
+        let note = signer.sign_event(EventBuilder::text_note("hi").build(pk)).await.unwrap();
+        assert!(note.tags.iter().any(|t| t.as_slice()[0] == "content-warning"));
+        assert!(nostr::nips::nip13::get_leading_zero_bits(note.id.as_bytes()) >= 4);
+        let nonces = note.tags.iter().filter(|t| t.as_slice()[0] == "nonce").count();
+        assert_eq!(nonces, 1);
+    }
+
+    #[test]
+    fn expired_events_are_dropped() {
+        let keys = Keys::generate();
+        let note = |expiration: Option<u64>| {
+            EventBuilder::text_note("notice")
+                .tags(expiration.map(|at| Tag::expiration(Timestamp::from(at))))
+                .sign_with_keys(&keys)
+                .unwrap()
+        };
+        let future = Timestamp::now().as_secs() + 3600;
+        let kept = vec![note(None), note(Some(future))];
+        let mut events = kept.clone();
+        events.push(note(Some(1_000)));
+        assert_eq!(drop_expired(events), kept);
+    }
+
+    #[tokio::test]
+    async fn publishes_with_options_and_clears_the_signer() {
+        let relay = crate::test_relay::TestRelay::start().await;
+        let client = Client::new(Keys::generate());
+        client.add_relay(relay.url.as_str()).await.unwrap();
+        client.connect().await;
+        let miner = Miner::background(CancellationToken::new());
+        let options = PublishOptions {
+            content_warning: Some("spoiler".to_string()),
+            ..Default::default()
+        };
+
+        for content in ["one", "two"] {
+            with_publish_options(&client, &miner, &options, None, |scoped| async move {
+                Ok(scoped.send_event_builder(EventBuilder::text_note(content)).await?)
+            })
+            .await
+            .unwrap();
+        }
+
+        let events = relay.events().await;
+        assert_eq!(events.len(), 2);
+        let warned = |e: &Event| e.tags.iter().any(|t| t.as_slice()[0] == "content-warning");
+        assert!(events.iter().all(warned));
+        let publisher = PUBLISHER.lock().await;
+        assert!(!publisher.as_ref().unwrap().client.has_signer().await);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::progress::Progress;
+use crate::relays;
+use anyhow::{bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RebroadcastArgs {
+    /// NIP-01 filter selecting the events to copy
+    pub filter: Option<serde_json::Value>,
+    /// Hex event IDs to copy; used instead of `filter`
+    pub event_ids: Option<Vec<String>>,
+    /// Relay URLs to republish the events to
+    pub target_relays: Vec<String>,
+    /// Query timeout in seconds for fetching from current relays (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RelayRebroadcast {
+    pub accepted: Vec<String>,
+    pub rejected: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RebroadcastResult {
+    pub fetched: usize,
+    pub fully_delivered: usize,
+    pub relays: BTreeMap<String, RelayRebroadcast>,
+}
+
+fn rebroadcast_filter(args: &RebroadcastArgs) -> Result<Filter> {
+    match (&args.filter, &args.event_ids) {
+        (Some(_), Some(_)) => bail!("provide either filter or event_ids, not both"),
+        (Some(value), None) => Ok(Filter::from_json(value.to_string())?),
+        (None, Some(ids)) if !ids.is_empty() => {
+            let ids = ids
+                .iter()
+                .map(|id| EventId::parse(id))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            Ok(Filter::new().ids(ids))
+        }
+        _ => bail!("either filter or event_ids is required"),
+    }
+}
+
+/// Parses `target_relays` once so the summary is keyed by the same URLs the
+/// relay pool reports back.
+fn normalize_targets(urls: &[String]) -> Result<Vec<String>> {
+    let mut targets: Vec<String> = Vec::with_capacity(urls.len());
+    for url in urls {
+        let url = RelayUrl::parse(url)
+            .map_err(|e| anyhow::anyhow!("invalid relay URL {url}: {e}"))?
+            .as_str_without_trailing_slash()
+            .to_string();
+        if !targets.contains(&url) {
+            targets.push(url);
+        }
+    }
+    Ok(targets)
+}
+
+/// Fetches events from the currently configured relays and republishes them,
+/// byte-for-byte, to `target_relays` over one set of connections. Deliveries
+/// that fail are left to the outbox to retry.
+pub async fn rebroadcast(
+    client: &Client,
+    args: RebroadcastArgs,
+    progress: &Progress,
+) -> Result<RebroadcastResult> {
+    if args.target_relays.is_empty() {
+        bail!("target_relays must not be empty");
+    }
+    let targets = normalize_targets(&args.target_relays)?;
+    let filter = rebroadcast_filter(&args)?;
+    let mut events = relays::fetch_events(client, filter, args.timeout_secs.unwrap_or(10)).await?;
+    events.sort_by_key(|e| e.created_at);
+
+    let total = events.len();
+    let targets = &targets;
+    let events = &events;
+    let (fully_delivered, relays_summary) =
+        relays::with_relays(client, targets, |scoped| async move {
+            let mut relays_summary: BTreeMap<String, RelayRebroadcast> = targets
+                .iter()
+                .map(|r| (r.clone(), RelayRebroadcast::default()))
+                .collect();
+            let mut fully_delivered = 0;
+            for (i, event) in events.iter().enumerate() {
+                let id = event.id.to_hex();
+                match relays::send_to(&scoped, event, targets).await {
+                    Ok(result) => {
+                        if result.failed.is_empty() {
+                            fully_delivered += 1;
+                        }
+                        for url in result.success {
+                            relays_summary
+                                .entry(url)
+                                .or_default()
+                                .accepted
+                                .push(id.clone());
+                        }
+                        for (url, err) in result.failed {
+                            relays_summary
+                                .entry(url)
+                                .or_default()
+                                .rejected
+                                .insert(id.clone(), err);
+                        }
+                    }
+                    Err(e) => {
+                        for summary in relays_summary.values_mut() {
+                            summary.rejected.insert(id.clone(), e.to_string());
+                        }
+                    }
+                }
+                progress
+                    .report(
+                        (i + 1) as f64,
+                        Some(total as f64),
+                        format!("republished {id}"),
+                    )
+                    .await;
+            }
+            Ok((fully_delivered, relays_summary))
+        })
+        .await?;
+
+    Ok(RebroadcastResult {
+        fetched: total,
+        fully_delivered,
+        relays: relays_summary,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_relay::TestRelay;
+
+    fn note(keys: &Keys, content: &str) -> Event {
+        EventBuilder::text_note(content)
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn targets_are_normalized_and_deduplicated() {
+        let targets = normalize_targets(&[
+            "wss://relay.example.com".to_string(),
+            "wss://relay.example.com/".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(targets.len(), 1);
+        assert!(normalize_targets(&["not a url".to_string()]).is_err());
+    }
+
+    #[test]
+    fn filter_and_ids_are_exclusive() {
+        let args = RebroadcastArgs {
+            filter: Some(serde_json::json!({"kinds": [1]})),
+            event_ids: Some(vec![EventId::all_zeros().to_hex()]),
+            target_relays: vec![],
+            timeout_secs: None,
+        };
+        assert!(rebroadcast_filter(&args).is_err());
+    }
+
+    #[tokio::test]
+    async fn copies_every_event_to_the_targets() {
+        let keys = Keys::generate();
+        let source = TestRelay::start().await;
+        let target = TestRelay::start().await;
+        let events = vec![note(&keys, "one"), note(&keys, "two")];
+        source.insert(events.clone()).await;
+
+        let client = Client::new(keys.clone());
+        client.add_relay(source.url.as_str()).await.unwrap();
+        client.connect().await;
+
+        let args = RebroadcastArgs {
+            filter: Some(serde_json::json!({"authors": [keys.public_key().to_hex()]})),
+            event_ids: None,
+            target_relays: vec![format!("{}/", target.url)],
+            timeout_secs: Some(5),
+        };
+        let result = rebroadcast(&client, args, &Progress::disabled())
+            .await
+            .unwrap();
+
+        assert_eq!(result.fetched, 2);
+        assert_eq!(result.fully_delivered, 2);
+        assert_eq!(result.relays.len(), 1);
+        let summary = result.relays.values().next().unwrap();
+        assert_eq!(summary.accepted.len(), 2);
+        assert!(summary.rejected.is_empty());
+
+        let copied = target.events().await;
+        for event in &events {
+            assert!(copied.iter().any(|e| e.id == event.id));
+        }
+        // The target never joins the active client's pool.
+        assert_eq!(client.relays().await.len(), 1);
+    }
+}
@@ -8,11 +8,19 @@ use nostr_mcp_core::events as core_events;
 use nostr_mcp_core::replies as core_replies;
 use nostr_mcp_core::polls as core_polls;
 use nostr_mcp_core::groups as core_groups;
+use crate::pow::Miner;
+use crate::publish_options::with_publish_options;
 use nostr_sdk::prelude::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+const SCOPED_CONNECT_TIMEOUT_SECS: u64 = 5;
 
 pub use nostr_mcp_core::publish::{PostGroupChatArgs, PostReactionArgs, PostTextArgs, PostThreadArgs, SendResult};
 pub use nostr_mcp_core::replies::{PostCommentArgs, PostReplyArgs};
+pub use crate::publish_options::{PublishOptions, Published};
 pub use nostr_mcp_core::polls::{
     CreatePollArgs, GetPollResultsArgs, PollOption, PollResultOption, PollResults, VotePollArgs,
 };
@@ -69,12 +77,66 @@ pub async fn subscription_targets_my_metadata(pk: PublicKey) -> Filter {
     core_events::subscription_targets_my_metadata(pk).await
 }
 
-pub async fn list_events(client: &Client, filter: Filter, timeout_secs: u64) -> Result<Vec<Event>> {
+/// Events exactly as the relays returned them. Internal lookups use these
+/// as is; tools showing events to the user pass them through
+/// `visible_events`.
+pub async fn fetch_events(client: &Client, filter: Filter, timeout_secs: u64) -> Result<Vec<Event>> {
     core_events::list_events(client, filter, timeout_secs)
         .await
         .map_err(|e| anyhow::anyhow!(e))
 }
 
+/// Drops expired (NIP-40), locally deleted (NIP-09) and muted (NIP-51)
+/// events from what is shown to the user.
+pub async fn visible_events(client: &Client, events: Vec<Event>) -> Vec<Event> {
+    let events = crate::publish_options::drop_expired(events);
+    let events = crate::deletions::apply_deletions(events).await;
+    crate::lists::apply_mutes(client, events).await
+}
+
+/// Fetches every matching event from one relay, walking back with `until`
+/// because relays cap how many events one REQ returns. Pages overlap on the
+/// boundary timestamp and are deduplicated by ID; when a page brings nothing
+/// new, the walk steps below that timestamp rather than stopping, so a burst
+/// of events sharing one `created_at` can't end it early. The flag is false
+/// when the walk stopped at `max_events` before the relay ran dry.
+pub async fn fetch_all_from(
+    client: &Client,
+    relay: &str,
+    filter: &Filter,
+    page_size: usize,
+    max_events: usize,
+    timeout: Duration,
+) -> Result<(Vec<Event>, bool)> {
+    let mut seen: HashSet<EventId> = HashSet::new();
+    let mut events = Vec::new();
+    let mut until = filter.until;
+    loop {
+        let mut page = filter.clone().limit(page_size);
+        if let Some(until) = until {
+            page = page.until(until);
+        }
+        let batch = client.fetch_events_from([relay], page, timeout).await?;
+        let mut oldest: Option<Timestamp> = None;
+        let mut fresh = 0;
+        for event in batch.into_iter() {
+            oldest = Some(oldest.map_or(event.created_at, |o| o.min(event.created_at)));
+            if seen.insert(event.id) {
+                fresh += 1;
+                events.push(event);
+            }
+        }
+        if events.len() >= max_events {
+            return Ok((events, false));
+        }
+        until = match oldest {
+            Some(oldest) if fresh > 0 => Some(oldest),
+            Some(oldest) if oldest.as_secs() > 0 => Some(oldest - 1),
+            _ => return Ok((events, true)),
+        };
+    }
+}
+
 pub async fn status_summary(client: &Client) -> Result<HashMap<String, String>> {
     core_relays::status_summary(client)
         .await
@@ -86,60 +148,173 @@ pub async fn publish_event_builder(
     builder: EventBuilder,
     to_relays: Option<Vec<String>>,
 ) -> Result<SendResult> {
-    core_publish::publish_event_builder(client, builder, to_relays)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+    let event = client.sign_event_builder(builder).await?;
+    publish_event(client, &event, to_relays).await
 }
 
-pub async fn post_text_note(client: &Client, args: PostTextArgs) -> Result<SendResult> {
-    core_publish::post_text_note(client, args)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+pub async fn publish_event(
+    client: &Client,
+    event: &Event,
+    to_relays: Option<Vec<String>>,
+) -> Result<SendResult> {
+    if let Some(urls) = to_relays.filter(|urls| !urls.is_empty()) {
+        let targets = &urls;
+        return with_relays(client, &urls, |scoped| async move {
+            send_to(&scoped, event, targets).await
+        })
+        .await;
+    }
+    let output = client.send_event(event).await?;
+    if let Err(e) = crate::outbox::record_failures(event, &output.failed, false).await {
+        warn!(event_id = %event.id, error = %e, "failed to queue undelivered relays");
+    }
+    Ok(send_result(event, &output))
 }
 
-pub async fn post_thread(client: &Client, args: PostThreadArgs) -> Result<SendResult> {
-    core_publish::post_thread(client, args)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+/// Sends `event` to `urls` through a client opened by `with_relays`, so a
+/// batch of events can share one set of connections. Failures are queued in
+/// the outbox as explicit targets.
+pub async fn send_to(scoped: &Client, event: &Event, urls: &[String]) -> Result<SendResult> {
+    let output = scoped.send_event_to(urls, event).await?;
+    if let Err(e) = crate::outbox::record_failures(event, &output.failed, true).await {
+        warn!(event_id = %event.id, error = %e, "failed to queue undelivered relays");
+    }
+    Ok(send_result(event, &output))
+}
+
+/// Runs `f` against a short-lived client connected to `urls` that shares the
+/// active client's signer and database. Relays reached this way never join
+/// the active client's pool, so later publishes and queries don't go to them.
+pub async fn with_relays<T, F, Fut>(client: &Client, urls: &[String], f: F) -> Result<T>
+where
+    F: FnOnce(Client) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut builder = Client::builder().database(client.database().clone());
+    if let Ok(signer) = client.signer().await {
+        builder = builder.signer(signer);
+    }
+    let scoped = builder.build();
+    for url in urls {
+        if let Err(e) = scoped.add_relay(url.as_str()).await {
+            debug!(relay = %url, error = %e, "skipping relay");
+        }
+    }
+    scoped.connect().await;
+    scoped
+        .wait_for_connection(Duration::from_secs(SCOPED_CONNECT_TIMEOUT_SECS))
+        .await;
+    let result = f(scoped.clone()).await;
+    scoped.shutdown().await;
+    result
+}
+
+/// Queues the relays an upstream publisher failed on in the outbox.
+async fn queued(client: &Client, result: SendResult) -> Result<SendResult> {
+    crate::outbox::record_result(client, &result).await;
+    Ok(result)
+}
+
+fn send_result(event: &Event, output: &Output<EventId>) -> SendResult {
+    SendResult {
+        id: event.id.to_hex(),
+        pubkey: event.pubkey.to_hex(),
+        success: output.success.iter().map(|u| u.to_string()).collect(),
+        failed: output
+            .failed
+            .iter()
+            .map(|(u, e)| (u.to_string(), e.clone()))
+            .collect(),
+    }
+}
+
+pub async fn post_thread(
+    client: &Client,
+    miner: &Miner,
+    args: Published<PostThreadArgs>,
+) -> Result<SendResult> {
+    let Published { pow, options, args } = args;
+    let result = with_publish_options(client, miner, &options, pow, |client| async move {
+        core_publish::post_thread(&client, args)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    })
+    .await?;
+    queued(client, result).await
 }
 
 pub async fn post_group_chat(
     client: &Client,
-    args: PostGroupChatArgs,
+    miner: &Miner,
+    args: Published<PostGroupChatArgs>,
 ) -> Result<SendResult> {
-    core_publish::post_group_chat(client, args)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+    let Published { pow, options, args } = args;
+    let result = with_publish_options(client, miner, &options, pow, |client| async move {
+        core_publish::post_group_chat(&client, args)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    })
+    .await?;
+    queued(client, result).await
 }
 
 pub async fn post_reaction(client: &Client, args: PostReactionArgs) -> Result<SendResult> {
-    core_publish::post_reaction(client, args)
+    let result = core_publish::post_reaction(client, args)
         .await
-        .map_err(|e| anyhow::anyhow!(e))
+        .map_err(|e| anyhow::anyhow!(e))?;
+    queued(client, result).await
 }
 
-pub async fn post_reply(client: &Client, args: PostReplyArgs) -> Result<SendResult> {
-    core_replies::post_reply(client, args)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+pub async fn post_reply(
+    client: &Client,
+    miner: &Miner,
+    args: Published<PostReplyArgs>,
+) -> Result<SendResult> {
+    let Published { pow, options, args } = args;
+    let result = with_publish_options(client, miner, &options, pow, |client| async move {
+        core_replies::post_reply(&client, args)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    })
+    .await?;
+    queued(client, result).await
 }
 
-pub async fn post_comment(client: &Client, args: PostCommentArgs) -> Result<SendResult> {
-    core_replies::post_comment(client, args)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+pub async fn post_comment(
+    client: &Client,
+    miner: &Miner,
+    args: Published<PostCommentArgs>,
+) -> Result<SendResult> {
+    let Published { pow, options, args } = args;
+    let result = with_publish_options(client, miner, &options, pow, |client| async move {
+        core_replies::post_comment(&client, args)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    })
+    .await?;
+    queued(client, result).await
 }
 
-pub async fn create_poll(client: &Client, args: CreatePollArgs) -> Result<SendResult> {
-    core_polls::create_poll(client, args)
-        .await
-        .map_err(|e| anyhow::anyhow!(e))
+pub async fn create_poll(
+    client: &Client,
+    miner: &Miner,
+    args: Published<CreatePollArgs>,
+) -> Result<SendResult> {
+    let Published { pow, options, args } = args;
+    let result = with_publish_options(client, miner, &options, pow, |client| async move {
+        core_polls::create_poll(&client, args)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    })
+    .await?;
+    queued(client, result).await
 }
 
 pub async fn vote_poll(client: &Client, args: VotePollArgs) -> Result<SendResult> {
-    core_polls::vote_poll(client, args)
+    let result = core_polls::vote_poll(client, args)
         .await
-        .map_err(|e| anyhow::anyhow!(e))
+        .map_err(|e| anyhow::anyhow!(e))?;
+    queued(client, result).await
 }
 
 pub async fn get_poll_results(
@@ -153,55 +328,125 @@ pub async fn get_poll_results(
 }
 
 pub async fn put_user(client: &Client, args: PutUserArgs) -> Result<SendResult> {
-    core_groups::put_user(client, args)
+    let result = core_groups::put_user(client, args)
         .await
-        .map_err(|e| anyhow::anyhow!(e))
+        .map_err(|e| anyhow::anyhow!(e))?;
+    queued(client, result).await
 }
 
 pub async fn remove_user(client: &Client, args: RemoveUserArgs) -> Result<SendResult> {
-    core_groups::remove_user(client, args)
+    let result = core_groups::remove_user(client, args)
         .await
-        .map_err(|e| anyhow::anyhow!(e))
+        .map_err(|e| anyhow::anyhow!(e))?;
+    queued(client, result).await
 }
 
 pub async fn edit_group_metadata(client: &Client, args: EditGroupMetadataArgs) -> Result<SendResult> {
-    core_groups::edit_group_metadata(client, args)
+    let result = core_groups::edit_group_metadata(client, args)
         .await
-        .map_err(|e| anyhow::anyhow!(e))
+        .map_err(|e| anyhow::anyhow!(e))?;
+    queued(client, result).await
 }
 
 pub async fn delete_group_event(client: &Client, args: DeleteEventArgs) -> Result<SendResult> {
-    core_groups::delete_group_event(client, args)
+    let result = core_groups::delete_group_event(client, args)
         .await
-        .map_err(|e| anyhow::anyhow!(e))
+        .map_err(|e| anyhow::anyhow!(e))?;
+    queued(client, result).await
 }
 
 pub async fn create_group(client: &Client, args: CreateGroupArgs) -> Result<SendResult> {
-    core_groups::create_group(client, args)
+    let result = core_groups::create_group(client, args)
         .await
-        .map_err(|e| anyhow::anyhow!(e))
+        .map_err(|e| anyhow::anyhow!(e))?;
+    queued(client, result).await
 }
 
 pub async fn delete_group(client: &Client, args: DeleteGroupArgs) -> Result<SendResult> {
-    core_groups::delete_group(client, args)
+    let result = core_groups::delete_group(client, args)
         .await
-        .map_err(|e| anyhow::anyhow!(e))
+        .map_err(|e| anyhow::anyhow!(e))?;
+    queued(client, result).await
 }
 
 pub async fn create_invite(client: &Client, args: CreateInviteArgs) -> Result<SendResult> {
-    core_groups::create_invite(client, args)
+    let result = core_groups::create_invite(client, args)
         .await
-        .map_err(|e| anyhow::anyhow!(e))
+        .map_err(|e| anyhow::anyhow!(e))?;
+    queued(client, result).await
 }
 
 pub async fn join_group(client: &Client, args: JoinGroupArgs) -> Result<SendResult> {
-    core_groups::join_group(client, args)
+    let result = core_groups::join_group(client, args)
         .await
-        .map_err(|e| anyhow::anyhow!(e))
+        .map_err(|e| anyhow::anyhow!(e))?;
+    queued(client, result).await
 }
 
 pub async fn leave_group(client: &Client, args: LeaveGroupArgs) -> Result<SendResult> {
-    core_groups::leave_group(client, args)
+    let result = core_groups::leave_group(client, args)
         .await
-        .map_err(|e| anyhow::anyhow!(e))
+        .map_err(|e| anyhow::anyhow!(e))?;
+    queued(client, result).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_relay::TestRelay;
+
+    fn note_at(keys: &Keys, content: &str, at: u64) -> Event {
+        EventBuilder::text_note(content)
+            .custom_created_at(Timestamp::from(at))
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    async fn connected(relay: &TestRelay) -> Client {
+        let client = Client::default();
+        client.add_relay(relay.url.as_str()).await.unwrap();
+        client.connect().await;
+        client
+    }
+
+    #[tokio::test]
+    async fn fetch_all_from_walks_past_a_crowded_timestamp() {
+        let keys = Keys::generate();
+        let relay = TestRelay::start().await;
+        let mut events: Vec<Event> = (0..3).map(|i| note_at(&keys, &format!("burst {i}"), 100)).collect();
+        events.extend((0..3).map(|i| note_at(&keys, &format!("older {i}"), 99 - i)));
+        relay.insert(events).await;
+        let client = connected(&relay).await;
+
+        let filter = Filter::new().author(keys.public_key());
+        let (fetched, complete) =
+            fetch_all_from(&client, &relay.url, &filter, 2, usize::MAX, Duration::from_secs(5))
+                .await
+                .unwrap();
+
+        assert!(complete);
+        // One burst event is out of reach of a two-event page, but every
+        // older event is still found.
+        assert_eq!(fetched.len(), 5);
+        for at in 97..=99 {
+            assert!(fetched.iter().any(|e| e.created_at.as_secs() == at));
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_all_from_stops_at_max_events() {
+        let keys = Keys::generate();
+        let relay = TestRelay::start().await;
+        relay.insert((0..5).map(|i| note_at(&keys, "note", 100 + i))).await;
+        let client = connected(&relay).await;
+
+        let filter = Filter::new().author(keys.public_key());
+        let (fetched, complete) =
+            fetch_all_from(&client, &relay.url, &filter, 2, 3, Duration::from_secs(5))
+                .await
+                .unwrap();
+
+        assert!(!complete);
+        assert!(fetched.len() >= 3);
+    }
 }
@@ -0,0 +1,159 @@
+use crate::articles;
+use crate::relays::{self, SendResult};
+use crate::thread;
+use anyhow::{anyhow, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::debug;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RepostArgs {
+    /// Event to repost: hex ID, note1... or nevent1...
+    pub event: String,
+    /// Relay where the event can be found, embedded in the repost (default: first hint in the reference)
+    pub relay_hint: Option<String>,
+    /// Specific relay URLs to publish to
+    pub to_relays: Option<Vec<String>>,
+    /// Query timeout in seconds when fetching the target (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepostResult {
+    pub kind: u16,
+    pub target_id: String,
+    pub target_kind: u16,
+    #[serde(flatten)]
+    pub send: SendResult,
+}
+
+async fn fetch_target(
+    client: &Client,
+    reference: &str,
+    relay_hint: Option<&str>,
+    timeout: u64,
+) -> Result<(Event, Option<RelayUrl>)> {
+    let (id, hints) = thread::parse_event_ref(reference)?;
+    let hint = match relay_hint {
+        Some(url) => Some(RelayUrl::parse(url)?),
+        None => hints.first().cloned(),
+    };
+    let filter = Filter::new().id(id);
+    let mut found = relays::fetch_events(client, filter.clone(), timeout)
+        .await?
+        .into_iter()
+        .next();
+    // Hinted relays are only asked when the configured ones don't have the
+    // event, through a short-lived client; an unreachable hint is skipped.
+    let hint_urls: Vec<String> = hint.iter().chain(&hints).map(|u| u.to_string()).collect();
+    if found.is_none() && !hint_urls.is_empty() {
+        let targets = hint_urls.clone();
+        let fetched = relays::with_relays(client, &hint_urls, |scoped| async move {
+            Ok(scoped
+                .fetch_events_from(targets, filter, Duration::from_secs(timeout))
+                .await?)
+        })
+        .await;
+        match fetched {
+            Ok(events) => found = events.into_iter().next(),
+            Err(e) => debug!(error = %e, "relay hints did not answer"),
+        }
+    }
+    let event = found.ok_or_else(|| anyhow!("event {} not found", id.to_hex()))?;
+    Ok((event, hint))
+}
+
+/// NIP-18 repost: kind 6 for text notes, kind 16 with a `k` tag for any other
+/// kind. The target is embedded as JSON so clients need not fetch it.
+pub async fn repost(client: &Client, args: RepostArgs) -> Result<RepostResult> {
+    let timeout = args.timeout_secs.unwrap_or(10);
+    let (target, hint) =
+        fetch_target(client, &args.event, args.relay_hint.as_deref(), timeout).await?;
+    let builder = EventBuilder::repost(&target, hint);
+    let kind = if target.kind == Kind::TextNote {
+        Kind::Repost
+    } else {
+        Kind::GenericRepost
+    };
+    let send = relays::publish_event_builder(client, builder, args.to_relays).await?;
+    Ok(RepostResult {
+        kind: kind.as_u16(),
+        target_id: target.id.to_hex(),
+        target_kind: target.kind.as_u16(),
+        send,
+    })
+}
+
+/// NIP-18 quote of an already fetched event: the `nostr:` reference to append
+/// to the content (nevent, or naddr for addressable events) and its `q` and
+/// `p` tags.
+pub fn quote_parts(target: &Event, hint: Option<RelayUrl>) -> Result<(String, Vec<Tag>)> {
+    let hint_str = hint.as_ref().map(|u| u.to_string()).unwrap_or_default();
+    let (reference, q_value) = match target.tags.identifier() {
+        Some(d) if target.kind.is_addressable() => (
+            articles::naddr(target.kind, target.pubkey, d, hint.clone().into_iter().collect())?,
+            Coordinate::new(target.kind, target.pubkey)
+                .identifier(d)
+                .to_string(),
+        ),
+        _ => (
+            Nip19Event::new(target.id)
+                .author(target.pubkey)
+                .kind(target.kind)
+                .relays(hint.clone())
+                .to_bech32()?,
+            target.id.to_hex(),
+        ),
+    };
+    let tags = vec![
+        Tag::parse(["q".to_string(), q_value, hint_str, target.pubkey.to_hex()])?,
+        Tag::public_key(target.pubkey),
+    ];
+    Ok((format!("nostr:{reference}"), tags))
+}
+
+/// Fetches the event to quote (asking its relay hints when the configured
+/// relays miss it) and returns its reference and tags, see [`quote_parts`].
+pub async fn quote(
+    client: &Client,
+    reference: &str,
+    relay_hint: Option<&str>,
+    timeout: u64,
+) -> Result<(String, Vec<Tag>)> {
+    let (target, hint) = fetch_target(client, reference, relay_hint, timeout).await?;
+    quote_parts(&target, hint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_notes_by_nevent_and_addressable_events_by_naddr() {
+        let keys = Keys::generate();
+        let hint = RelayUrl::parse("wss://relay.example.com").unwrap();
+        let note = EventBuilder::text_note("quoted")
+            .sign_with_keys(&keys)
+            .unwrap();
+        let (reference, tags) = quote_parts(&note, Some(hint.clone())).unwrap();
+        assert!(reference.starts_with("nostr:nevent1"));
+        let q = tags[0].as_slice();
+        assert_eq!(q[0], "q");
+        assert_eq!(q[1], note.id.to_hex());
+        assert_eq!(q[3], keys.public_key().to_hex());
+        assert_eq!(tags[1], Tag::public_key(keys.public_key()));
+
+        let article = EventBuilder::new(Kind::LongFormTextNote, "body")
+            .tag(Tag::identifier("post"))
+            .sign_with_keys(&keys)
+            .unwrap();
+        let (reference, tags) = quote_parts(&article, None).unwrap();
+        assert!(reference.starts_with("nostr:naddr1"));
+        assert_eq!(
+            tags[0].as_slice()[1],
+            format!("30023:{}:post", keys.public_key().to_hex())
+        );
+    }
+}
@@ -0,0 +1,453 @@
+use crate::keys::KeyStore;
+use crate::nostr_client;
+use crate::pow::{self, Miner};
+use crate::relays::{self, SendResult};
+use crate::settings::SettingsStore;
+use crate::{storage, util};
+use anyhow::{anyhow, bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, OnceCell};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+const SCHEDULER_TICK_SECS: u64 = 15;
+/// A post more than this late when the scheduler reaches it counts as missed.
+const MISSED_GRACE_SECS: u64 = 5 * 60;
+const MAX_PUBLISH_ATTEMPTS: u32 = 5;
+const RETRY_BACKOFF_SECS: u64 = 30;
+
+static SCHEDULE: OnceCell<Arc<ScheduleStore>> = OnceCell::const_new();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedPolicy {
+    /// Publish as soon as possible, however late
+    #[default]
+    PublishLate,
+    /// Mark the post as missed and do not publish it when it could not go
+    /// out within five minutes of `publish_at`
+    Skip,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum ScheduleStatus {
+    Pending,
+    Published { event_id: String, success: Vec<String> },
+    Failed { error: String },
+    Missed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ScheduledPayload {
+    Signed { event: Event },
+    Template { kind: u16, content: String, tags: Vec<Vec<String>>, pow: Option<u8> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledPost {
+    pub id: String,
+    pub pubkey: String,
+    pub publish_at: u64,
+    pub created_at: u64,
+    pub on_missed: MissedPolicy,
+    pub to_relays: Option<Vec<String>>,
+    pub payload: ScheduledPayload,
+    pub status: ScheduleStatus,
+    /// Failed publish attempts so far; the post is retried with backoff
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_attempt_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ScheduleFile {
+    posts: BTreeMap<String, ScheduledPost>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ScheduleEventArgs {
+    /// Unix timestamp at which the event should be published
+    pub publish_at: u64,
+    /// Fully signed event JSON; mutually exclusive with `content`
+    pub event_json: Option<String>,
+    /// Event kind for an unsigned template (default: 1)
+    pub kind: Option<u16>,
+    /// Content for an unsigned template, signed by the active key at publish time
+    pub content: Option<String>,
+    /// Tags for an unsigned template
+    pub tags: Option<Vec<Vec<String>>>,
+    /// Proof of work difficulty for an unsigned template
+    pub pow: Option<u8>,
+    /// Specific relay URLs to publish to
+    pub to_relays: Option<Vec<String>>,
+    /// What to do if the post could not be published on time, e.g. because
+    /// goostr was not running or the key was not active (default: publish_late)
+    pub on_missed: Option<MissedPolicy>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ScheduleListArgs {
+    /// Include published, failed and missed posts (default: false)
+    pub include_finished: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ScheduleCancelArgs {
+    /// Scheduled post ID
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ScheduleRescheduleArgs {
+    /// Scheduled post ID
+    pub id: String,
+    /// New Unix timestamp at which to publish
+    pub publish_at: u64,
+}
+
+pub struct ScheduleStore {
+    path: PathBuf,
+    pass: Vec<u8>,
+    state: Mutex<ScheduleFile>,
+    /// Posts being published right now, so cancelling or rescheduling one
+    /// stops its proof-of-work mining.
+    in_flight: std::sync::Mutex<HashMap<String, CancellationToken>>,
+}
+
+fn retry_backoff_secs(attempts: u32) -> u64 {
+    RETRY_BACKOFF_SECS.saturating_mul(1u64 << attempts.min(16))
+}
+
+/// Whether a post that is still pending at `now` has missed its slot. Only
+/// the first attempt counts: a post being retried after an error is late
+/// because of the retry, not missed.
+fn is_missed(post: &ScheduledPost, now: u64) -> bool {
+    post.attempts == 0 && now.saturating_sub(post.publish_at) > MISSED_GRACE_SECS
+}
+
+fn new_schedule_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("sched-{nanos:x}")
+}
+
+impl ScheduleStore {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let pass = util::ensure_keystore_secret()?;
+        let state = if path.exists() {
+            storage::decrypt_from_file(&path, &pass)?
+        } else {
+            ScheduleFile::default()
+        };
+        Ok(Self {
+            path,
+            pass,
+            state: Mutex::new(state),
+            in_flight: std::sync::Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn persist(&self, state: &ScheduleFile) -> Result<()> {
+        util::ensure_parent_dir(&self.path)?;
+        storage::encrypt_to_file(&self.path, &self.pass, state)
+    }
+
+    pub async fn add(&self, pubkey: &PublicKey, args: ScheduleEventArgs) -> Result<ScheduledPost> {
+        let payload = match (args.event_json, args.content) {
+            (Some(_), Some(_)) => bail!("provide either event_json or content, not both"),
+            (Some(json), None) => {
+                let event = Event::from_json(&json)?;
+                event.verify()?;
+                if event.pubkey != *pubkey {
+                    bail!("signed event author does not match the active key");
+                }
+                ScheduledPayload::Signed { event }
+            }
+            (None, Some(content)) => {
+                let tags = args.tags.unwrap_or_default();
+                for tag in &tags {
+                    Tag::parse(tag.clone())?;
+                }
+                ScheduledPayload::Template {
+                    kind: args.kind.unwrap_or(1),
+                    content,
+                    tags,
+                    pow: args.pow,
+                }
+            }
+            (None, None) => bail!("either event_json or content is required"),
+        };
+        let post = ScheduledPost {
+            id: new_schedule_id(),
+            pubkey: pubkey.to_hex(),
+            publish_at: args.publish_at,
+            created_at: Timestamp::now().as_secs(),
+            on_missed: args.on_missed.unwrap_or_default(),
+            to_relays: args.to_relays,
+            payload,
+            status: ScheduleStatus::Pending,
+            attempts: 0,
+            next_attempt_at: None,
+            last_error: None,
+        };
+        let mut state = self.state.lock().await;
+        state.posts.insert(post.id.clone(), post.clone());
+        self.persist(&state)?;
+        Ok(post)
+    }
+
+    pub async fn list(&self, include_finished: bool) -> Vec<ScheduledPost> {
+        let state = self.state.lock().await;
+        let mut posts: Vec<ScheduledPost> = state
+            .posts
+            .values()
+            .filter(|p| include_finished || p.status == ScheduleStatus::Pending)
+            .cloned()
+            .collect();
+        posts.sort_by_key(|p| p.publish_at);
+        posts
+    }
+
+    fn begin(&self, id: &str) -> CancellationToken {
+        let token = CancellationToken::new();
+        self.in_flight
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), token.clone());
+        token
+    }
+
+    fn finish(&self, id: &str) {
+        self.in_flight.lock().unwrap().remove(id);
+    }
+
+    fn interrupt(&self, id: &str) {
+        if let Some(token) = self.in_flight.lock().unwrap().remove(id) {
+            token.cancel();
+        }
+    }
+
+    pub async fn cancel(&self, id: &str) -> Result<ScheduledPost> {
+        self.interrupt(id);
+        let mut state = self.state.lock().await;
+        let post = state
+            .posts
+            .remove(id)
+            .ok_or_else(|| anyhow!("no scheduled post {id}"))?;
+        self.persist(&state)?;
+        Ok(post)
+    }
+
+    pub async fn reschedule(&self, id: &str, publish_at: u64) -> Result<ScheduledPost> {
+        self.interrupt(id);
+        let mut state = self.state.lock().await;
+        let post = state
+            .posts
+            .get_mut(id)
+            .ok_or_else(|| anyhow!("no scheduled post {id}"))?;
+        if matches!(post.status, ScheduleStatus::Published { .. }) {
+            bail!("scheduled post {id} was already published");
+        }
+        post.publish_at = publish_at;
+        post.status = ScheduleStatus::Pending;
+        post.attempts = 0;
+        post.next_attempt_at = None;
+        post.last_error = None;
+        let post = post.clone();
+        self.persist(&state)?;
+        Ok(post)
+    }
+
+    async fn due(&self, now: u64) -> Vec<ScheduledPost> {
+        let state = self.state.lock().await;
+        state
+            .posts
+            .values()
+            .filter(|p| p.status == ScheduleStatus::Pending && p.publish_at <= now)
+            .filter(|p| p.next_attempt_at.is_none_or(|at| at <= now))
+            .cloned()
+            .collect()
+    }
+
+    /// Records a failed attempt: the post stays pending with exponential
+    /// backoff until it has failed `MAX_PUBLISH_ATTEMPTS` times.
+    async fn record_failure(&self, id: &str, error: String, now: u64) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let Some(post) = state.posts.get_mut(id) else {
+            return Ok(());
+        };
+        post.attempts = post.attempts.saturating_add(1);
+        if post.attempts >= MAX_PUBLISH_ATTEMPTS {
+            post.status = ScheduleStatus::Failed { error };
+            post.next_attempt_at = None;
+        } else {
+            post.last_error = Some(error);
+            post.next_attempt_at = Some(now + retry_backoff_secs(post.attempts));
+        }
+        self.persist(&state)
+    }
+
+    async fn set_status(&self, id: &str, status: ScheduleStatus) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if let Some(post) = state.posts.get_mut(id) {
+            post.status = status;
+            self.persist(&state)?;
+        }
+        Ok(())
+    }
+}
+
+pub async fn shared() -> Result<Arc<ScheduleStore>> {
+    SCHEDULE
+        .get_or_try_init(|| async { ScheduleStore::load(util::nostr_schedule_path()).map(Arc::new) })
+        .await
+        .cloned()
+}
+
+pub async fn schedule(client: &Client, args: ScheduleEventArgs) -> Result<ScheduledPost> {
+    let pubkey = client.signer().await?.get_public_key().await?;
+    shared().await?.add(&pubkey, args).await
+}
+
+async fn publish_post(
+    client: &Client,
+    post: &ScheduledPost,
+    cancel: CancellationToken,
+) -> Result<SendResult> {
+    match &post.payload {
+        ScheduledPayload::Signed { event } => {
+            relays::publish_event(client, event, post.to_relays.clone()).await
+        }
+        ScheduledPayload::Template { kind, content, tags, pow } => {
+            let tags = tags
+                .iter()
+                .map(|t| Tag::parse(t.clone()))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let builder = EventBuilder::new(Kind::from(*kind), content.clone()).tags(tags);
+            let event = pow::sign_mined(
+                client,
+                &Miner::background(cancel),
+                builder,
+                *pow,
+                post.to_relays.as_deref(),
+            )
+            .await?;
+            relays::publish_event(client, &event, post.to_relays.clone()).await
+        }
+    }
+}
+
+/// Publishes every pending post whose time has come. Posts for other keys
+/// wait until their key is active again; posts that are past the grace
+/// window by then follow their `on_missed` policy. Relay-level failures are
+/// left to the outbox, while errors before anything was sent are retried
+/// with backoff.
+async fn run_due(client: &Client) -> Result<()> {
+    let store = shared().await?;
+    let signer = client.signer().await?.get_public_key().await?.to_hex();
+    let now = Timestamp::now().as_secs();
+    for post in store.due(now).await {
+        if post.pubkey != signer {
+            continue;
+        }
+        if post.on_missed == MissedPolicy::Skip && is_missed(&post, now) {
+            info!(id = %post.id, "skipping missed scheduled post");
+            store.set_status(&post.id, ScheduleStatus::Missed).await?;
+            continue;
+        }
+        let cancel = store.begin(&post.id);
+        let published = publish_post(client, &post, cancel.clone()).await;
+        store.finish(&post.id);
+        match published {
+            Err(_) if cancel.is_cancelled() => {
+                info!(id = %post.id, "scheduled post changed while publishing");
+            }
+            Ok(result) => {
+                let status = ScheduleStatus::Published {
+                    event_id: result.id,
+                    success: result.success,
+                };
+                store.set_status(&post.id, status).await?;
+            }
+            Err(e) => {
+                warn!(id = %post.id, error = %e, "scheduled post failed");
+                store.record_failure(&post.id, e.to_string(), now).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn run_scheduler(keystore: Arc<KeyStore>, settings: Arc<SettingsStore>) {
+    let mut tick = tokio::time::interval(Duration::from_secs(SCHEDULER_TICK_SECS));
+    loop {
+        tick.tick().await;
+        let active = match nostr_client::ensure_client(keystore.clone(), settings.clone()).await {
+            Ok(active) => active,
+            Err(_) => continue,
+        };
+        if let Err(e) = run_due(&active.client).await {
+            warn!(error = %e, "scheduler pass failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(publish_at: u64, attempts: u32) -> ScheduledPost {
+        ScheduledPost {
+            id: new_schedule_id(),
+            pubkey: Keys::generate().public_key().to_hex(),
+            publish_at,
+            created_at: publish_at,
+            on_missed: MissedPolicy::Skip,
+            to_relays: None,
+            payload: ScheduledPayload::Template {
+                kind: 1,
+                content: "gm".to_string(),
+                tags: Vec::new(),
+                pow: None,
+            },
+            status: ScheduleStatus::Pending,
+            attempts,
+            next_attempt_at: None,
+            last_error: None,
+        }
+    }
+
+    #[test]
+    fn missed_only_past_grace_window() {
+        let post = pending(1_000, 0);
+        assert!(!is_missed(&post, 1_000));
+        assert!(!is_missed(&post, 1_000 + MISSED_GRACE_SECS));
+        assert!(is_missed(&post, 1_001 + MISSED_GRACE_SECS));
+    }
+
+    #[test]
+    fn retried_posts_are_not_missed() {
+        let post = pending(1_000, 2);
+        assert!(!is_missed(&post, 1_000 + 10 * MISSED_GRACE_SECS));
+    }
+
+    #[test]
+    fn retry_backoff_doubles() {
+        assert_eq!(retry_backoff_secs(1), 60);
+        assert_eq!(retry_backoff_secs(2), 120);
+        assert_eq!(retry_backoff_secs(4), 480);
+    }
+}
@@ -0,0 +1,387 @@
+use crate::nip11;
+use crate::profiles;
+use crate::relays;
+use anyhow::{bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+const DEFAULT_LIMIT: usize = 20;
+const LOCAL_SCAN_LIMIT: usize = 5000;
+const SNIPPET_RADIUS: usize = 60;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SearchEventsArgs {
+    /// Full-text search query
+    pub query: String,
+    /// Extra NIP-01 constraints (kinds, authors, since...) applied alongside the search
+    pub filter: Option<serde_json::Value>,
+    /// Maximum number of results (default: 20)
+    pub limit: Option<usize>,
+    /// Relays to consider (default: all configured relays)
+    pub relays: Option<Vec<String>>,
+    /// Skip relays and search only the local event cache
+    pub local_only: Option<bool>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchSource {
+    Relays,
+    Local,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub score: f64,
+    pub snippet: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    pub event: Event,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub source: SearchSource,
+    pub relays_used: Vec<String>,
+    pub hits: Vec<SearchHit>,
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '#' && c != '@')
+        .map(|t| t.trim_start_matches(['#', '@']).to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// Inverted index over event content, built on demand from the local cache.
+pub struct SearchIndex {
+    events: Vec<Event>,
+    postings: HashMap<String, Vec<(usize, u32)>>,
+    lengths: Vec<usize>,
+}
+
+impl SearchIndex {
+    pub fn build(events: Vec<Event>) -> Self {
+        let mut postings: HashMap<String, Vec<(usize, u32)>> = HashMap::new();
+        let mut lengths = Vec::with_capacity(events.len());
+        for (i, event) in events.iter().enumerate() {
+            let tokens = tokenize(&event.content);
+            lengths.push(tokens.len());
+            let mut tf: HashMap<String, u32> = HashMap::new();
+            for token in tokens {
+                *tf.entry(token).or_default() += 1;
+            }
+            for (token, n) in tf {
+                postings.entry(token).or_default().push((i, n));
+            }
+        }
+        Self {
+            events,
+            postings,
+            lengths,
+        }
+    }
+
+    /// BM25-style ranking; every query term must appear. Newer events win ties.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<(f64, &Event)> {
+        let terms: HashSet<String> = tokenize(query).into_iter().collect();
+        if terms.is_empty() || self.events.is_empty() {
+            return Vec::new();
+        }
+        let n = self.events.len() as f64;
+        let avg_len = self.lengths.iter().sum::<usize>() as f64 / n;
+        let mut scores: HashMap<usize, (f64, usize)> = HashMap::new();
+        for term in &terms {
+            let Some(list) = self.postings.get(term) else {
+                return Vec::new();
+            };
+            let idf = ((n - list.len() as f64 + 0.5) / (list.len() as f64 + 0.5) + 1.0).ln();
+            for &(doc, tf) in list {
+                let tf = tf as f64;
+                let len = self.lengths[doc] as f64;
+                let score = idf * (tf * 2.2) / (tf + 1.2 * (0.25 + 0.75 * len / avg_len.max(1.0)));
+                let entry = scores.entry(doc).or_default();
+                entry.0 += score;
+                entry.1 += 1;
+            }
+        }
+        let mut ranked: Vec<(f64, &Event)> = scores
+            .into_iter()
+            .filter(|(_, (_, matched))| *matched == terms.len())
+            .map(|(doc, (score, _))| (score, &self.events[doc]))
+            .collect();
+        ranked.sort_by(|a, b| {
+            b.0.partial_cmp(&a.0)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(b.1.created_at.cmp(&a.1.created_at))
+        });
+        ranked.truncate(limit);
+        ranked
+    }
+}
+
+/// Byte offset in `content` of the first case-insensitive match of the
+/// lowercase `term`. Lowercasing can change a string's byte length, so the
+/// match is made against `content` itself rather than a lowercased copy.
+fn find_ignore_case(content: &str, term: &str) -> Option<usize> {
+    content.char_indices().map(|(i, _)| i).find(|&i| {
+        let mut rest = content[i..].chars().flat_map(char::to_lowercase);
+        term.chars().all(|t| rest.next() == Some(t))
+    })
+}
+
+/// Cuts a window of content around the first query term and wraps every
+/// matching word in `**`.
+pub fn snippet(content: &str, query: &str) -> String {
+    let terms: HashSet<String> = tokenize(query).into_iter().collect();
+    let first = terms
+        .iter()
+        .filter_map(|t| find_ignore_case(content, t))
+        .min()
+        .unwrap_or(0);
+    let floor = |mut i: usize| {
+        while !content.is_char_boundary(i) {
+            i -= 1;
+        }
+        i
+    };
+    let start = floor(first.saturating_sub(SNIPPET_RADIUS).min(content.len()));
+    let end = floor((first + SNIPPET_RADIUS * 2).min(content.len()));
+    let window = &content[start..end];
+
+    let mut out = String::with_capacity(window.len() + 16);
+    if start > 0 {
+        out.push('…');
+    }
+    let mut word = String::new();
+    let flush = |word: &mut String, out: &mut String| {
+        if terms.contains(&word.to_lowercase()) {
+            out.push_str("**");
+            out.push_str(word);
+            out.push_str("**");
+        } else {
+            out.push_str(word);
+        }
+        word.clear();
+    };
+    for c in window.chars() {
+        if c.is_alphanumeric() {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut out);
+            out.push(c);
+        }
+    }
+    flush(&mut word, &mut out);
+    if end < content.len() {
+        out.push('…');
+    }
+    out
+}
+
+fn base_filter(args: &SearchEventsArgs) -> Result<Filter> {
+    match &args.filter {
+        Some(value) => Ok(Filter::from_json(value.to_string())?),
+        None => Ok(Filter::new().kind(Kind::TextNote)),
+    }
+}
+
+async fn search_capable_relays(client: &Client, relays: Option<Vec<String>>) -> Vec<String> {
+    let candidates = match relays {
+        Some(r) if !r.is_empty() => r,
+        _ => relays::get_relay_urls(client).await,
+    };
+    let mut capable = Vec::new();
+    for relay in candidates {
+        if nip11::supports_nip(&relay, 50).await {
+            capable.push(relay);
+        }
+    }
+    capable
+}
+
+/// Events from the local cache to rank when no relay can search. The cache
+/// has no full-text index, so the `search` field is dropped and the newest
+/// matches are scanned instead.
+async fn local_candidates(client: &Client, filter: &Filter) -> Result<Vec<Event>> {
+    let mut local = filter.clone();
+    local.search = None;
+    let events = client.database().query(local.limit(LOCAL_SCAN_LIMIT)).await?;
+    Ok(events.into_iter().collect())
+}
+
+/// Sends a NIP-50 search to relays that advertise it and falls back to the
+/// local event cache when none do. Both paths drop events the user shouldn't
+/// see and are ranked by the same scorer.
+pub async fn search(client: &Client, args: SearchEventsArgs) -> Result<SearchResult> {
+    if args.query.trim().is_empty() {
+        bail!("query must not be empty");
+    }
+    let limit = args.limit.unwrap_or(DEFAULT_LIMIT).max(1);
+    let filter = base_filter(&args)?;
+    let timeout = Duration::from_secs(args.timeout_secs.unwrap_or(10));
+
+    let relays_used = if args.local_only.unwrap_or(false) {
+        Vec::new()
+    } else {
+        search_capable_relays(client, args.relays.clone()).await
+    };
+
+    let (source, candidates) = if relays_used.is_empty() {
+        (SearchSource::Local, local_candidates(client, &filter).await?)
+    } else {
+        let remote = filter.clone().search(args.query.clone()).limit(limit * 2);
+        let events = client
+            .fetch_events_from(relays_used.clone(), remote, timeout)
+            .await?;
+        (SearchSource::Relays, events.into_iter().collect::<Vec<_>>())
+    };
+
+    // Expired, deleted and muted events must not take a ranked slot.
+    let candidates = relays::visible_events(client, candidates).await;
+    let index = SearchIndex::build(candidates);
+    let mut hits: Vec<SearchHit> = index
+        .search(&args.query, limit)
+        .into_iter()
+        .map(|(score, event)| SearchHit {
+            score,
+            snippet: snippet(&event.content, &args.query),
+            author_name: None,
+            event: event.clone(),
+        })
+        .collect();
+
+    // Relays may match on fields other than content (e.g. tags); keep those
+    // results too, unranked, after the scored ones.
+    if let SearchSource::Relays = source {
+        let scored: HashSet<EventId> = hits.iter().map(|h| h.event.id).collect();
+        for event in index.events.iter().filter(|e| !scored.contains(&e.id)) {
+            if hits.len() >= limit {
+                break;
+            }
+            hits.push(SearchHit {
+                score: 0.0,
+                snippet: snippet(&event.content, &args.query),
+                author_name: None,
+                event: event.clone(),
+            });
+        }
+    }
+
+    let names = profiles::display_names(
+        client,
+        hits.iter().map(|h| h.event.pubkey),
+        args.timeout_secs.unwrap_or(10),
+    )
+    .await?;
+    for hit in &mut hits {
+        hit.author_name = names.get(&hit.event.pubkey).cloned();
+    }
+
+    Ok(SearchResult {
+        source,
+        relays_used,
+        hits,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippet_offsets_survive_case_folding() {
+        // 'İ' lowercases to two chars, which shifted offsets into the wrong
+        // place when searching a lowercased copy
+        let content = format!("{}needle at the end", "İ ".repeat(200));
+        let out = snippet(&content, "needle");
+        assert!(out.contains("**needle**"), "{out}");
+        assert_eq!(find_ignore_case("Hello World", "world"), Some(6));
+        assert_eq!(find_ignore_case("Hello", "bye"), None);
+    }
+
+    fn note(keys: &Keys, content: &str, at: u64) -> Event {
+        EventBuilder::text_note(content)
+            .custom_created_at(Timestamp::from(at))
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn ranking_needs_every_term_and_prefers_denser_matches() {
+        let keys = Keys::generate();
+        let index = SearchIndex::build(vec![
+            note(&keys, "rust rust rust nostr", 1),
+            note(
+                &keys,
+                "a long note that mentions rust once and nostr once among many other words",
+                2,
+            ),
+            note(&keys, "only rust here", 3),
+            note(&keys, "#Nostr and @rust tags count too", 4),
+        ]);
+        let hits = index.search("Rust nostr", 10);
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].1.content, "rust rust rust nostr");
+        assert!(hits.iter().all(|(_, e)| e.content != "only rust here"));
+        assert!(index.search("rust absent", 10).is_empty());
+        assert!(index.search("   ", 10).is_empty());
+        assert_eq!(index.search("rust", 2).len(), 2);
+    }
+
+    #[test]
+    fn ties_go_to_the_newer_event() {
+        let keys = Keys::generate();
+        let index =
+            SearchIndex::build(vec![note(&keys, "same text", 1), note(&keys, "same text", 5)]);
+        let hits = index.search("text", 10);
+        assert_eq!(hits[0].1.created_at.as_secs(), 5);
+    }
+
+    #[test]
+    fn snippet_marks_terms_and_trims_long_content() {
+        assert_eq!(snippet("Hello World", "world"), "Hello **World**");
+        let content = format!("{} needle {}", "a".repeat(200), "b".repeat(200));
+        let out = snippet(&content, "needle");
+        assert!(out.starts_with('…') && out.ends_with('…'), "{out}");
+        assert!(out.contains("**needle**"));
+        assert!(out.chars().count() < content.chars().count());
+    }
+
+    #[tokio::test]
+    async fn local_fallback_scans_the_cache_with_the_base_filter() {
+        let keys = Keys::generate();
+        let database = MemoryDatabase::with_opts(MemoryDatabaseOptions {
+            events: true,
+            ..Default::default()
+        });
+        let client = Client::builder().database(database).build();
+        let text = note(&keys, "cached needle", 10);
+        let article = EventBuilder::long_form_text_note("needle in an article")
+            .sign_with_keys(&keys)
+            .unwrap();
+        client.database().save_event(&text).await.unwrap();
+        client.database().save_event(&article).await.unwrap();
+
+        let args = SearchEventsArgs {
+            query: "needle".to_string(),
+            filter: None,
+            limit: None,
+            relays: None,
+            local_only: Some(true),
+            timeout_secs: None,
+        };
+        let filter = base_filter(&args).unwrap().search("needle");
+        let candidates = local_candidates(&client, &filter).await.unwrap();
+        assert_eq!(candidates.len(), 1);
+        let index = SearchIndex::build(candidates);
+        let hits = index.search(&args.query, 10);
+        assert_eq!(hits[0].1.id, text.id);
+        assert_eq!(snippet(&hits[0].1.content, &args.query), "cached **needle**");
+    }
+}
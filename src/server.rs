@@ -1,3 +1,1143 @@
+use crate::articles::{self, ArticleGetArgs, ArticleListArgs, ArticlePublishArgs};
+use crate::cashu::{
+    self, CashuWalletArgs, CashuWalletSetupArgs, NutzapReceiveArgs, NutzapSendArgs,
+};
+use crate::content::{self, PostNoteArgs};
+use crate::count::{self, CountEventsArgs};
+use crate::deletions::{self, DeleteEventsArgs};
+use crate::error::GoostrError;
+use crate::feed::{self, FeedHomeArgs};
+use crate::follow_guard::{self, FollowsRollbackArgs, FollowsSnapshotsArgs};
+use crate::follow_lists::{
+    self, FollowsAddArgs, FollowsCopyArgs, FollowsExportArgs, FollowsImportArgs,
+    FollowsRemoveArgs, FollowsSetArgs,
+};
+use crate::groups::{self, GroupMembershipArgs, GroupMessagesArgs, GroupsListArgs};
+use crate::keys::{self, KeyStore};
+use crate::nostr_client::{self, ActiveClient};
+use crate::lists::{self, ListEditArgs, ListGetArgs, ListSetsArgs};
+use crate::mint::HttpMints;
+use crate::nip05::{self, Nip05ResolveArgs, Nip05VerifyArgs};
+use crate::notifications::{self, NotificationsArgs, NotificationsMarkReadArgs};
+use crate::nwc::{
+    self, NwcBalanceArgs, NwcConnectArgs, NwcListTransactionsArgs, NwcMakeInvoiceArgs,
+    NwcPayInvoiceArgs, NwcPolicyArgs,
+};
+use crate::outbox::{self, OutboxDropArgs, OutboxListArgs, OutboxRetryArgs};
+use crate::pagination::{self, ListEventsArgs, QueryEventsArgs};
+use crate::pow::{self, Miner, PowMineArgs};
+use crate::profiles::{self, FetchManyArgs, FetchProfileArgs};
+use crate::progress::Progress;
+use crate::rebroadcast::{self, RebroadcastArgs};
+use crate::relays::{
+    self, CreatePollArgs, PostCommentArgs, PostGroupChatArgs, PostReactionArgs, PostReplyArgs,
+    PostThreadArgs, Published,
+};
+use crate::repost::{self, RepostArgs};
+use crate::schedule::{
+    self, ScheduleCancelArgs, ScheduleEventArgs, ScheduleListArgs, ScheduleRescheduleArgs,
+};
+use crate::search::{self, SearchEventsArgs};
+use crate::settings::{self, SettingsStore};
+use crate::sync::{self, SyncArgs};
+use crate::thread::{self, ThreadGetArgs};
+use crate::util;
+use crate::zaps::{self, ZapReceiptsArgs, ZapRequestArgs};
+use nostr_mcp_tools::server::NostrMcpServer;
+use rmcp::handler::server::router::tool::ToolRouter;
+use rmcp::handler::server::tool::ToolCallContext;
+use rmcp::handler::server::wrapper::Parameters;
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, Content, ListToolsResult, PaginatedRequestParam,
+    ServerInfo,
+};
+use rmcp::service::RequestContext;
+use rmcp::{tool, tool_router, ErrorData, RoleServer, ServerHandler, ServiceExt};
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::info;
+
+/// Goostr's MCP server: the upstream nostr-mcp tool set plus the tools that
+/// only ship with goostr. A goostr tool with the same name as an upstream one
+/// replaces it; every other call is forwarded to the upstream server unchanged.
+pub struct GoostrServer {
+    upstream: NostrMcpServer,
+    keystore: Arc<KeyStore>,
+    settings: Arc<SettingsStore>,
+    tool_router: ToolRouter<Self>,
+}
+
+fn json_result<T: Serialize>(value: &T) -> Result<CallToolResult, ErrorData> {
+    Ok(CallToolResult::success(vec![Content::json(value)?]))
+}
+
+impl GoostrServer {
+    pub fn new(keystore: Arc<KeyStore>, settings: Arc<SettingsStore>) -> Self {
+        Self {
+            upstream: NostrMcpServer::new(keystore.clone(), settings.clone()),
+            keystore,
+            settings,
+            tool_router: Self::outbox_router()
+                + Self::schedule_router()
+                + Self::rebroadcast_router()
+                + Self::sync_router()
+                + Self::query_router()
+                + Self::count_router()
+                + Self::search_router()
+                + Self::thread_router()
+                + Self::notifications_router()
+                + Self::feed_router()
+                + Self::profiles_router()
+                + Self::nip05_router()
+                + Self::follow_lists_router()
+                + Self::lists_router()
+                + Self::articles_router()
+                + Self::repost_router()
+                + Self::deletions_router()
+                + Self::content_router()
+                + Self::publish_router()
+                + Self::pow_router()
+                + Self::zaps_router()
+                + Self::nwc_router()
+                + Self::cashu_router()
+                + Self::groups_router(),
+        }
+    }
+
+    async fn active_client(&self) -> Result<ActiveClient, GoostrError> {
+        nostr_client::ensure_client(self.keystore.clone(), self.settings.clone()).await
+    }
+}
+
+#[tool_router(router = outbox_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_outbox_list",
+        description = "List events whose delivery failed on some relays and are queued for retry"
+    )]
+    async fn outbox_list(
+        &self,
+        Parameters(args): Parameters<OutboxListArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let outbox = outbox::shared().await.map_err(GoostrError::from)?;
+        let rows = outbox.list(args.include_event.unwrap_or(false)).await;
+        json_result(&rows)
+    }
+
+    #[tool(
+        name = "nostr_outbox_retry",
+        description = "Retry queued relay deliveries now, ignoring backoff for the selected entry"
+    )]
+    async fn outbox_retry(
+        &self,
+        Parameters(args): Parameters<OutboxRetryArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let report = outbox::retry(&active.client, args.event_id.as_deref())
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&report)
+    }
+
+    #[tool(
+        name = "nostr_outbox_drop",
+        description = "Stop retrying an outbox entry, or only some of its relays"
+    )]
+    async fn outbox_drop(
+        &self,
+        Parameters(args): Parameters<OutboxDropArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let outbox = outbox::shared().await.map_err(GoostrError::from)?;
+        let result = outbox.drop_entry(args).await.map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = schedule_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_events_schedule",
+        description = "Queue a signed event or an unsigned template to be published at a given time"
+    )]
+    async fn events_schedule(
+        &self,
+        Parameters(args): Parameters<ScheduleEventArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let post = schedule::schedule(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&post)
+    }
+
+    #[tool(
+        name = "nostr_events_schedule_list",
+        description = "List scheduled posts, ordered by publish time"
+    )]
+    async fn events_schedule_list(
+        &self,
+        Parameters(args): Parameters<ScheduleListArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let store = schedule::shared().await.map_err(GoostrError::from)?;
+        let posts = store.list(args.include_finished.unwrap_or(false)).await;
+        json_result(&posts)
+    }
+
+    #[tool(
+        name = "nostr_events_schedule_cancel",
+        description = "Cancel a scheduled post"
+    )]
+    async fn events_schedule_cancel(
+        &self,
+        Parameters(args): Parameters<ScheduleCancelArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let store = schedule::shared().await.map_err(GoostrError::from)?;
+        let post = store.cancel(&args.id).await.map_err(GoostrError::from)?;
+        json_result(&post)
+    }
+
+    #[tool(
+        name = "nostr_events_schedule_reschedule",
+        description = "Move a scheduled, failed or missed post to a new publish time"
+    )]
+    async fn events_schedule_reschedule(
+        &self,
+        Parameters(args): Parameters<ScheduleRescheduleArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let store = schedule::shared().await.map_err(GoostrError::from)?;
+        let post = store
+            .reschedule(&args.id, args.publish_at)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&post)
+    }
+}
+
+#[tool_router(router = rebroadcast_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_events_rebroadcast",
+        description = "Copy events matching a filter or ID list from current relays to target relays, unchanged"
+    )]
+    async fn events_rebroadcast(
+        &self,
+        Parameters(args): Parameters<RebroadcastArgs>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let progress = Progress::from_context(&context);
+        let result = rebroadcast::rebroadcast(&active.client, args, &progress)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = sync_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_events_sync",
+        description = "Reconcile the local event cache with relays using NIP-77 negentropy, falling back to paginated REQs"
+    )]
+    async fn events_sync(
+        &self,
+        Parameters(args): Parameters<SyncArgs>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let progress = Progress::from_context(&context);
+        let result = sync::sync(&active.client, args, &progress)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = query_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_events_list",
+        description = "Fetch events using presets (my_notes, mentions_me, my_metadata, by_author, by_kind), leaving out deleted, expired and muted events"
+    )]
+    async fn events_list(
+        &self,
+        Parameters(args): Parameters<ListEventsArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let events = pagination::list_events(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&events)
+    }
+
+    #[tool(
+        name = "nostr_events_query",
+        description = "Query events using one or more NIP-01 filters, a page at a time with a continuation cursor"
+    )]
+    async fn events_query(
+        &self,
+        Parameters(args): Parameters<QueryEventsArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let page = pagination::query_page(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&page)
+    }
+}
+
+#[tool_router(router = count_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_events_count",
+        description = "Count events matching a filter with NIP-45 COUNT, falling back to fetch-and-count per relay"
+    )]
+    async fn events_count(
+        &self,
+        Parameters(args): Parameters<CountEventsArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = count::count_events(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = search_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_events_search",
+        description = "Full-text search via NIP-50 relays, or the local event cache when no relay supports search"
+    )]
+    async fn events_search(
+        &self,
+        Parameters(args): Parameters<SearchEventsArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = search::search(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = thread_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_thread_get",
+        description = "Reconstruct a whole conversation (NIP-10 replies and NIP-22 comments) as a nested tree from any event in it"
+    )]
+    async fn thread_get(
+        &self,
+        Parameters(args): Parameters<ThreadGetArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = thread::get_thread(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = notifications_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_notifications",
+        description = "Digest of replies, mentions, reactions, reposts and zaps for the active key since it last marked them read"
+    )]
+    async fn notifications(
+        &self,
+        Parameters(args): Parameters<NotificationsArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let digest = notifications::digest(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&digest)
+    }
+
+    #[tool(
+        name = "nostr_notifications_mark_read",
+        description = "Mark all notifications for the active key as read up to a time (default: now)"
+    )]
+    async fn notifications_mark_read(
+        &self,
+        Parameters(args): Parameters<NotificationsMarkReadArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let seen_at = notifications::mark_read(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&serde_json::json!({ "notifications_seen_at": seen_at }))
+    }
+}
+
+#[tool_router(router = feed_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_feed_home",
+        description = "Home timeline of notes, reposts and articles from the accounts the active key follows"
+    )]
+    async fn feed_home(
+        &self,
+        Parameters(args): Parameters<FeedHomeArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = feed::home(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = profiles_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_metadata_fetch",
+        description = "Fetch the kind 0 profile for a public key (default: active key), using the profile cache"
+    )]
+    async fn metadata_fetch(
+        &self,
+        Parameters(args): Parameters<FetchProfileArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let row = profiles::fetch_one(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&row)
+    }
+
+    #[tool(
+        name = "nostr_metadata_fetch_many",
+        description = "Fetch kind 0 profiles for many public keys in one request, using the profile cache"
+    )]
+    async fn metadata_fetch_many(
+        &self,
+        Parameters(args): Parameters<FetchManyArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let rows = profiles::fetch_many(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&rows)
+    }
+}
+
+#[tool_router(router = nip05_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_nip05_resolve",
+        description = "Resolve a NIP-05 identifier (name@domain) to a public key and relay hints"
+    )]
+    async fn nip05_resolve(
+        &self,
+        Parameters(args): Parameters<Nip05ResolveArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let resolution = nip05::resolve(&args.identifier)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&resolution)
+    }
+
+    #[tool(
+        name = "nostr_nip05_verify",
+        description = "Check that a profile's NIP-05 identifier really points back to its public key"
+    )]
+    async fn nip05_verify(
+        &self,
+        Parameters(args): Parameters<Nip05VerifyArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let verification = nip05::verify(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&verification)
+    }
+}
+
+#[tool_router(router = follow_lists_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_follows_set",
+        description = "Set the kind 3 follow list; with dry_run, preview additions, removals and petname/relay changes without publishing"
+    )]
+    async fn follows_set(
+        &self,
+        Parameters(args): Parameters<FollowsSetArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = follow_lists::set_follows(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_follows_add",
+        description = "Follow a public key by publishing an updated kind 3, guarded against stale lists"
+    )]
+    async fn follows_add(
+        &self,
+        Parameters(args): Parameters<FollowsAddArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = follow_lists::add_follow(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_follows_remove",
+        description = "Unfollow a public key by publishing an updated kind 3, guarded against stale lists"
+    )]
+    async fn follows_remove(
+        &self,
+        Parameters(args): Parameters<FollowsRemoveArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = follow_lists::remove_follow(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_follows_export",
+        description = "Export a follow list as JSON or CSV"
+    )]
+    async fn follows_export(
+        &self,
+        Parameters(args): Parameters<FollowsExportArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let export = follow_lists::export(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&export)
+    }
+
+    #[tool(
+        name = "nostr_follows_import",
+        description = "Import a JSON or CSV follow list, previewing the diff unless dry_run is false"
+    )]
+    async fn follows_import(
+        &self,
+        Parameters(args): Parameters<FollowsImportArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = follow_lists::import(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_follows_copy",
+        description = "Use another user's follow list as a starting point, previewing the diff unless dry_run is false"
+    )]
+    async fn follows_copy(
+        &self,
+        Parameters(args): Parameters<FollowsCopyArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = follow_lists::copy(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_follows_snapshots",
+        description = "List local snapshots of the active key's kind 3 follow list, newest first"
+    )]
+    async fn follows_snapshots(
+        &self,
+        Parameters(_args): Parameters<FollowsSnapshotsArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let rows = follow_guard::snapshots(&active.client)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&rows)
+    }
+
+    #[tool(
+        name = "nostr_follows_rollback",
+        description = "Republish an earlier follow list snapshot as the current kind 3"
+    )]
+    async fn follows_rollback(
+        &self,
+        Parameters(args): Parameters<FollowsRollbackArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = follow_guard::rollback(&active.client, &args.snapshot_id)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = lists_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_lists_get",
+        description = "Read a NIP-51 list (mute, pins, bookmarks, blocked relays, follow or relay set), including private items for the active key"
+    )]
+    async fn lists_get(
+        &self,
+        Parameters(args): Parameters<ListGetArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let contents = lists::get_list(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&contents)
+    }
+
+    #[tool(
+        name = "nostr_lists_edit",
+        description = "Add or remove items in one of the active key's NIP-51 lists, optionally as private items encrypted to self"
+    )]
+    async fn lists_edit(
+        &self,
+        Parameters(args): Parameters<ListEditArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = lists::edit_list(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_lists_sets",
+        description = "List the identifiers and titles of a key's follow sets or relay sets"
+    )]
+    async fn lists_sets(
+        &self,
+        Parameters(args): Parameters<ListSetsArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let rows = lists::list_sets(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&rows)
+    }
+}
+
+#[tool_router(router = articles_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_articles_publish",
+        description = "Create or update (same identifier) a NIP-23 long-form Markdown article, or save it as a draft; returns the naddr"
+    )]
+    async fn articles_publish(
+        &self,
+        Parameters(args): Parameters<ArticlePublishArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = articles::publish(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_articles_list",
+        description = "List long-form articles or drafts by an author, newest first"
+    )]
+    async fn articles_list(
+        &self,
+        Parameters(args): Parameters<ArticleListArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let rows = articles::list(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&rows)
+    }
+
+    #[tool(
+        name = "nostr_articles_get",
+        description = "Fetch a long-form article by naddr or author and identifier"
+    )]
+    async fn articles_get(
+        &self,
+        Parameters(args): Parameters<ArticleGetArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let article = articles::get(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&article)
+    }
+}
+
+#[tool_router(router = repost_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_events_repost",
+        description = "Repost an event (NIP-18): kind 6 for text notes, kind 16 for other kinds"
+    )]
+    async fn events_repost(
+        &self,
+        Parameters(args): Parameters<RepostArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = repost::repost(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = deletions_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_events_delete",
+        description = "Request deletion (NIP-09 kind 5) of events or addressable events authored by the active key"
+    )]
+    async fn events_delete(
+        &self,
+        Parameters(args): Parameters<DeleteEventsArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = deletions::delete_events(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = content_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_events_post_text",
+        description = "Post a kind=1 text note, optionally quoting another event (NIP-18), deriving hashtag, mention, quote and media tags from the content"
+    )]
+    async fn events_post_text(
+        &self,
+        Parameters(args): Parameters<PostNoteArgs>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let miner = Miner::from_context(&context);
+        let result = content::post_note(&active.client, &miner, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = publish_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_events_post_reply",
+        description = "Reply to an event: NIP-10 kind 1 for text notes, NIP-22 kind 1111 for other kinds"
+    )]
+    async fn events_post_reply(
+        &self,
+        Parameters(args): Parameters<Published<PostReplyArgs>>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let miner = Miner::from_context(&context);
+        let result = relays::post_reply(&active.client, &miner, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_events_post_comment",
+        description = "Post a kind=1111 comment (NIP-22) on any event"
+    )]
+    async fn events_post_comment(
+        &self,
+        Parameters(args): Parameters<Published<PostCommentArgs>>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let miner = Miner::from_context(&context);
+        let result = relays::post_comment(&active.client, &miner, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_events_post_reaction",
+        description = "Post a kind=7 reaction (NIP-25) to another event"
+    )]
+    async fn events_post_reaction(
+        &self,
+        Parameters(args): Parameters<PostReactionArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = relays::post_reaction(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_events_post_thread",
+        description = "Post a kind=11 thread (NIP-7D) with a title"
+    )]
+    async fn events_post_thread(
+        &self,
+        Parameters(args): Parameters<Published<PostThreadArgs>>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let miner = Miner::from_context(&context);
+        let result = relays::post_thread(&active.client, &miner, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_events_post_group_chat",
+        description = "Post a kind=9 chat message to a NIP-29 group"
+    )]
+    async fn events_post_group_chat(
+        &self,
+        Parameters(args): Parameters<Published<PostGroupChatArgs>>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let miner = Miner::from_context(&context);
+        let result = relays::post_group_chat(&active.client, &miner, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_events_create_poll",
+        description = "Create a kind=1068 poll (NIP-88)"
+    )]
+    async fn events_create_poll(
+        &self,
+        Parameters(args): Parameters<Published<CreatePollArgs>>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let miner = Miner::from_context(&context);
+        let result = relays::create_poll(&active.client, &miner, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = pow_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_pow_mine",
+        description = "Mine NIP-13 proof of work for an event template on worker threads, then sign and optionally publish it"
+    )]
+    async fn pow_mine(
+        &self,
+        Parameters(args): Parameters<PowMineArgs>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let miner = Miner::from_context(&context);
+        let result = pow::mine_template(&active.client, &miner, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = zaps_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_zaps_request",
+        description = "Build and sign a NIP-57 zap request for a profile or event and fetch the invoice from the recipient's LNURL server"
+    )]
+    async fn zaps_request(
+        &self,
+        Parameters(args): Parameters<ZapRequestArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = zaps::zap_request(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_zaps_receipts",
+        description = "Fetch and verify zap receipts (kind 9735) and total valid zaps per event"
+    )]
+    async fn zaps_receipts(
+        &self,
+        Parameters(args): Parameters<ZapReceiptsArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = zaps::zap_receipts(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = nwc_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_nwc_connect",
+        description = "Store a nostr+walletconnect:// connection string in the secret store"
+    )]
+    async fn nwc_connect(
+        &self,
+        Parameters(args): Parameters<NwcConnectArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let connection = nwc::connect(args).map_err(GoostrError::from)?;
+        json_result(&connection)
+    }
+
+    #[tool(name = "nostr_nwc_disconnect", description = "Forget the stored wallet connection")]
+    async fn nwc_disconnect(&self) -> Result<CallToolResult, ErrorData> {
+        nwc::disconnect().map_err(GoostrError::from)?;
+        json_result(&serde_json::json!({ "disconnected": true }))
+    }
+
+    #[tool(
+        name = "nostr_nwc_status",
+        description = "Show the connected wallet service, if any"
+    )]
+    async fn nwc_status(&self) -> Result<CallToolResult, ErrorData> {
+        let connection = nwc::status().map_err(GoostrError::from)?;
+        json_result(&connection)
+    }
+
+    #[tool(
+        name = "nostr_nwc_policy",
+        description = "Show the wallet spending policy set by the operator, or lower the daily cap until goostr restarts"
+    )]
+    async fn nwc_policy(
+        &self,
+        Parameters(args): Parameters<NwcPolicyArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let result = nwc::policy(args).await.map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_nwc_pay_invoice",
+        description = "Pay a BOLT11 invoice through the connected NWC wallet, subject to the spending policy"
+    )]
+    async fn nwc_pay_invoice(
+        &self,
+        Parameters(args): Parameters<NwcPayInvoiceArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let result = nwc::pay_invoice(args).await.map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_nwc_make_invoice",
+        description = "Create a BOLT11 invoice with the connected NWC wallet"
+    )]
+    async fn nwc_make_invoice(
+        &self,
+        Parameters(args): Parameters<NwcMakeInvoiceArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let result = nwc::make_invoice(args).await.map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_nwc_get_balance",
+        description = "Get the balance of the connected NWC wallet"
+    )]
+    async fn nwc_get_balance(
+        &self,
+        Parameters(args): Parameters<NwcBalanceArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let result = nwc::get_balance(args).await.map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_nwc_list_transactions",
+        description = "List transactions of the connected NWC wallet"
+    )]
+    async fn nwc_list_transactions(
+        &self,
+        Parameters(args): Parameters<NwcListTransactionsArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let result = nwc::list_transactions(args).await.map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = cashu_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_cashu_wallet",
+        description = "Show the active key's NIP-60 Cashu wallet: mints, balance per mint and optionally history"
+    )]
+    async fn cashu_wallet(
+        &self,
+        Parameters(args): Parameters<CashuWalletArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let view = cashu::wallet(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&view)
+    }
+
+    #[tool(
+        name = "nostr_cashu_wallet_setup",
+        description = "Publish the NIP-60 wallet event (kind 17375) and NIP-61 nutzap info (kind 10019) for the given mints"
+    )]
+    async fn cashu_wallet_setup(
+        &self,
+        Parameters(args): Parameters<CashuWalletSetupArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = cashu::setup_wallet(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_cashu_nutzap_send",
+        description = "Send a NIP-61 nutzap (kind 9321) from the Cashu wallet to a profile or event"
+    )]
+    async fn cashu_nutzap_send(
+        &self,
+        Parameters(args): Parameters<NutzapSendArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = cashu::send_nutzap(&active.client, &HttpMints, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+
+    #[tool(
+        name = "nostr_cashu_nutzap_receive",
+        description = "Redeem incoming NIP-61 nutzaps into the Cashu wallet"
+    )]
+    async fn cashu_nutzap_receive(
+        &self,
+        Parameters(args): Parameters<NutzapReceiveArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let result = cashu::receive_nutzaps(&active.client, &HttpMints, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&result)
+    }
+}
+
+#[tool_router(router = groups_router)]
+impl GoostrServer {
+    #[tool(
+        name = "nostr_groups_list",
+        description = "List the NIP-29 groups a relay hosts with their metadata, admins, member count and roles"
+    )]
+    async fn groups_list(
+        &self,
+        Parameters(args): Parameters<GroupsListArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let groups = groups::list_groups(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&groups)
+    }
+
+    #[tool(
+        name = "nostr_groups_messages",
+        description = "Fetch recent chat messages (kind 9) and threads (kind 11) posted to a NIP-29 group"
+    )]
+    async fn groups_messages(
+        &self,
+        Parameters(args): Parameters<GroupMessagesArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let messages = groups::group_messages(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&messages)
+    }
+
+    #[tool(
+        name = "nostr_groups_membership",
+        description = "Show which NIP-29 groups on a relay the active key (or another pubkey) belongs to, with admin roles and pending join requests"
+    )]
+    async fn groups_membership(
+        &self,
+        Parameters(args): Parameters<GroupMembershipArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let active = self.active_client().await?;
+        let rows = groups::membership(&active.client, args)
+            .await
+            .map_err(GoostrError::from)?;
+        json_result(&rows)
+    }
+}
+
+impl ServerHandler for GoostrServer {
+    fn get_info(&self) -> ServerInfo {
+        self.upstream.get_info()
+    }
+
+    async fn list_tools(
+        &self,
+        request: Option<PaginatedRequestParam>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        let mut result = self.upstream.list_tools(request, context).await?;
+        result
+            .tools
+            .retain(|tool| !self.tool_router.has_route(tool.name.as_ref()));
+        result.tools.extend(self.tool_router.list_all());
+        Ok(result)
+    }
+
+    async fn call_tool(
+        &self,
+        mut request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        if let Some(arguments) = request.arguments.as_mut() {
+            nip05::resolve_pubkey_args(arguments)
+                .await
+                .map_err(|e| GoostrError::InvalidPublicKey(e.to_string()))?;
+        }
+        if self.tool_router.has_route(request.name.as_ref()) {
+            let tcc = ToolCallContext::new(self, request, context);
+            self.tool_router.call(tcc).await
+        } else {
+            self.upstream.call_tool(request, context).await
+        }
+    }
+}
+
 pub async fn start_stdio_server() -> anyhow::Result<()> {
-    nostr_mcp_tools::server::start_stdio_server().await
+    let keystore = Arc::new(keys::load_or_init_keystore(util::nostr_index_path()).await?);
+    let settings = Arc::new(settings::load_or_init(util::nostr_settings_path()).await?);
+
+    let retry_loop = tokio::spawn(outbox::run_retry_loop(keystore.clone(), settings.clone()));
+    let scheduler = tokio::spawn(schedule::run_scheduler(keystore.clone(), settings.clone()));
+
+    let server = GoostrServer::new(keystore, settings);
+    let service = server.serve(rmcp::transport::stdio()).await?;
+    info!("goostr stdio server ready");
+    service.waiting().await?;
+
+    retry_loop.abort();
+    scheduler.abort();
+    Ok(())
 }
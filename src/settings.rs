@@ -1,12 +1,19 @@
+use crate::{storage, util};
 use anyhow::{Context, Result};
 use nostr_mcp_core::settings::SettingsStore as CoreSettingsStore;
+use nostr_sdk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::Arc;
+use tokio::sync::{Mutex, OnceCell};
 
 pub use nostr_mcp_core::settings::{FollowEntry, KeySettings, ProfileMetadata};
 
 pub type SettingsStore = CoreSettingsStore;
 
+static LOCAL: OnceCell<Arc<LocalSettingsStore>> = OnceCell::const_new();
+
 pub async fn load_or_init(path: PathBuf) -> Result<SettingsStore> {
     let pass = crate::util::ensure_keystore_secret()?;
     CoreSettingsStore::load_or_init(path, Arc::new(pass))
@@ -14,3 +21,96 @@ pub async fn load_or_init(path: PathBuf) -> Result<SettingsStore> {
         .map_err(|e| anyhow::anyhow!(e))
         .context("decrypt settings file")
 }
+
+/// Per-key settings goostr adds on top of the upstream `KeySettings`.
+/// Upstream rewrites `settings.enc` from its own struct, which would drop
+/// fields it doesn't know, so these are kept next to it under the same
+/// keystore secret.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LocalSettings {
+    #[serde(default)]
+    pub notifications_seen_at: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LocalSettingsFile {
+    keys: BTreeMap<String, LocalSettings>,
+}
+
+pub struct LocalSettingsStore {
+    path: PathBuf,
+    pass: Vec<u8>,
+    state: Mutex<LocalSettingsFile>,
+}
+
+impl LocalSettingsStore {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        Self::open(path, util::ensure_keystore_secret()?)
+    }
+
+    pub fn open(path: PathBuf, pass: Vec<u8>) -> Result<Self> {
+        let state = if path.exists() {
+            storage::decrypt_from_file(&path, &pass)?
+        } else {
+            LocalSettingsFile::default()
+        };
+        Ok(Self {
+            path,
+            pass,
+            state: Mutex::new(state),
+        })
+    }
+
+    pub async fn get(&self, pubkey: &PublicKey) -> LocalSettings {
+        let state = self.state.lock().await;
+        state.keys.get(&pubkey.to_hex()).cloned().unwrap_or_default()
+    }
+
+    pub async fn update<F>(&self, pubkey: &PublicKey, f: F) -> Result<LocalSettings>
+    where
+        F: FnOnce(&mut LocalSettings),
+    {
+        let mut state = self.state.lock().await;
+        let entry = state.keys.entry(pubkey.to_hex()).or_default();
+        f(entry);
+        let updated = entry.clone();
+        util::ensure_parent_dir(&self.path)?;
+        storage::encrypt_to_file(&self.path, &self.pass, &*state)?;
+        Ok(updated)
+    }
+}
+
+pub async fn local() -> Result<Arc<LocalSettingsStore>> {
+    LOCAL
+        .get_or_try_init(|| async {
+            LocalSettingsStore::load(util::nostr_local_settings_path()).map(Arc::new)
+        })
+        .await
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn local_settings_are_kept_per_key_across_reopens() {
+        let path = std::env::temp_dir().join(format!(
+            "goostr-local-settings-{}.enc",
+            Keys::generate().public_key().to_hex()
+        ));
+        let alice = Keys::generate().public_key();
+        let bob = Keys::generate().public_key();
+
+        let store = LocalSettingsStore::open(path.clone(), b"test".to_vec()).unwrap();
+        store
+            .update(&alice, |s| s.notifications_seen_at = Some(42))
+            .await
+            .unwrap();
+
+        let reopened = LocalSettingsStore::open(path.clone(), b"test".to_vec()).unwrap();
+        assert_eq!(reopened.get(&alice).await.notifications_seen_at, Some(42));
+        assert_eq!(reopened.get(&bob).await.notifications_seen_at, None);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::progress::Progress;
+use crate::relays;
+use crate::{storage, util};
+use anyhow::{bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const DEFAULT_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMode {
+    /// Only download events missing locally
+    #[default]
+    Down,
+    /// Download missing events and upload events the relay lacks (NIP-77 relays only)
+    Both,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SyncArgs {
+    /// NIP-01 filter describing the events to keep in sync
+    pub filter: serde_json::Value,
+    /// Relays to sync with (default: all configured relays)
+    pub relays: Option<Vec<String>>,
+    /// Sync direction (default: down)
+    pub direction: Option<SyncMode>,
+    /// Page size for the REQ pagination fallback (default: 500)
+    pub page_size: Option<usize>,
+    /// Per-request timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMethod {
+    Negentropy,
+    ReqPagination,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelaySyncResult {
+    pub method: SyncMethod,
+    pub received: usize,
+    pub sent: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncResult {
+    pub relays: BTreeMap<String, RelaySyncResult>,
+    pub received_total: usize,
+}
+
+/// Per relay and filter, the time up to which the REQ fallback has already
+/// fetched everything, so later syncs only ask for newer events.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SyncCheckpoints {
+    checkpoints: BTreeMap<String, u64>,
+}
+
+struct CheckpointStore {
+    path: PathBuf,
+    pass: Vec<u8>,
+}
+
+impl CheckpointStore {
+    fn open() -> Result<Self> {
+        Ok(Self {
+            path: util::nostr_sync_state_path(),
+            pass: util::ensure_keystore_secret()?,
+        })
+    }
+
+    fn load(&self) -> Result<SyncCheckpoints> {
+        if !self.path.exists() {
+            return Ok(SyncCheckpoints::default());
+        }
+        storage::decrypt_from_file(&self.path, &self.pass)
+    }
+
+    fn save(&self, state: &SyncCheckpoints) -> Result<()> {
+        util::ensure_parent_dir(&self.path)?;
+        storage::encrypt_to_file(&self.path, &self.pass, state)
+    }
+}
+
+fn checkpoint_key(relay: &str, filter: &Filter) -> String {
+    format!("{relay}|{}", filter.as_json())
+}
+
+/// A checkpoint only holds while the events it vouches for are still in the
+/// local database. The client database lives in memory by default, so after
+/// a restart it is empty: the stale checkpoint is dropped and the relay is
+/// walked again from the start.
+async fn checkpoint_since(
+    client: &Client,
+    state: &mut SyncCheckpoints,
+    key: &str,
+    filter: &Filter,
+) -> Result<Option<Timestamp>> {
+    let Some(at) = state.checkpoints.get(key).map(|t| Timestamp::from(*t)) else {
+        return Ok(None);
+    };
+    let held = client.database().count(filter.clone().until(at)).await?;
+    if held == 0 {
+        state.checkpoints.remove(key);
+        return Ok(None);
+    }
+    Ok(Some(at))
+}
+
+/// Downloads everything a relay holds for `filter` since `since` and saves
+/// it into the local database, returning how many events were new. The relay
+/// pool stores what it receives on its own, so new events are counted from
+/// the database rather than from what `save_event` reports.
+async fn paginate_relay(
+    client: &Client,
+    relay: &str,
+    filter: &Filter,
+    since: Option<Timestamp>,
+    page_size: usize,
+    timeout: Duration,
+) -> Result<usize> {
+    let mut filter = filter.clone();
+    if let Some(since) = since {
+        filter = filter.since(since);
+    }
+    let mut stored = filter.clone();
+    stored.limit = None;
+    let before = client.database().count(stored.clone()).await?;
+    let (events, _) =
+        relays::fetch_all_from(client, relay, &filter, page_size, usize::MAX, timeout).await?;
+    for event in events {
+        client.database().save_event(&event).await?;
+    }
+    let after = client.database().count(stored).await?;
+    Ok(after.saturating_sub(before))
+}
+
+pub async fn sync(client: &Client, args: SyncArgs, progress: &Progress) -> Result<SyncResult> {
+    let filter = Filter::from_json(args.filter.to_string())?;
+    let targets = match args.relays {
+        Some(r) if !r.is_empty() => r,
+        _ => relays::get_relay_urls(client).await,
+    };
+    if targets.is_empty() {
+        bail!("no relays to sync with");
+    }
+    let timeout = Duration::from_secs(args.timeout_secs.unwrap_or(10));
+    let page_size = args.page_size.unwrap_or(DEFAULT_PAGE_SIZE).max(1);
+    let direction = match args.direction.unwrap_or_default() {
+        SyncMode::Down => SyncDirection::Down,
+        SyncMode::Both => SyncDirection::Both,
+    };
+    let opts = SyncOptions::default()
+        .direction(direction)
+        .initial_timeout(timeout);
+
+    let checkpoints = CheckpointStore::open()?;
+    let mut state = checkpoints.load()?;
+    let mut results = BTreeMap::new();
+    let mut received_total = 0;
+    let total = targets.len();
+
+    for (i, relay) in targets.into_iter().enumerate() {
+        let started_at = Timestamp::now();
+        let negentropy = client.sync_with([relay.as_str()], filter.clone(), &opts).await;
+        let result = match negentropy {
+            Ok(output) if output.failed.is_empty() => RelaySyncResult {
+                method: SyncMethod::Negentropy,
+                received: output.val.received.len(),
+                sent: output.val.sent.len(),
+                error: None,
+            },
+            _ => {
+                let key = checkpoint_key(&relay, &filter);
+                let since = match checkpoint_since(client, &mut state, &key, &filter).await {
+                    Ok(at) => at.max(filter.since),
+                    Err(_) => filter.since,
+                };
+                match paginate_relay(client, &relay, &filter, since, page_size, timeout).await {
+                    Ok(received) => {
+                        state.checkpoints.insert(key, started_at.as_secs());
+                        RelaySyncResult {
+                            method: SyncMethod::ReqPagination,
+                            received,
+                            sent: 0,
+                            error: None,
+                        }
+                    }
+                    Err(e) => RelaySyncResult {
+                        method: SyncMethod::ReqPagination,
+                        received: 0,
+                        sent: 0,
+                        error: Some(e.to_string()),
+                    },
+                }
+            }
+        };
+        received_total += result.received;
+        progress
+            .report((i + 1) as f64, Some(total as f64), format!("synced {relay}"))
+            .await;
+        results.insert(relay, result);
+    }
+
+    checkpoints.save(&state)?;
+    Ok(SyncResult {
+        relays: results,
+        received_total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_relay::TestRelay;
+
+    #[tokio::test]
+    async fn pagination_saves_everything_since_the_checkpoint() {
+        let keys = Keys::generate();
+        let relay = TestRelay::start().await;
+        relay
+            .insert((0..7).map(|i| {
+                EventBuilder::text_note(format!("note {i}"))
+                    .custom_created_at(Timestamp::from(1_000 + i))
+                    .sign_with_keys(&keys)
+                    .unwrap()
+            }))
+            .await;
+        let database = MemoryDatabase::with_opts(MemoryDatabaseOptions {
+            events: true,
+            ..Default::default()
+        });
+        let client = Client::builder().database(database).build();
+        client.add_relay(relay.url.as_str()).await.unwrap();
+        client.connect().await;
+
+        let filter = Filter::new().author(keys.public_key());
+        let received = paginate_relay(
+            &client,
+            &relay.url,
+            &filter,
+            Some(Timestamp::from(1_002)),
+            2,
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(received, 5);
+        let stored = client.database().query(filter).await.unwrap();
+        assert_eq!(stored.len(), 5);
+    }
+}
@@ -0,0 +1,166 @@
+//! In-process relay for tests. It keeps every event it is sent, answers REQ
+//! with the stored matches and EOSE, COUNT with the number of matches, and
+//! forwards new events to open subscriptions. A handler can answer events
+//! with more events, which is how tests stand in for services such as an
+//! NWC wallet.
+
+use futures::{SinkExt, StreamExt};
+use nostr_sdk::prelude::*;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Mutex};
+use tokio_tungstenite::tungstenite::Message;
+
+type Handler = Arc<dyn Fn(&Event) -> Vec<Event> + Send + Sync>;
+
+struct Subscription {
+    id: SubscriptionId,
+    filters: Vec<Filter>,
+    outgoing: mpsc::UnboundedSender<String>,
+}
+
+#[derive(Default)]
+struct State {
+    events: Vec<Event>,
+    subscriptions: Vec<Subscription>,
+}
+
+pub struct TestRelay {
+    pub url: String,
+    state: Arc<Mutex<State>>,
+}
+
+fn matches(filters: &[Filter], event: &Event) -> bool {
+    filters
+        .iter()
+        .any(|f| f.match_event(event, MatchEventOptions::new()))
+}
+
+impl TestRelay {
+    pub async fn start() -> Self {
+        Self::with_handler(|_| Vec::new()).await
+    }
+
+    pub async fn with_handler(
+        handler: impl Fn(&Event) -> Vec<Event> + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(State::default()));
+        let handler: Handler = Arc::new(handler);
+        let shared = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, shared.clone(), handler.clone()));
+            }
+        });
+        Self { url, state }
+    }
+
+    pub async fn insert(&self, events: impl IntoIterator<Item = Event>) {
+        self.state.lock().await.events.extend(events);
+    }
+
+    pub async fn events(&self) -> Vec<Event> {
+        self.state.lock().await.events.clone()
+    }
+}
+
+async fn publish(state: &Mutex<State>, event: Event) {
+    let mut state = state.lock().await;
+    if state.events.iter().any(|e| e.id == event.id) {
+        return;
+    }
+    state.subscriptions.retain(|sub| {
+        !matches(&sub.filters, &event)
+            || sub
+                .outgoing
+                .send(RelayMessage::event(sub.id.clone(), event.clone()).as_json())
+                .is_ok()
+    });
+    state.events.push(event);
+}
+
+async fn serve(stream: tokio::net::TcpStream, state: Arc<Mutex<State>>, handler: Handler) {
+    let Ok(socket) = tokio_tungstenite::accept_async(stream).await else {
+        return;
+    };
+    let (mut sink, mut source) = socket.split();
+    let (outgoing, mut queue) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        while let Some(text) = queue.recv().await {
+            if sink.send(Message::text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+    while let Some(Ok(message)) = source.next().await {
+        let Message::Text(text) = message else {
+            continue;
+        };
+        let Ok(message) = ClientMessage::from_json(text.as_str()) else {
+            continue;
+        };
+        match message {
+            ClientMessage::Event(event) => {
+                let event = event.into_owned();
+                let _ = outgoing.send(RelayMessage::ok(event.id, true, "").as_json());
+                let replies = handler(&event);
+                publish(&state, event).await;
+                for reply in replies {
+                    publish(&state, reply).await;
+                }
+            }
+            ClientMessage::Req {
+                subscription_id,
+                filters,
+            } => {
+                let id = subscription_id.into_owned();
+                let filters: Vec<Filter> = filters.into_iter().map(|f| f.into_owned()).collect();
+                let mut state = state.lock().await;
+                let mut stored: Vec<&Event> = state
+                    .events
+                    .iter()
+                    .filter(|e| matches(&filters, e))
+                    .collect();
+                stored.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+                if let Some(limit) = filters.iter().map(|f| f.limit.unwrap_or(usize::MAX)).max() {
+                    stored.truncate(limit);
+                }
+                for event in stored {
+                    let _ = outgoing.send(RelayMessage::event(id.clone(), event.clone()).as_json());
+                }
+                let _ = outgoing.send(RelayMessage::eose(id.clone()).as_json());
+                state
+                    .subscriptions
+                    .retain(|sub| sub.id != id || !sub.outgoing.same_channel(&outgoing));
+                state.subscriptions.push(Subscription {
+                    id,
+                    filters,
+                    outgoing: outgoing.clone(),
+                });
+            }
+            ClientMessage::Count {
+                subscription_id,
+                filter,
+            } => {
+                let state = state.lock().await;
+                let count = state
+                    .events
+                    .iter()
+                    .filter(|e| filter.match_event(e, MatchEventOptions::new()))
+                    .count();
+                let _ = outgoing
+                    .send(RelayMessage::count(subscription_id.into_owned(), count).as_json());
+            }
+            ClientMessage::Close(id) => {
+                state
+                    .lock()
+                    .await
+                    .subscriptions
+                    .retain(|sub| sub.id != *id || !sub.outgoing.same_channel(&outgoing));
+            }
+            _ => {}
+        }
+    }
+}
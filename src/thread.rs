@@ -0,0 +1,466 @@
+use crate::profiles;
+use crate::relays;
+use anyhow::{anyhow, bail, Result};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+const DEFAULT_MAX_DEPTH: usize = 10;
+const DEFAULT_MAX_EVENTS: usize = 500;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ThreadGetArgs {
+    /// Any event in the conversation: hex ID, note1... or nevent1...
+    pub event: String,
+    /// Maximum reply depth to include below the root (default: 10)
+    pub max_depth: Option<usize>,
+    /// Maximum number of events to fetch (default: 500)
+    pub max_events: Option<usize>,
+    /// Query timeout in seconds per round trip (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThreadNode {
+    pub id: String,
+    pub pubkey: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    pub kind: u16,
+    pub created_at: u64,
+    pub content: String,
+    pub depth: usize,
+    /// An ancestor the user has muted, deleted or that expired; kept so the
+    /// replies below it stay in place, with its content left out
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+    /// True when replies exist below this node but were cut by `max_depth`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    pub children: Vec<ThreadNode>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThreadResult {
+    pub root: ThreadNode,
+    pub focus_id: String,
+    pub event_count: usize,
+    /// Event IDs referenced as ancestors that no relay returned
+    pub missing: Vec<String>,
+}
+
+/// Root and direct parent of a reply, per NIP-10 (kind 1) or NIP-22
+/// (kind 1111). Events that are not replies return `(None, None)`.
+pub fn thread_refs(event: &Event) -> (Option<EventId>, Option<EventId>) {
+    let tag_id = |t: &[String]| t.get(1).and_then(|id| EventId::from_hex(id).ok());
+    if event.kind == Kind::Comment {
+        let root = event
+            .tags
+            .iter()
+            .find(|t| t.as_slice().first().map(String::as_str) == Some("E"))
+            .and_then(|t| tag_id(t.as_slice()));
+        let parent = event
+            .tags
+            .iter()
+            .find(|t| t.as_slice().first().map(String::as_str) == Some("e"))
+            .and_then(|t| tag_id(t.as_slice()))
+            .or(root);
+        return (root, parent);
+    }
+
+    let e_tags: Vec<&[String]> = event
+        .tags
+        .iter()
+        .map(|t| t.as_slice())
+        .filter(|t| t.first().map(String::as_str) == Some("e"))
+        .collect();
+    let marked = |marker: &str| {
+        e_tags
+            .iter()
+            .find(|t| t.get(3).map(String::as_str) == Some(marker))
+            .and_then(|t| tag_id(t))
+    };
+    let root = marked("root");
+    let reply = marked("reply");
+    if root.is_some() || reply.is_some() {
+        return (root.or(reply), reply.or(root));
+    }
+    // Deprecated positional scheme: first `e` is the root, last is the parent.
+    let positional: Vec<&[String]> = e_tags
+        .into_iter()
+        .filter(|t| t.get(3).map(String::as_str) != Some("mention"))
+        .collect();
+    (
+        positional.first().and_then(|t| tag_id(t)),
+        positional.last().and_then(|t| tag_id(t)),
+    )
+}
+
+/// Accepts a hex event ID, `note1...`, `nevent1...` or a `nostr:` URI.
+pub fn parse_event_ref(input: &str) -> Result<(EventId, Vec<RelayUrl>)> {
+    let input = input.trim().trim_start_matches("nostr:");
+    if let Ok(id) = EventId::from_hex(input) {
+        return Ok((id, Vec::new()));
+    }
+    match Nip19::from_bech32(input).map_err(|e| anyhow!("invalid event reference: {e}"))? {
+        Nip19::EventId(id) => Ok((id, Vec::new())),
+        Nip19::Event(nevent) => Ok((nevent.event_id, nevent.relays)),
+        _ => bail!("expected a note or nevent reference"),
+    }
+}
+
+async fn fetch_ids(
+    client: &Client,
+    ids: impl IntoIterator<Item = EventId>,
+    timeout: u64,
+) -> Result<Vec<Event>> {
+    let ids: Vec<EventId> = ids.into_iter().collect();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    relays::fetch_events(client, Filter::new().ids(ids), timeout).await
+}
+
+async fn fetch_replies(client: &Client, parents: &[EventId], timeout: u64) -> Result<Vec<Event>> {
+    let hex: Vec<String> = parents.iter().map(|id| id.to_hex()).collect();
+    let nip10 = Filter::new()
+        .kind(Kind::TextNote)
+        .events(parents.iter().copied());
+    let nip22 = Filter::new()
+        .kind(Kind::Comment)
+        .custom_tags(SingleLetterTag::uppercase(Alphabet::E), hex.clone());
+    let nip22_nested = Filter::new()
+        .kind(Kind::Comment)
+        .custom_tags(SingleLetterTag::lowercase(Alphabet::E), hex);
+    // Ancestors are always followed up to the root, but hidden replies (and
+    // with them their subtrees) are left out of what is shown.
+    let mut out = relays::fetch_events(client, nip10, timeout).await?;
+    out.extend(relays::fetch_events(client, nip22, timeout).await?);
+    out.extend(relays::fetch_events(client, nip22_nested, timeout).await?);
+    out.retain(|e| replies_to(e, parents));
+    Ok(relays::visible_events(client, out).await)
+}
+
+/// True when `event` is a reply within the thread of one of `parents`, by
+/// its NIP-10 markers or positional tags (or NIP-22 scope). The `#e` filter
+/// also matches notes that only mention or quote a parent; those are not
+/// part of the conversation.
+fn replies_to(event: &Event, parents: &[EventId]) -> bool {
+    let (root, parent) = thread_refs(event);
+    [root, parent]
+        .into_iter()
+        .flatten()
+        .any(|id| parents.contains(&id))
+}
+
+struct TreeParts<'a> {
+    events: &'a HashMap<EventId, Event>,
+    children: &'a HashMap<EventId, Vec<EventId>>,
+    names: &'a HashMap<PublicKey, String>,
+    hidden: &'a HashSet<EventId>,
+    max_depth: usize,
+}
+
+fn build_node(id: &EventId, parts: &TreeParts<'_>, depth: usize) -> ThreadNode {
+    let TreeParts {
+        events,
+        children,
+        names,
+        hidden,
+        max_depth,
+    } = parts;
+    let event = &events[id];
+    let kids = children.get(id).map(Vec::as_slice).unwrap_or_default();
+    let truncated = depth >= *max_depth && !kids.is_empty();
+    let children = if truncated {
+        Vec::new()
+    } else {
+        kids.iter()
+            .map(|k| build_node(k, parts, depth + 1))
+            .collect()
+    };
+    let hidden = hidden.contains(id);
+    ThreadNode {
+        id: event.id.to_hex(),
+        pubkey: event.pubkey.to_hex(),
+        author_name: names.get(&event.pubkey).cloned().filter(|_| !hidden),
+        kind: event.kind.as_u16(),
+        created_at: event.created_at.as_secs(),
+        content: if hidden { String::new() } else { event.content.clone() },
+        depth,
+        hidden,
+        truncated,
+        children,
+    }
+}
+
+/// Groups every event under its parent, oldest reply first. Events whose
+/// parent was not fetched hang off the root so they still show up.
+fn link_children(
+    events: &HashMap<EventId, Event>,
+    root_id: EventId,
+) -> HashMap<EventId, Vec<EventId>> {
+    let mut children: HashMap<EventId, Vec<EventId>> = HashMap::new();
+    for event in events.values() {
+        if event.id == root_id {
+            continue;
+        }
+        let (_, parent) = thread_refs(event);
+        let parent = parent
+            .filter(|p| events.contains_key(p))
+            .unwrap_or(root_id);
+        children.entry(parent).or_default().push(event.id);
+    }
+    for kids in children.values_mut() {
+        kids.sort_by_key(|id| (events[id].created_at, *id));
+    }
+    children
+}
+
+/// Reconstructs the conversation around any event: walks parents up to the
+/// root, then fetches replies breadth-first down from it.
+pub async fn get_thread(client: &Client, args: ThreadGetArgs) -> Result<ThreadResult> {
+    let (focus_id, _hints) = parse_event_ref(&args.event)?;
+    let max_depth = args.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+    let max_events = args.max_events.unwrap_or(DEFAULT_MAX_EVENTS).max(1);
+    let timeout = args.timeout_secs.unwrap_or(10);
+
+    let mut events: HashMap<EventId, Event> = HashMap::new();
+    let mut missing: Vec<String> = Vec::new();
+
+    let focus = fetch_ids(client, [focus_id], timeout)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("event {} not found", focus_id.to_hex()))?;
+    let (root_ref, _) = thread_refs(&focus);
+    events.insert(focus.id, focus.clone());
+
+    // Ancestors: prefer the declared root, then follow parent links upward.
+    if let Some(root_id) = root_ref {
+        for event in fetch_ids(client, [root_id], timeout).await? {
+            events.insert(event.id, event);
+        }
+    }
+    let mut cursor = focus.clone();
+    while events.len() < max_events {
+        let (_, parent) = thread_refs(&cursor);
+        let Some(parent) = parent else { break };
+        let next = match events.get(&parent) {
+            Some(e) => e.clone(),
+            None => match fetch_ids(client, [parent], timeout).await?.into_iter().next() {
+                Some(e) => {
+                    events.insert(e.id, e.clone());
+                    e
+                }
+                None => {
+                    missing.push(parent.to_hex());
+                    break;
+                }
+            },
+        };
+        if next.id == cursor.id {
+            break;
+        }
+        cursor = next;
+    }
+    let root_id = match root_ref {
+        Some(id) if events.contains_key(&id) => id,
+        Some(id) => {
+            if !missing.contains(&id.to_hex()) {
+                missing.push(id.to_hex());
+            }
+            cursor.id
+        }
+        None => cursor.id,
+    };
+
+    // Ancestors keep the tree connected even when the user shouldn't see
+    // them; those are marked hidden instead of dropped. The focus was asked
+    // for by ID and is always shown.
+    let ancestors: Vec<Event> = events
+        .values()
+        .filter(|e| e.id != focus.id)
+        .cloned()
+        .collect();
+    let shown: HashSet<EventId> = relays::visible_events(client, ancestors.clone())
+        .await
+        .iter()
+        .map(|e| e.id)
+        .collect();
+    let hidden: HashSet<EventId> = ancestors
+        .iter()
+        .map(|e| e.id)
+        .filter(|id| !shown.contains(id))
+        .collect();
+
+    // Descendants, level by level.
+    let mut frontier = vec![root_id];
+    let mut expanded: HashSet<EventId> = HashSet::new();
+    for _ in 0..max_depth {
+        frontier.retain(|id| expanded.insert(*id));
+        if frontier.is_empty() || events.len() >= max_events {
+            break;
+        }
+        let mut next = Vec::new();
+        for reply in fetch_replies(client, &frontier, timeout).await? {
+            if events.len() >= max_events {
+                break;
+            }
+            if let Entry::Vacant(slot) = events.entry(reply.id) {
+                next.push(reply.id);
+                slot.insert(reply);
+            }
+        }
+        // Already-known nodes also need their replies fetched.
+        next.extend(events.keys().filter(|id| !expanded.contains(id)).copied());
+        frontier = next;
+    }
+
+    let children = link_children(&events, root_id);
+    let names = profiles::display_names(client, events.values().map(|e| e.pubkey), timeout).await?;
+    let parts = TreeParts {
+        events: &events,
+        children: &children,
+        names: &names,
+        hidden: &hidden,
+        max_depth,
+    };
+    Ok(ThreadResult {
+        root: build_node(&root_id, &parts, 0),
+        focus_id: focus_id.to_hex(),
+        event_count: events.len(),
+        missing,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(keys: &Keys, content: &str, at: u64, tags: Vec<Tag>) -> Event {
+        EventBuilder::text_note(content)
+            .tags(tags)
+            .custom_created_at(Timestamp::from(at))
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    fn e_tag(id: EventId, marker: &str) -> Tag {
+        let mut parts = vec!["e".to_string(), id.to_hex(), String::new()];
+        if !marker.is_empty() {
+            parts.push(marker.to_string());
+        }
+        Tag::parse(parts).unwrap()
+    }
+
+    #[test]
+    fn refs_follow_markers_then_positions() {
+        let keys = Keys::generate();
+        let root = event(&keys, "root", 1, vec![]);
+        let parent = event(&keys, "parent", 2, vec![e_tag(root.id, "root")]);
+        let other = event(&keys, "other", 3, vec![]);
+
+        let marked = event(
+            &keys,
+            "marked",
+            4,
+            vec![e_tag(root.id, "root"), e_tag(other.id, "mention"), e_tag(parent.id, "reply")],
+        );
+        assert_eq!(thread_refs(&marked), (Some(root.id), Some(parent.id)));
+
+        let positional = event(
+            &keys,
+            "positional",
+            5,
+            vec![e_tag(root.id, ""), e_tag(other.id, ""), e_tag(parent.id, "")],
+        );
+        assert_eq!(thread_refs(&positional), (Some(root.id), Some(parent.id)));
+
+        let top_level = event(&keys, "top level", 6, vec![]);
+        assert_eq!(thread_refs(&top_level), (None, None));
+    }
+
+    #[test]
+    fn mentions_and_quotes_are_not_replies() {
+        let keys = Keys::generate();
+        let root = event(&keys, "root", 1, vec![]);
+        let reply = event(&keys, "reply", 2, vec![e_tag(root.id, "root")]);
+        let positional = event(&keys, "old style", 3, vec![e_tag(root.id, "")]);
+        let mention = event(&keys, "mention", 4, vec![e_tag(root.id, "mention")]);
+        let quote = EventBuilder::text_note("quote")
+            .tag(Tag::parse(["q", &root.id.to_hex()]).unwrap())
+            .sign_with_keys(&keys)
+            .unwrap();
+        let elsewhere = event(
+            &keys,
+            "reply elsewhere",
+            5,
+            vec![e_tag(EventId::all_zeros(), "root"), e_tag(root.id, "mention")],
+        );
+
+        assert!(replies_to(&reply, &[root.id]));
+        assert!(replies_to(&positional, &[root.id]));
+        assert!(!replies_to(&mention, &[root.id]));
+        assert!(!replies_to(&quote, &[root.id]));
+        assert!(!replies_to(&elsewhere, &[root.id]));
+    }
+
+    #[test]
+    fn tree_nests_replies_and_truncates_at_max_depth() {
+        let keys = Keys::generate();
+        let root = event(&keys, "root", 1, vec![]);
+        let late = event(&keys, "late", 5, vec![e_tag(root.id, "root")]);
+        let early = event(&keys, "early", 2, vec![e_tag(root.id, "root")]);
+        let nested = event(
+            &keys,
+            "nested",
+            3,
+            vec![e_tag(root.id, "root"), e_tag(early.id, "reply")],
+        );
+        let deeper = event(
+            &keys,
+            "deeper",
+            4,
+            vec![e_tag(root.id, "root"), e_tag(nested.id, "reply")],
+        );
+        let orphan = event(
+            &keys,
+            "orphan",
+            6,
+            vec![e_tag(root.id, "root"), e_tag(EventId::all_zeros(), "reply")],
+        );
+        let events: HashMap<EventId, Event> = [&root, &late, &early, &nested, &deeper, &orphan]
+            .into_iter()
+            .map(|e| (e.id, e.clone()))
+            .collect();
+
+        let children = link_children(&events, root.id);
+        assert_eq!(children[&root.id], vec![early.id, late.id, orphan.id]);
+        assert_eq!(children[&early.id], vec![nested.id]);
+
+        let names = HashMap::new();
+        let hidden = HashSet::from([late.id]);
+        let parts = TreeParts {
+            events: &events,
+            children: &children,
+            names: &names,
+            hidden: &hidden,
+            max_depth: 2,
+        };
+        let tree = build_node(&root.id, &parts, 0);
+        assert_eq!(tree.children.len(), 3);
+        let early_node = &tree.children[0];
+        assert_eq!(early_node.content, "early");
+        let nested_node = &early_node.children[0];
+        assert_eq!(nested_node.depth, 2);
+        assert!(nested_node.truncated);
+        assert!(nested_node.children.is_empty());
+        let late_node = &tree.children[1];
+        assert!(late_node.hidden);
+        assert!(late_node.content.is_empty());
+        assert!(!early_node.hidden);
+    }
+}
@@ -19,6 +19,38 @@ pub fn nostr_settings_path() -> PathBuf {
     nostr_config_root().join("settings.enc")
 }
 
+pub fn nostr_outbox_path() -> PathBuf {
+    nostr_config_root().join("outbox.enc")
+}
+
+pub fn nostr_schedule_path() -> PathBuf {
+    nostr_config_root().join("schedule.enc")
+}
+
+pub fn nostr_sync_state_path() -> PathBuf {
+    nostr_config_root().join("sync.enc")
+}
+
+pub fn nostr_local_settings_path() -> PathBuf {
+    nostr_config_root().join("settings.local.enc")
+}
+
+pub fn nostr_contact_snapshots_path() -> PathBuf {
+    nostr_config_root().join("contact_snapshots.enc")
+}
+
+pub fn nostr_deletions_path() -> PathBuf {
+    nostr_config_root().join("deletions.enc")
+}
+
+pub fn nostr_wallet_policy_path() -> PathBuf {
+    nostr_config_root().join("wallet_policy.enc")
+}
+
+pub fn nostr_wallet_approvals_path() -> PathBuf {
+    nostr_config_root().join("wallet_approvals.enc")
+}
+
 pub fn legacy_keys_json_path() -> PathBuf {
     nostr_config_root().join("keys.json")
 }
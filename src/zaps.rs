@@ -0,0 +1,782 @@
+use crate::http;
+use crate::metadata;
+use crate::nip05;
+use crate::profiles;
+use crate::relays;
+use crate::thread;
+use anyhow::{anyhow, bail, Context, Result};
+use nostr::hashes::{sha256::Hash as Sha256Hash, Hash};
+use nostr_sdk::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BOLT11_SIGNATURE_WORDS: usize = 104;
+const BOLT11_TIMESTAMP_WORDS: usize = 7;
+const BOLT11_DESCRIPTION_HASH: u8 = 23;
+const MSATS_PER_BTC: u64 = 100_000_000_000;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ZapRequestArgs {
+    /// Profile to zap (hex, npub or NIP-05); defaults to the author of `event`
+    pub recipient: Option<String>,
+    /// Event to zap: hex ID, note1..., nevent1... or naddr1...
+    pub event: Option<String>,
+    /// Amount in sats
+    pub amount_sats: u64,
+    /// Comment carried in the zap request
+    pub comment: Option<String>,
+    /// Relays the recipient's wallet should publish the receipt to (default: configured relays)
+    pub relays: Option<Vec<String>>,
+    /// LNURL to use instead of the profile's lud16/lud06: `name@domain`, `lnurl1...` or a URL
+    pub lnurl: Option<String>,
+    /// Ask the LNURL callback for an invoice (default: true)
+    pub fetch_invoice: Option<bool>,
+    /// Query timeout in seconds when fetching the target (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ZapReceiptsArgs {
+    /// Only receipts for this event: hex ID, note1... or nevent1...
+    pub event: Option<String>,
+    /// Only receipts for zaps to this profile (hex, npub or NIP-05; default: active key when `event` is omitted)
+    pub recipient: Option<String>,
+    /// Unix timestamp - only receipts after this time
+    pub since: Option<u64>,
+    /// Maximum receipts to fetch (default: 500)
+    pub limit: Option<usize>,
+    /// Query timeout in seconds (default: 10)
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZapRequestResult {
+    pub zap_request: Event,
+    pub recipient: String,
+    pub amount_msats: u64,
+    pub callback: String,
+    /// Pubkey that must sign the kind 9735 receipt
+    pub zapper_pubkey: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invoice: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZapReceiptRow {
+    pub receipt_id: String,
+    pub recipient: Option<String>,
+    pub zapped_event: Option<String>,
+    /// Author of the zap request
+    pub sender: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sender_name: Option<String>,
+    pub amount_msats: Option<u64>,
+    pub comment: Option<String>,
+    pub created_at: u64,
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ZapTotal {
+    pub count: usize,
+    pub msats: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZapReceiptsResult {
+    pub receipts: Vec<ZapReceiptRow>,
+    /// Valid zaps per zapped event ID; profile zaps are keyed by `profile`
+    pub totals: BTreeMap<String, ZapTotal>,
+    pub total_msats: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PayParams {
+    callback: String,
+    min_sendable: u64,
+    max_sendable: u64,
+    #[serde(default)]
+    allows_nostr: bool,
+    nostr_pubkey: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InvoiceResponse {
+    pr: Option<String>,
+    reason: Option<String>,
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GEN: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut chk: u32 = 1;
+    for v in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ u32::from(*v);
+        for (i, g) in GEN.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+/// Decodes bech32 without the 90 character limit, which LNURLs and
+/// invoices routinely exceed. Returns the HRP and the 5-bit data words.
+fn bech32_words(input: &str) -> Result<(String, Vec<u8>)> {
+    let input = input.trim().to_lowercase();
+    let pos = input.rfind('1').ok_or_else(|| anyhow!("not bech32: {input}"))?;
+    let (hrp, data) = (&input[..pos], &input[pos + 1..]);
+    let words = data
+        .chars()
+        .map(|c| BECH32_CHARSET.find(c).map(|i| i as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| anyhow!("invalid bech32 character in {input}"))?;
+    let mut check: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    check.push(0);
+    check.extend(hrp.bytes().map(|b| b & 31));
+    check.extend(&words);
+    if words.len() < 6 || bech32_polymod(&check) != 1 {
+        bail!("invalid bech32 checksum");
+    }
+    Ok((hrp.to_string(), words[..words.len() - 6].to_vec()))
+}
+
+fn words_to_bytes(words: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(words.len() * 5 / 8);
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for w in words {
+        acc = (acc << 5) | u32::from(*w);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    out
+}
+
+/// Amount encoded in a BOLT11 invoice's human-readable part, in msats.
+fn bolt11_amount_msats(hrp: &str) -> Result<Option<u64>> {
+    let rest = hrp
+        .strip_prefix("ln")
+        .ok_or_else(|| anyhow!("not a lightning invoice"))?;
+    let Some(start) = rest.find(|c: char| c.is_ascii_digit()) else {
+        return Ok(None);
+    };
+    let amount = &rest[start..];
+    let (digits, multiplier) = match amount.chars().last() {
+        Some(c @ ('m' | 'u' | 'n' | 'p')) => (&amount[..amount.len() - 1], Some(c)),
+        _ => (amount, None),
+    };
+    let n: u64 = digits.parse().context("invalid invoice amount")?;
+    let msats = match multiplier {
+        None => n.checked_mul(MSATS_PER_BTC),
+        Some('m') => n.checked_mul(MSATS_PER_BTC / 1_000),
+        Some('u') => n.checked_mul(MSATS_PER_BTC / 1_000_000),
+        Some('n') => n.checked_mul(MSATS_PER_BTC / 1_000_000_000),
+        Some(_) if n % 10 == 0 => Some(n / 10),
+        Some(_) => bail!("sub-millisatoshi invoice amount"),
+    };
+    msats.map(Some).ok_or_else(|| anyhow!("invoice amount overflows"))
+}
+
+struct Bolt11 {
+    amount_msats: Option<u64>,
+    description_hash: Option<[u8; 32]>,
+}
+
+fn parse_bolt11(invoice: &str) -> Result<Bolt11> {
+    let (hrp, words) = bech32_words(invoice)?;
+    let amount_msats = bolt11_amount_msats(&hrp)?;
+    if words.len() < BOLT11_TIMESTAMP_WORDS + BOLT11_SIGNATURE_WORDS {
+        bail!("invoice too short");
+    }
+    let fields = &words[BOLT11_TIMESTAMP_WORDS..words.len() - BOLT11_SIGNATURE_WORDS];
+    let mut description_hash = None;
+    let mut i = 0;
+    while i + 3 <= fields.len() {
+        let kind = fields[i];
+        let len = usize::from(fields[i + 1]) * 32 + usize::from(fields[i + 2]);
+        let data = fields
+            .get(i + 3..i + 3 + len)
+            .ok_or_else(|| anyhow!("truncated invoice field"))?;
+        if kind == BOLT11_DESCRIPTION_HASH && len == 52 {
+            let bytes = words_to_bytes(data);
+            description_hash = bytes.get(..32).and_then(|b| b.try_into().ok());
+        }
+        i += 3 + len;
+    }
+    Ok(Bolt11 {
+        amount_msats,
+        description_hash,
+    })
+}
+
+/// Amount of a BOLT11 invoice in msats; `None` for amountless invoices.
+pub fn invoice_amount_msats(invoice: &str) -> Result<Option<u64>> {
+    Ok(parse_bolt11(invoice)?.amount_msats)
+}
+
+/// Accepts `name@domain` (lud16), `lnurl1...` (lud06) or a plain URL.
+fn lnurl_endpoint(lnurl: &str) -> Result<String> {
+    let lnurl = lnurl.trim().trim_start_matches("lightning:");
+    if lnurl.starts_with("https://") {
+        return Ok(lnurl.to_string());
+    }
+    if let Some(rest) = lnurl.strip_prefix("http://") {
+        let host = rest.split('/').next().unwrap_or(rest);
+        if http::scheme_for(host) != "http" {
+            bail!("LNURL endpoints must use https: {lnurl}");
+        }
+        return Ok(lnurl.to_string());
+    }
+    if let Some((name, domain)) = lnurl.split_once('@') {
+        let scheme = http::scheme_for(domain);
+        return Ok(format!("{scheme}://{domain}/.well-known/lnurlp/{name}"));
+    }
+    let (hrp, words) = bech32_words(lnurl)?;
+    if hrp != "lnurl" {
+        bail!("unsupported lightning address: {lnurl}");
+    }
+    Ok(String::from_utf8(words_to_bytes(&words))?)
+}
+
+async fn pay_params(endpoint: &str) -> Result<PayParams> {
+    Ok(http::client()
+        .get(endpoint)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+async fn lnurl_for(client: &Client, recipient: &PublicKey) -> Result<String> {
+    let metadata = metadata::fetch_metadata(client, recipient)
+        .await?
+        .ok_or_else(|| anyhow!("recipient has no profile metadata"))?;
+    metadata
+        .lud16
+        .or(metadata.lud06)
+        .filter(|l| !l.trim().is_empty())
+        .ok_or_else(|| anyhow!("recipient profile has no lud16 or lud06"))
+}
+
+pub(crate) async fn zapper_pubkey(client: &Client, recipient: &PublicKey) -> Result<PublicKey> {
+    let params = pay_params(&lnurl_endpoint(&lnurl_for(client, recipient).await?)?).await?;
+    let pubkey = params
+        .nostr_pubkey
+        .filter(|_| params.allows_nostr)
+        .ok_or_else(|| anyhow!("recipient's LNURL server does not support zaps"))?;
+    Ok(PublicKey::parse(&pubkey)?)
+}
+
+enum ZapTarget {
+    Event(Event),
+    Address(Coordinate),
+}
+
+async fn zap_target(client: &Client, reference: &str, timeout: u64) -> Result<ZapTarget> {
+    let trimmed = reference.trim().trim_start_matches("nostr:");
+    if trimmed.starts_with("naddr1") {
+        return Ok(ZapTarget::Address(Nip19Coordinate::from_bech32(trimmed)?.coordinate));
+    }
+    let (id, _) = thread::parse_event_ref(reference)?;
+    let event = relays::fetch_events(client, Filter::new().id(id), timeout)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("event {} not found", id.to_hex()))?;
+    Ok(ZapTarget::Event(event))
+}
+
+/// Builds and signs a NIP-57 kind 9734 zap request, then (by default) trades
+/// it for an invoice at the recipient's LNURL callback. The request is not
+/// published; the wallet service publishes the receipt.
+pub async fn zap_request(client: &Client, args: ZapRequestArgs) -> Result<ZapRequestResult> {
+    let timeout = args.timeout_secs.unwrap_or(10);
+    if args.amount_sats == 0 {
+        bail!("amount_sats must be greater than 0");
+    }
+    let amount_msats = args
+        .amount_sats
+        .checked_mul(1_000)
+        .ok_or_else(|| anyhow!("amount too large"))?;
+
+    let target = match &args.event {
+        Some(reference) => Some(zap_target(client, reference, timeout).await?),
+        None => None,
+    };
+    let recipient = match (&args.recipient, &target) {
+        (Some(r), _) => nip05::resolve_pubkey(r).await?,
+        (None, Some(ZapTarget::Event(event))) => event.pubkey,
+        (None, Some(ZapTarget::Address(coordinate))) => coordinate.public_key,
+        (None, None) => bail!("provide recipient or event"),
+    };
+
+    let lnurl = match args.lnurl {
+        Some(lnurl) => lnurl,
+        None => lnurl_for(client, &recipient).await?,
+    };
+    let endpoint = lnurl_endpoint(&lnurl)?;
+    let params = pay_params(&endpoint).await?;
+    if !params.allows_nostr {
+        bail!("LNURL server at {endpoint} does not support zaps");
+    }
+    let zapper = params
+        .nostr_pubkey
+        .clone()
+        .ok_or_else(|| anyhow!("LNURL server did not advertise a nostrPubkey"))?;
+    if amount_msats < params.min_sendable || amount_msats > params.max_sendable {
+        bail!(
+            "amount {amount_msats} msats is outside the allowed range {}..={}",
+            params.min_sendable,
+            params.max_sendable
+        );
+    }
+
+    let receipt_relays = match args.relays {
+        Some(relays) if !relays.is_empty() => relays,
+        _ => relays::get_relay_urls(client).await,
+    };
+    if receipt_relays.is_empty() {
+        bail!("no relays to receive the zap receipt");
+    }
+    let mut relays_tag = vec!["relays".to_string()];
+    relays_tag.extend(receipt_relays);
+    let mut tags = vec![
+        Tag::parse(relays_tag)?,
+        Tag::parse(["amount".to_string(), amount_msats.to_string()])?,
+        Tag::public_key(recipient),
+    ];
+    if lnurl.to_lowercase().starts_with("lnurl1") {
+        tags.push(Tag::parse(["lnurl".to_string(), lnurl.to_lowercase()])?);
+    }
+    match &target {
+        Some(ZapTarget::Event(event)) => {
+            tags.push(Tag::event(event.id));
+            tags.push(Tag::parse(["k".to_string(), event.kind.as_u16().to_string()])?);
+            if event.kind.is_addressable() {
+                if let Some(d) = event.tags.identifier() {
+                    let coordinate = Coordinate::new(event.kind, event.pubkey).identifier(d);
+                    tags.push(Tag::coordinate(coordinate, None));
+                }
+            }
+        }
+        Some(ZapTarget::Address(coordinate)) => {
+            tags.push(Tag::coordinate(coordinate.clone(), None));
+            tags.push(Tag::parse(["k".to_string(), coordinate.kind.as_u16().to_string()])?);
+        }
+        None => {}
+    }
+    let builder = EventBuilder::new(Kind::ZapRequest, args.comment.unwrap_or_default()).tags(tags);
+    let zap_request = client.sign_event_builder(builder).await?;
+
+    let invoice = if args.fetch_invoice.unwrap_or(true) {
+        let mut query = vec![
+            ("amount", amount_msats.to_string()),
+            ("nostr", zap_request.as_json()),
+        ];
+        if lnurl.to_lowercase().starts_with("lnurl1") {
+            query.push(("lnurl", lnurl.to_lowercase()));
+        }
+        let response: InvoiceResponse = http::client()
+            .get(&params.callback)
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let pr = response.pr.ok_or_else(|| {
+            anyhow!(
+                "LNURL callback returned no invoice: {}",
+                response.reason.unwrap_or_default()
+            )
+        })?;
+        let invoice = parse_bolt11(&pr)?;
+        if invoice.amount_msats.is_some_and(|a| a != amount_msats) {
+            bail!("LNURL callback returned an invoice for a different amount");
+        }
+        Some(pr)
+    } else {
+        None
+    };
+
+    Ok(ZapRequestResult {
+        zap_request,
+        recipient: recipient.to_hex(),
+        amount_msats,
+        callback: params.callback,
+        zapper_pubkey: zapper,
+        invoice,
+    })
+}
+
+fn tag_value<'a>(event: &'a Event, name: &str) -> Option<&'a String> {
+    event
+        .tags
+        .iter()
+        .map(|t| t.as_slice())
+        .find(|t| t.first().map(String::as_str) == Some(name))
+        .and_then(|t| t.get(1))
+}
+
+/// Checks a kind 9735 receipt against NIP-57 Appendix F: the embedded zap
+/// request is a valid signed 9734, the invoice amount matches its `amount`
+/// tag, the invoice commits to it via the description hash, its `p` and `e`
+/// tags are the receipt's, and the receipt was signed by the recipient's
+/// LNURL server. `zapper` is the lookup for the receipt's `p` tag; without
+/// one the signer cannot be checked.
+pub(crate) fn check_receipt(receipt: &Event, zapper: Option<&Result<PublicKey, String>>) -> ZapReceiptRow {
+    let mut problems = Vec::new();
+    let request = tag_value(receipt, "description").and_then(|d| match Event::from_json(d) {
+        Ok(request) if request.kind != Kind::ZapRequest => {
+            problems.push("description is not a kind 9734 zap request".to_string());
+            None
+        }
+        Ok(request) => {
+            if request.verify().is_err() {
+                problems.push("zap request signature is invalid".to_string());
+            }
+            Some(request)
+        }
+        Err(e) => {
+            problems.push(format!("description is not a zap request: {e}"));
+            None
+        }
+    });
+    if request.is_none() && tag_value(receipt, "description").is_none() {
+        problems.push("missing description tag".to_string());
+    }
+    if let Some(request) = &request {
+        let recipients = request
+            .tags
+            .iter()
+            .filter(|t| t.as_slice().first().map(String::as_str) == Some("p"))
+            .count();
+        if recipients != 1 {
+            problems.push(format!("zap request has {recipients} p tags instead of one"));
+        }
+        for name in ["p", "e"] {
+            if tag_value(request, name) != tag_value(receipt, name) {
+                problems.push(format!("receipt {name} tag does not match the zap request"));
+            }
+        }
+    }
+
+    let invoice = match tag_value(receipt, "bolt11").map(|b| parse_bolt11(b)) {
+        Some(Ok(invoice)) => Some(invoice),
+        Some(Err(e)) => {
+            problems.push(format!("invalid bolt11: {e}"));
+            None
+        }
+        None => {
+            problems.push("missing bolt11 tag".to_string());
+            None
+        }
+    };
+    let amount_msats = invoice.as_ref().and_then(|i| i.amount_msats);
+    if let Some(invoice) = &invoice {
+        let requested = request
+            .as_ref()
+            .and_then(|r| tag_value(r, "amount"))
+            .and_then(|a| a.parse::<u64>().ok());
+        if let (Some(requested), Some(paid)) = (requested, invoice.amount_msats) {
+            if requested != paid {
+                problems.push(format!("invoice pays {paid} msats but {requested} were requested"));
+            }
+        }
+        if invoice.amount_msats.is_none() {
+            problems.push("invoice has no amount".to_string());
+        }
+        match (invoice.description_hash, tag_value(receipt, "description")) {
+            (Some(hash), Some(description)) => {
+                if Sha256Hash::hash(description.as_bytes()).to_byte_array() != hash {
+                    problems.push("invoice description hash does not match the zap request".to_string());
+                }
+            }
+            (None, _) => problems.push("invoice has no description hash".to_string()),
+            _ => {}
+        }
+    }
+    match zapper {
+        Some(Ok(expected)) if *expected != receipt.pubkey => {
+            problems.push("receipt was not signed by the recipient's zapper pubkey".to_string())
+        }
+        Some(Err(e)) => problems.push(format!("could not check zapper pubkey: {e}")),
+        Some(Ok(_)) => {}
+        None => problems.push("missing p tag, so the zapper pubkey cannot be checked".to_string()),
+    }
+
+    ZapReceiptRow {
+        receipt_id: receipt.id.to_hex(),
+        recipient: tag_value(receipt, "p").cloned(),
+        zapped_event: tag_value(receipt, "e").cloned(),
+        sender: request.as_ref().map(|r| r.pubkey.to_hex()),
+        sender_name: None,
+        amount_msats,
+        comment: request.as_ref().map(|r| r.content.clone()).filter(|c| !c.is_empty()),
+        created_at: receipt.created_at.as_secs(),
+        valid: problems.is_empty(),
+        problems,
+    }
+}
+
+/// Fetches kind 9735 receipts for an event or profile, verifies each one and
+/// totals the valid zaps per zapped event.
+pub async fn zap_receipts(client: &Client, args: ZapReceiptsArgs) -> Result<ZapReceiptsResult> {
+    let timeout = args.timeout_secs.unwrap_or(10);
+    let mut filter = Filter::new()
+        .kind(Kind::ZapReceipt)
+        .limit(args.limit.unwrap_or(500));
+    if let Some(reference) = &args.event {
+        filter = filter.event(thread::parse_event_ref(reference)?.0);
+    }
+    match (&args.recipient, &args.event) {
+        (Some(r), _) => filter = filter.pubkey(nip05::resolve_pubkey(r).await?),
+        (None, None) => filter = filter.pubkey(client.signer().await?.get_public_key().await?),
+        (None, Some(_)) => {}
+    }
+    if let Some(since) = args.since {
+        filter = filter.since(Timestamp::from(since));
+    }
+    let events = relays::fetch_events(client, filter, timeout).await?;
+
+    let mut zappers: HashMap<String, Result<PublicKey, String>> = HashMap::new();
+    for receipt in &events {
+        let Some(recipient) = tag_value(receipt, "p") else {
+            continue;
+        };
+        if zappers.contains_key(recipient) {
+            continue;
+        }
+        let zapper = match PublicKey::from_hex(recipient) {
+            Ok(pk) => zapper_pubkey(client, &pk).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        zappers.insert(recipient.clone(), zapper);
+    }
+
+    let mut receipts: Vec<ZapReceiptRow> = events
+        .iter()
+        .map(|receipt| {
+            let zapper = tag_value(receipt, "p").and_then(|p| zappers.get(p));
+            check_receipt(receipt, zapper)
+        })
+        .collect();
+    receipts.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+    let senders: Vec<PublicKey> = receipts
+        .iter()
+        .filter_map(|r| r.sender.as_deref())
+        .filter_map(|s| PublicKey::from_hex(s).ok())
+        .collect();
+    let names = profiles::display_names(client, senders, timeout).await?;
+    for row in &mut receipts {
+        row.sender_name = row
+            .sender
+            .as_deref()
+            .and_then(|s| PublicKey::from_hex(s).ok())
+            .and_then(|pk| names.get(&pk).cloned());
+    }
+
+    let mut totals: BTreeMap<String, ZapTotal> = BTreeMap::new();
+    let mut total_msats = 0u64;
+    for row in receipts.iter().filter(|r| r.valid) {
+        let msats = row.amount_msats.unwrap_or(0);
+        let key = row.zapped_event.clone().unwrap_or_else(|| "profile".to_string());
+        let total = totals.entry(key).or_default();
+        total.count += 1;
+        total.msats += msats;
+        total_msats += msats;
+    }
+    Ok(ZapReceiptsResult {
+        receipts,
+        totals,
+        total_msats,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    // Test vectors from BOLT #11 and LUD-01.
+    const INVOICE_2500U: &str = "lnbc2500u1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpu9qrsgquk0rl77nj30yxdy8j9vdx85fkpmdla2087ne0xh8nhedh8w27kyke0lp53ut353s06fv3qfegext0eh0ymjpf39tuven09sam30g4vgpfna3rh";
+    const INVOICE_20M_HASHED: &str = "lnbc20m1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqhp58yjmdan79s6qqdhdzgynm4zwqd5d7xmw5fk98klysy043l2ahrqs9qrsgq7ea976txfraylvgzuxs8kgcw23ezlrszfnh8r6qtfpr6cxga50aj6txm9rxrydzd06dfeawfk6swupvz4erwnyutnjq7x39ymw6j38gp7ynn44";
+    const LNURL: &str = "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS";
+
+    #[test]
+    fn decodes_bolt11_vectors() {
+        let invoice = parse_bolt11(INVOICE_2500U).unwrap();
+        assert_eq!(invoice.amount_msats, Some(250_000_000));
+        assert_eq!(invoice.description_hash, None);
+
+        let invoice = parse_bolt11(INVOICE_20M_HASHED).unwrap();
+        assert_eq!(invoice.amount_msats, Some(2_000_000_000));
+        let description = "One piece of chocolate cake, one icecream cone, one pickle, one slice of swiss cheese, one slice of salami, one lollypop, one piece of cherry pie, one sausage, one cupcake, and one slice of watermelon";
+        assert_eq!(
+            invoice.description_hash,
+            Some(Sha256Hash::hash(description.as_bytes()).to_byte_array())
+        );
+    }
+
+    #[test]
+    fn rejects_corrupted_invoices() {
+        let corrupted = INVOICE_2500U.replacen("lnbc2500u", "lnbc2600u", 1);
+        assert!(parse_bolt11(&corrupted).is_err());
+        assert!(parse_bolt11("lnbc1qqqqqqqq").is_err());
+    }
+
+    #[test]
+    fn bolt11_amount_multipliers() {
+        assert_eq!(bolt11_amount_msats("lnbc").unwrap(), None);
+        assert_eq!(bolt11_amount_msats("lnbc1").unwrap(), Some(100_000_000_000));
+        assert_eq!(bolt11_amount_msats("lnbc20m").unwrap(), Some(2_000_000_000));
+        assert_eq!(bolt11_amount_msats("lntb10n").unwrap(), Some(1_000));
+        assert_eq!(bolt11_amount_msats("lnbc10p").unwrap(), Some(1));
+        assert!(bolt11_amount_msats("lnbc1p").is_err());
+        assert!(bolt11_amount_msats("bc20m").is_err());
+    }
+
+    #[test]
+    fn decodes_lnurl_vectors() {
+        assert_eq!(
+            lnurl_endpoint(LNURL).unwrap(),
+            "https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df"
+        );
+        assert_eq!(
+            lnurl_endpoint("alice@example.com").unwrap(),
+            "https://example.com/.well-known/lnurlp/alice"
+        );
+        assert!(lnurl_endpoint("http://example.com/lnurlp").is_err());
+    }
+
+    fn ok_json(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// Stands in for an LNURL server: serves the pay parameters, then the
+    /// callback's invoice, and hands back both request lines.
+    async fn lnurl_stand_in(zapper: &PublicKey, invoice: &str) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let params = format!(
+            r#"{{"callback":"http://{host}/callback","minSendable":1000,"maxSendable":1000000000,"allowsNostr":true,"nostrPubkey":"{}","tag":"payRequest"}}"#,
+            zapper.to_hex()
+        );
+        let responses = [ok_json(&params), ok_json(&format!(r#"{{"pr":"{invoice}"}}"#))];
+        let served = tokio::spawn(async move {
+            let mut lines = Vec::new();
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 8192];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                lines.push(request.lines().next().unwrap_or_default().to_string());
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            lines
+        });
+        (host, served)
+    }
+
+    fn args(recipient: &PublicKey, host: &str, amount_sats: u64) -> ZapRequestArgs {
+        ZapRequestArgs {
+            recipient: Some(recipient.to_hex()),
+            event: None,
+            amount_sats,
+            comment: Some("great post".to_string()),
+            relays: Some(vec!["wss://relay.example.com".to_string()]),
+            lnurl: Some(format!("alice@{host}")),
+            fetch_invoice: None,
+            timeout_secs: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn zap_request_against_local_lnurl_server() {
+        let recipient = Keys::generate().public_key();
+        let zapper = Keys::generate().public_key();
+        let (host, served) = lnurl_stand_in(&zapper, INVOICE_2500U).await;
+        let client = Client::new(Keys::generate());
+        let result = zap_request(&client, args(&recipient, &host, 250_000)).await.unwrap();
+
+        assert_eq!(result.amount_msats, 250_000_000);
+        assert_eq!(result.zapper_pubkey, zapper.to_hex());
+        assert_eq!(result.invoice.as_deref(), Some(INVOICE_2500U));
+        assert_eq!(result.zap_request.kind, Kind::ZapRequest);
+        assert!(result.zap_request.verify().is_ok());
+        assert_eq!(tag_value(&result.zap_request, "amount").map(String::as_str), Some("250000000"));
+
+        let lines = served.await.unwrap();
+        assert!(lines[0].starts_with("GET /.well-known/lnurlp/alice "), "{}", lines[0]);
+        assert!(lines[1].starts_with("GET /callback?amount=250000000&nostr="), "{}", lines[1]);
+    }
+
+    #[tokio::test]
+    async fn refuses_invoice_for_another_amount() {
+        let recipient = Keys::generate().public_key();
+        let zapper = Keys::generate().public_key();
+        let (host, _) = lnurl_stand_in(&zapper, INVOICE_2500U).await;
+        let client = Client::new(Keys::generate());
+        let err = zap_request(&client, args(&recipient, &host, 1_000)).await.unwrap_err();
+        assert!(err.to_string().contains("different amount"), "{err}");
+    }
+
+    #[test]
+    fn receipt_tags_must_match_the_zap_request() {
+        let sender = Keys::generate();
+        let zapper = Keys::generate();
+        let recipient = Keys::generate().public_key();
+        let zapped = EventId::all_zeros();
+        let request = EventBuilder::new(Kind::ZapRequest, "")
+            .tags([Tag::public_key(recipient), Tag::event(zapped)])
+            .sign_with_keys(&sender)
+            .unwrap();
+        let receipt = |tags: Vec<Tag>| {
+            EventBuilder::new(Kind::ZapReceipt, "")
+                .tags(tags)
+                .tag(Tag::parse(["bolt11", INVOICE_2500U]).unwrap())
+                .tag(Tag::parse(["description".to_string(), request.as_json()]).unwrap())
+                .sign_with_keys(&zapper)
+                .unwrap()
+        };
+        let mentions =
+            |row: &ZapReceiptRow, needle: &str| row.problems.iter().any(|p| p.contains(needle));
+        let zapper_lookup = Ok(zapper.public_key());
+
+        let matching = check_receipt(
+            &receipt(vec![Tag::public_key(recipient), Tag::event(zapped)]),
+            Some(&zapper_lookup),
+        );
+        assert!(!mentions(&matching, "tag does not match"), "{:?}", matching.problems);
+        assert!(!mentions(&matching, "zapper"), "{:?}", matching.problems);
+
+        let other_event = EventId::from_byte_array([1; 32]);
+        let mismatched = check_receipt(
+            &receipt(vec![Tag::public_key(recipient), Tag::event(other_event)]),
+            Some(&zapper_lookup),
+        );
+        assert!(mentions(&mismatched, "receipt e tag does not match"));
+        assert!(!mismatched.valid);
+
+        let anonymous = check_receipt(&receipt(vec![Tag::event(zapped)]), None);
+        assert!(mentions(&anonymous, "receipt p tag does not match"));
+        assert!(mentions(&anonymous, "missing p tag"));
+        assert!(!anonymous.valid);
+    }
+}